@@ -0,0 +1,163 @@
+//! Compares the borrowing `parse_xml` path with the buffered `parse_xml_reader`
+//! path on the Ariane survey artifacts, the free function with a reused
+//! `XmlParser` on many small documents, parsing with and without
+//! `ignore_attributes` on an attribute-heavy document, and with and without
+//! the `presize` scan on a document of many repeated records, and the
+//! `ParsePath` choices on small, medium and large documents.
+//!
+//! Run with `cargo bench --bench parse`.
+
+use openspeleo_core::{parse_xml, parse_xml_reader, ParseOptions, ParsePath, XmlParser};
+use std::hint::black_box;
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+const ARTIFACTS: &[&str] = &[
+    "tests/artifacts/test_simple.mini.tml",
+    "tests/artifacts/test_simple.tml",
+    "tests/artifacts/test_with_walls.tml",
+    "tests/artifacts/test_large.tml",
+];
+
+fn load_data_xml(path: &str) -> String {
+    let file = std::fs::File::open(path).expect("Unable to open artifact");
+    let mut archive = zip::ZipArchive::new(file).expect("Unable to open zip archive");
+    let mut xml = String::new();
+    archive
+        .by_name("Data.xml")
+        .expect("Missing Data.xml")
+        .read_to_string(&mut xml)
+        .expect("Unable to read Data.xml");
+    xml
+}
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    const RUNS: u32 = 20;
+    f(); // warm-up
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        f();
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let options = ParseOptions::default();
+    for path in ARTIFACTS {
+        let xml = load_data_xml(path);
+        let borrowed = time(|| {
+            black_box(parse_xml(black_box(&xml), &options).unwrap());
+        });
+        let buffered = time(|| {
+            black_box(parse_xml_reader(black_box(xml.as_bytes()), &options).unwrap());
+        });
+        println!(
+            "{:<40} {:>9} bytes  borrowed {:>10.3?}  buffered {:>10.3?}",
+            path,
+            xml.len(),
+            borrowed,
+            buffered
+        );
+    }
+
+    let shots: Vec<String> = (0..1000)
+        .map(|i| format!("<Shot id=\"{i}\"><From>A{i}</From><Length>{i}.5</Length></Shot>"))
+        .collect();
+    let free = time(|| {
+        for shot in &shots {
+            black_box(parse_xml(black_box(shot), &options).unwrap());
+        }
+    });
+    let mut parser = XmlParser::new(options.clone());
+    let reused = time(|| {
+        for shot in &shots {
+            black_box(parser.parse(black_box(shot)).unwrap());
+        }
+    });
+    println!(
+        "{:<40} {:>9} docs   free fn  {:>10.3?}  XmlParser {:>9.3?}",
+        "small shots",
+        shots.len(),
+        free,
+        reused
+    );
+
+    let survey: String = (0..10_000)
+        .map(|i| {
+            format!(
+                "<Shot id=\"{i}\" from=\"A{i}\" to=\"A{}\" length=\"{i}.5\"/>",
+                i + 1
+            )
+        })
+        .collect();
+    let survey = format!("<Survey>{survey}</Survey>");
+    let ignoring = ParseOptions {
+        ignore_attributes: true,
+        ..options.clone()
+    };
+    let kept = time(|| {
+        black_box(parse_xml(black_box(&survey), &options).unwrap());
+    });
+    let ignored = time(|| {
+        black_box(parse_xml(black_box(&survey), &ignoring).unwrap());
+    });
+    println!(
+        "{:<40} {:>9} bytes  attrs    {:>10.3?}  no attrs {:>10.3?}",
+        "attribute-heavy shots",
+        survey.len(),
+        kept,
+        ignored
+    );
+
+    let records: String = (0..200_000)
+        .map(|i| format!("<Shot><From>A{i}</From><Length>{i}.5</Length></Shot>"))
+        .collect();
+    let records = format!("<Survey>{records}</Survey>");
+    let presizing = ParseOptions {
+        presize: true,
+        ..options.clone()
+    };
+    let grown = time(|| {
+        black_box(parse_xml(black_box(&records), &options).unwrap());
+    });
+    let presized = time(|| {
+        black_box(parse_xml(black_box(&records), &presizing).unwrap());
+    });
+    println!(
+        "{:<40} {:>9} bytes  grown    {:>10.3?}  presized {:>10.3?}",
+        "repeated records",
+        records.len(),
+        grown,
+        presized
+    );
+
+    // Size tiers, each parsed often enough to take a comparable time
+    for (tier, shots, repeat) in [
+        ("small", 10, 10_000),
+        ("medium", 1_000, 100),
+        ("large", 100_000, 1),
+    ] {
+        let xml: String = (0..shots)
+            .map(|i| format!("<Shot id=\"{i}\"><From>A{i}</From><Length>{i}.5</Length></Shot>"))
+            .collect();
+        let xml = format!("<Survey>{xml}</Survey>");
+        let [borrowed, buffered] = [ParsePath::Borrowed, ParsePath::Buffered].map(|parse_path| {
+            let options = ParseOptions {
+                parse_path,
+                ..options.clone()
+            };
+            time(|| {
+                for _ in 0..repeat {
+                    black_box(parse_xml(black_box(&xml), &options).unwrap());
+                }
+            })
+        });
+        println!(
+            "{:<40} {:>9} bytes  borrowed {:>10.3?}  buffered {:>10.3?}",
+            format!("{tier} document x{repeat}"),
+            xml.len(),
+            borrowed,
+            buffered
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! Times `value_to_xml_document` on small, medium and large documents, with
+//! compact and `pretty` output.
+//!
+//! Run with `cargo bench --bench serialize`.
+
+use openspeleo_core::{value_to_xml_document, SerializeOptions};
+use serde_json::{json, Value};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    const RUNS: u32 = 20;
+    f(); // warm-up
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        f();
+    }
+    start.elapsed() / RUNS
+}
+
+fn survey(shots: usize) -> Value {
+    let shots: Vec<Value> = (0..shots)
+        .map(|i| {
+            json!({
+                "@id": i.to_string(),
+                "From": format!("A{i}"),
+                "Length": {"#text": format!("{i}.5"), "@unit": "m"},
+                "Note": "Wet & <low> crawl",
+            })
+        })
+        .collect();
+    json!({"Shot": shots})
+}
+
+fn main() {
+    let compact = SerializeOptions::default();
+    let pretty = SerializeOptions {
+        pretty: true,
+        ..Default::default()
+    };
+    // Size tiers, each written often enough to take a comparable time
+    for (tier, shots, repeat) in [
+        ("small", 10, 10_000),
+        ("medium", 1_000, 100),
+        ("large", 100_000, 1),
+    ] {
+        let value = survey(shots);
+        let size = value_to_xml_document(&value, "Survey", "utf-8", &compact)
+            .unwrap()
+            .len();
+        let [compact, pretty] = [&compact, &pretty].map(|options| {
+            time(|| {
+                for _ in 0..repeat {
+                    black_box(
+                        value_to_xml_document(black_box(&value), "Survey", "utf-8", options)
+                            .unwrap(),
+                    );
+                }
+            })
+        });
+        println!(
+            "{:<40} {:>9} bytes  compact  {:>10.3?}  pretty   {:>10.3?}",
+            format!("{tier} document x{repeat}"),
+            size,
+            compact,
+            pretty
+        );
+    }
+}
@@ -1,3 +1,291 @@
-def xml_str_to_dict(xml_str: str, keep_null: bool = True) -> dict: ...
-def dict_to_xml_str(data: dict, root_name: str) -> str: ...
+import os
+from collections.abc import Callable, Iterable, Sequence
+from typing import Literal
+
+from typing_extensions import Buffer
+
+class XmlDictError(ValueError): ...
+
+class ParseOptions:
+    keep_null: bool
+    force_list: set[str]
+    auto_list: bool
+    ordered_content: bool
+    collapse_single_attr: bool
+    skip_empty: bool
+    text_key: str
+    errors: Literal["strict", "replace", "ignore"]
+    max_name_len: int | None
+    max_attr_count: int | None
+    trim_whitespace: bool
+    coerce_types: bool
+    true_values: list[str]
+    false_values: list[str]
+    bool_case_sensitive: bool
+    big_int_mode: Literal["string", "float"]
+    allow_empty: bool
+    value_with_attrs: bool
+    fragment: bool
+    expand_empty_elements: bool
+    max_bytes: int | None
+    keep_declaration: bool
+    strict_coercion: bool
+    lenient_attributes: bool
+    duplicate_policy: Literal["array", "last-wins", "first-wins", "error"]
+    keep_comments: bool
+    empty_attr_as_none: bool
+    ignore_attributes: bool
+    presize: bool
+    strip_namespaces: bool
+    normalize_whitespace: bool
+    parse_path: Literal["auto", "borrowed", "buffered"]
+    intern_names: bool
+    key_by: dict[str, str]
+    keep_empty_text: bool
+    honor_index: str | None
+    track_positions: bool
+    strip_root: bool
+    root_attr_key: str | None
+    element_case: Literal["preserve", "lower", "upper"]
+    attr_case: Literal["preserve", "lower", "upper"]
+    empty_element_value: Literal["object", "string", "null"]
+    lenient_html: bool
+    max_array_len: int | None
+    keep_doctype: bool
+    attr_preserve_pattern: str | None
+    exact_numbers: bool
+    text_separator: str | None
+    decode_base64: bool
+    whitespace_mode: Literal["trim-ends", "collapse", "preserve"] | None
+    cdata_marker: bool
+    allowed_tags: set[str] | None
+    allowed_attributes: set[str] | None
+    def __init__(
+        self,
+        keep_null: bool = True,
+        force_list: Iterable[str] | None = None,
+        auto_list: bool = False,
+        ordered_content: bool = False,
+        collapse_single_attr: bool = False,
+        skip_empty: bool = False,
+        text_key: str = "#text",
+        errors: Literal["strict", "replace", "ignore"] = "replace",
+        max_name_len: int | None = None,
+        max_attr_count: int | None = None,
+        trim_whitespace: bool = True,
+        coerce_types: bool = False,
+        true_values: list[str] = ["true"],
+        false_values: list[str] = ["false"],
+        bool_case_sensitive: bool = True,
+        big_int_mode: Literal["string", "float"] = "string",
+        allow_empty: bool = False,
+        value_with_attrs: bool = False,
+        fragment: bool = False,
+        expand_empty_elements: bool = False,
+        max_bytes: int | None = None,
+        keep_declaration: bool = False,
+        strict_coercion: bool = False,
+        lenient_attributes: bool = False,
+        duplicate_policy: Literal[
+            "array", "last-wins", "first-wins", "error"
+        ] = "array",
+        keep_comments: bool = False,
+        empty_attr_as_none: bool = False,
+        ignore_attributes: bool = False,
+        presize: bool = False,
+        strip_namespaces: bool = False,
+        normalize_whitespace: bool = False,
+        parse_path: Literal["auto", "borrowed", "buffered"] = "auto",
+        intern_names: bool = False,
+        key_by: dict[str, str] = {},
+        keep_empty_text: bool = False,
+        honor_index: str | None = None,
+        track_positions: bool = False,
+        strip_root: bool = False,
+        root_attr_key: str | None = None,
+        element_case: Literal["preserve", "lower", "upper"] = "preserve",
+        attr_case: Literal["preserve", "lower", "upper"] = "preserve",
+        empty_element_value: Literal["object", "string", "null"] = "object",
+        lenient_html: bool = False,
+        max_array_len: int | None = None,
+        keep_doctype: bool = False,
+        attr_preserve_pattern: str | None = None,
+        exact_numbers: bool = False,
+        text_separator: str | None = None,
+        decode_base64: bool = False,
+        whitespace_mode: Literal["trim-ends", "collapse", "preserve"] | None = None,
+        cdata_marker: bool = False,
+        allowed_tags: Iterable[str] | None = None,
+        allowed_attributes: Iterable[str] | None = None,
+        round_trip: bool = False,
+    ) -> None: ...
+
+class ValueWithAttrs(dict):
+    @property
+    def value(self) -> str | int | float | bool | None: ...
+    @property
+    def attrs(self) -> dict[str, str | int | float | bool]: ...
+
+class SerializeOptions:
+    attr_quote: str
+    allow_unordered_sets: bool
+    array_item_name: str | None
+    element_order: dict[str, list[str]]
+    pretty: bool
+    indent_char: str
+    indent_size: int
+    control_char_policy: Literal["reject", "strip", "numeric-escape"]
+    flush_bytes: int
+    sort_keys: bool
+    sort_attributes: bool
+    line_ending: Literal["\n", "\r\n", "\r"]
+    processing_instructions: list[tuple[str, str]]
+    max_depth: int
+    canonical: bool
+    flat_attributes: bool
+    none_attr_as_empty: bool
+    index_attribute: str | None
+    nonfinite_floats: Literal["error", "string", "null"]
+    doctype: str | None
+    namespaces: dict[str, str]
+    def __init__(
+        self,
+        attr_quote: str = '"',
+        allow_unordered_sets: bool = False,
+        array_item_name: str | None = None,
+        element_order: dict[str, list[str]] = {},
+        pretty: bool = False,
+        indent_char: str = " ",
+        indent_size: int = 2,
+        control_char_policy: Literal["reject", "strip", "numeric-escape"] = "reject",
+        flush_bytes: int = 65536,
+        sort_keys: bool = False,
+        sort_attributes: bool = False,
+        line_ending: Literal["\n", "\r\n", "\r"] = "\n",
+        processing_instructions: Sequence[tuple[str, str]] = (),
+        max_depth: int = 512,
+        canonical: bool = False,
+        flat_attributes: bool = False,
+        none_attr_as_empty: bool = False,
+        index_attribute: str | None = None,
+        nonfinite_floats: Literal["error", "string", "null"] = "error",
+        doctype: str | None = None,
+        namespaces: dict[str, str] = {},
+    ) -> None: ...
+
+def xml_str_to_dict(
+    xml_str: str,
+    keep_null: bool | None = None,
+    force_list: Iterable[str] | None = None,
+    auto_list: bool | None = None,
+    ordered_content: bool | None = None,
+    skip_empty: bool | None = None,
+    return_stats: bool = False,
+    options: ParseOptions | None = None,
+    key_transform: Callable[[str], str] | None = None,
+    collect_errors: bool = False,
+) -> (
+    dict
+    | tuple[dict, dict[str, int]]
+    | tuple[dict, list[tuple[int, str]]]
+    | tuple[dict, dict[str, int], list[tuple[int, str]]]
+): ...
+
+def xml_buffer_to_dict(
+    buf: Buffer, options: ParseOptions | None = None
+) -> dict: ...
+
+def xml_elements_to_dict(
+    xml_str: str,
+    offset: int,
+    max_elements: int,
+    options: ParseOptions | None = None,
+) -> tuple[dict, int | None]: ...
+
+class XmlParser:
+    def __init__(
+        self,
+        keep_null: bool | None = None,
+        force_list: Iterable[str] | None = None,
+        auto_list: bool | None = None,
+        ordered_content: bool | None = None,
+        options: ParseOptions | None = None,
+    ) -> None: ...
+    @property
+    def options(self) -> ParseOptions: ...
+    def parse(self, xml_str: str) -> dict: ...
+
+def dict_to_xml_str(
+    data: dict,
+    root_name: str,
+    attr_quote: str | None = None,
+    array_item_name: str | None = None,
+    options: SerializeOptions | None = None,
+    sort_keys: bool | None = None,
+    default: Callable[[object], object] | None = None,
+    processing_instructions: Sequence[tuple[str, str]] | None = None,
+    doctype: str | None = None,
+    namespaces: dict[str, str] | None = None,
+) -> str: ...
+def dict_to_xml_bytes(
+    data: dict,
+    root_name: str,
+    encoding: str = "utf-8",
+    attr_quote: str | None = None,
+    array_item_name: str | None = None,
+    options: SerializeOptions | None = None,
+    sort_keys: bool | None = None,
+    default: Callable[[object], object] | None = None,
+    processing_instructions: Sequence[tuple[str, str]] | None = None,
+    doctype: str | None = None,
+    namespaces: dict[str, str] | None = None,
+) -> bytes: ...
+def dict_to_xml_file(
+    data: dict,
+    root_name: str,
+    path: str | os.PathLike[str],
+    options: SerializeOptions | None = None,
+    default: Callable[[object], object] | None = None,
+) -> None: ...
+def records_to_xml_file(
+    records: Iterable[dict],
+    root_name: str,
+    item_name: str,
+    path: str | os.PathLike[str],
+    options: SerializeOptions | None = None,
+    default: Callable[[object], object] | None = None,
+) -> None: ...
+def document_to_xml_str(
+    document: dict,
+    options: SerializeOptions | None = None,
+    default: Callable[[object], object] | None = None,
+) -> str: ...
+def xml_str_to_flat(
+    xml_str: str, options: ParseOptions | None = None
+) -> dict[str, str | int | float | bool | None]: ...
+def flat_to_xml_str(
+    flat_dict: dict[str, str | int | float | bool | None],
+    options: SerializeOptions | None = None,
+) -> str: ...
+def xml_extract(xml_str: str, path: str) -> dict | str | None: ...
+def xml_find_all(
+    xml_str: str, path: str, options: ParseOptions | None = None
+) -> list[dict | str | int | float | bool | None]: ...
+def xml_project(
+    xml_str: str, schema: dict[str, str], options: ParseOptions | None = None
+) -> dict[str, dict | str | int | float | bool | None]: ...
+def xml_subtree_to_dict(
+    xml_str: str, tag: str, options: ParseOptions | None = None
+) -> dict | None: ...
+def xml_diff(
+    a_xml: str, b_xml: str, options: ParseOptions | None = None
+) -> list[tuple[str, object, object]]: ...
+def parse_events(
+    xml_str: str,
+    callback: Callable[
+        [tuple[str, str, dict[str, str]] | tuple[str, str]], object
+    ],
+) -> None: ...
+def is_well_formed(xml_str: str) -> bool: ...
+def validate_well_formed(xml_str: str) -> str | None: ...
 def load_ariane_tml_file_to_dict(path: str) -> str: ...
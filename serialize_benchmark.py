@@ -0,0 +1,44 @@
+import statistics
+import time
+import zipfile
+from concurrent.futures import ThreadPoolExecutor
+
+from openspeleo_core import dict_to_xml_str
+from openspeleo_core import xml_str_to_dict
+
+THREADS = 4
+
+if __name__ == "__main__":
+    for filepath in [
+        "tests/artifacts/test_large.tml",
+        "tests/artifacts/test_simple.tml",
+        "tests/artifacts/test_with_walls.tml",
+    ]:
+        print(f"\nFilename: {filepath} ...\n")  # noqa: T201
+
+        with zipfile.ZipFile(filepath, "r") as zip_file:
+            xml_str = zip_file.open("Data.xml", mode="r").read().decode("utf-8")
+        data = xml_str_to_dict(xml_str)["CaveFile"]
+        batch = [data] * (THREADS * 4)
+
+        def serialize(survey):
+            return dict_to_xml_str(survey, root_name="CaveFile")
+
+        sequential, threaded = [], []
+        with ThreadPoolExecutor(max_workers=THREADS) as executor:
+            for _ in range(10):
+                start_t = time.perf_counter()
+                for survey in batch:
+                    serialize(survey)
+                sequential.append(time.perf_counter() - start_t)
+
+                start_t = time.perf_counter()
+                list(executor.map(serialize, batch))
+                threaded.append(time.perf_counter() - start_t)
+
+        # Only the Rust writing phase runs without the GIL, so the speed-up is
+        # bounded by the share of time spent converting the dict to a `Value`.
+        print(f"Sequential: {statistics.mean(sequential[2:]):.3f} secs")  # noqa: T201
+        print(  # noqa: T201
+            f"{THREADS} threads: {statistics.mean(threaded[2:]):.3f} secs"
+        )
@@ -0,0 +1,98 @@
+use serde_json::{Number, Value};
+
+use crate::{BigIntMode, ParseOptions, XmlDictError};
+
+/// Converts a text value to a JSON scalar when `options.coerce_types` is set.
+///
+/// Booleans are recognized from `true_values`/`false_values` first, so that
+/// tokens like `1`/`0` can be configured as booleans. Then come integers
+/// (fitting `i64` or `u64`, larger ones following `big_int_mode`) and finite
+/// floats. Numbers with leading zeros
+/// (`007`) or an explicit `+` sign are kept as strings, as they are usually
+/// identifiers or formatted values that would not survive a round trip.
+///
+/// With `strict_coercion`, text that looks numeric but is kept as a string
+/// is an `XmlDictError::AmbiguousNumber` instead; its `path` is left empty
+/// for the caller to fill in.
+pub(crate) fn coerce_text(text: String, options: &ParseOptions) -> Result<Value, XmlDictError> {
+    if !options.coerce_types {
+        return Ok(Value::String(text));
+    }
+    #[cfg(not(feature = "exact_numbers"))]
+    if options.exact_numbers {
+        return Err(XmlDictError::InvalidOption(
+            "exact_numbers needs the exact_numbers crate feature".to_string(),
+        ));
+    }
+    if let Some(b) = parse_bool(&text, options) {
+        return Ok(Value::Bool(b));
+    }
+    match parse_number(&text, options) {
+        Some(n) => Ok(Value::Number(n)),
+        None if options.strict_coercion && looks_numeric(&text) => {
+            Err(XmlDictError::AmbiguousNumber {
+                path: String::new(),
+                text,
+            })
+        }
+        None => Ok(Value::String(text)),
+    }
+}
+
+// A sign, then digits mixed with separators and exponent markers: `007`,
+// `+5`, `1,5`, `1.2.3` or `1e`, but not dates like `2024-01-05`
+fn looks_numeric(text: &str) -> bool {
+    let body = text.strip_prefix(['+', '-']).unwrap_or(text);
+    let digit_first = body.starts_with(|c: char| c.is_ascii_digit())
+        || (body.starts_with('.') && body[1..].starts_with(|c: char| c.is_ascii_digit()));
+    digit_first
+        && body.char_indices().all(|(i, c)| match c {
+            '0'..='9' | '.' | ',' | '_' | 'e' | 'E' => true,
+            '+' | '-' => body[..i].ends_with(['e', 'E']),
+            _ => false,
+        })
+}
+
+fn parse_bool(text: &str, options: &ParseOptions) -> Option<bool> {
+    let matches = |token: &String| {
+        if options.bool_case_sensitive {
+            token == text
+        } else {
+            token.eq_ignore_ascii_case(text)
+        }
+    };
+    if options.true_values.iter().any(matches) {
+        Some(true)
+    } else if options.false_values.iter().any(matches) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn parse_number(text: &str, options: &ParseOptions) -> Option<Number> {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let leading_zero = digits.len() > 1 && digits.starts_with('0') && !digits[1..].starts_with('.');
+    if !digits.starts_with(|c: char| c.is_ascii_digit()) || leading_zero {
+        return None;
+    }
+    // Numbers keep their text, as long as it is a JSON number (not `1.`)
+    #[cfg(feature = "exact_numbers")]
+    if options.exact_numbers {
+        if let Ok(n) = text.parse::<Number>() {
+            return Some(n);
+        }
+    }
+    if let Ok(n) = text.parse::<i64>() {
+        return Some(n.into());
+    }
+    if let Ok(n) = text.parse::<u64>() {
+        return Some(n.into());
+    }
+    if digits.bytes().all(|b| b.is_ascii_digit()) && options.big_int_mode == BigIntMode::String {
+        return None;
+    }
+    // `f64` parsing also accepts `inf`, `NaN`, ...: only plain decimal forms
+    // pass the first-character check above, and infinities are rejected here
+    text.parse::<f64>().ok().and_then(Number::from_f64)
+}
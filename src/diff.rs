@@ -0,0 +1,86 @@
+use serde_json::Value;
+
+use crate::{parse_xml, ParseOptions, XmlDictError};
+
+/// A difference between two parsed documents.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    /// Location of the difference, in the syntax of `extract_path`:
+    /// `survey/shot[2]/@id` (indices are 1-based).
+    pub path: String,
+    /// Value in the first document, `None` when missing there.
+    pub left: Option<Value>,
+    /// Value in the second document, `None` when missing there.
+    pub right: Option<Value>,
+}
+
+/// Compares two `Value` trees, returning their differences in path order.
+///
+/// Objects are compared key by key, so the order of attributes and of
+/// differently named children does not matter; arrays (repeated elements)
+/// are compared item by item.
+pub fn diff_values(left: &Value, right: &Value) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_into(left, right, &mut String::new(), &mut differences);
+    differences
+}
+
+fn diff_into(left: &Value, right: &Value, path: &mut String, out: &mut Vec<Difference>) {
+    let len = path.len();
+    match (left, right) {
+        (Value::Object(l), Value::Object(r)) => {
+            // Keys of either object, in sorted order
+            let mut keys: Vec<&String> = l.keys().chain(r.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                if !path.is_empty() {
+                    path.push('/');
+                }
+                path.push_str(key);
+                match (l.get(key), r.get(key)) {
+                    (Some(lv), Some(rv)) => diff_into(lv, rv, path, out),
+                    (lv, rv) => out.push(Difference {
+                        path: path.clone(),
+                        left: lv.cloned(),
+                        right: rv.cloned(),
+                    }),
+                }
+                path.truncate(len);
+            }
+        }
+        (Value::Array(l), Value::Array(r)) => {
+            for i in 0..l.len().max(r.len()) {
+                path.push_str(&format!("[{}]", i + 1));
+                match (l.get(i), r.get(i)) {
+                    (Some(lv), Some(rv)) => diff_into(lv, rv, path, out),
+                    (lv, rv) => out.push(Difference {
+                        path: path.clone(),
+                        left: lv.cloned(),
+                        right: rv.cloned(),
+                    }),
+                }
+                path.truncate(len);
+            }
+        }
+        _ if left == right => {}
+        _ => out.push(Difference {
+            path: path.clone(),
+            left: Some(left.clone()),
+            right: Some(right.clone()),
+        }),
+    }
+}
+
+/// Parses both documents with `options` and returns their differences, see
+/// `diff_values`. Formatting (indentation, attribute order, quoting, ...)
+/// is not significant.
+pub fn xml_diff(
+    left_xml: &str,
+    right_xml: &str,
+    options: &ParseOptions,
+) -> Result<Vec<Difference>, XmlDictError> {
+    let left = parse_xml(left_xml, options)?;
+    let right = parse_xml(right_xml, options)?;
+    Ok(diff_values(&left, &right))
+}
@@ -0,0 +1,34 @@
+use thiserror::Error;
+
+/// Errors raised while converting between XML and dict representations.
+///
+/// Exposed to Python as `openspeleo_core.XmlDictError` (a `ValueError` subclass).
+#[derive(Debug, Error)]
+pub enum XmlDictError {
+    #[error("XML parsing error: {0}")]
+    Parse(String),
+
+    #[error("XML generation error: {0}")]
+    Write(String),
+
+    #[error("UTF-8 conversion error: {0}")]
+    Utf8(#[from] std::string::FromUtf8Error),
+
+    #[error("Invalid option: {0}")]
+    InvalidOption(String),
+
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(String),
+
+    #[error("Ambiguous numeric value {text:?} at {path}")]
+    AmbiguousNumber { path: String, text: String },
+
+    #[error("Invalid path {0}")]
+    InvalidPath(String),
+
+    #[error("Unsupported output encoding: {0}")]
+    UnsupportedEncoding(String),
+
+    #[error("Character {ch:?} (U+{code:04X}) cannot be represented in {encoding}", code = *.ch as u32)]
+    Unencodable { ch: char, encoding: String },
+}
@@ -0,0 +1,546 @@
+use std::collections::HashMap;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde_json::{Map, Value};
+
+use crate::{parse_xml, scalar_text, ParseOptions, XmlDictError};
+
+/// One `/`-separated step of an extraction path, e.g. `item[2]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Step {
+    /// Element name, `*` matching any element.
+    name: String,
+    /// 1-based position among same-named siblings, as in XPath.
+    index: Option<usize>,
+    /// `[@attr]` predicate, with the value required by `[@attr='value']`.
+    predicate: Option<(String, Option<String>)>,
+}
+
+impl Step {
+    fn matches_name(&self, name: &[u8]) -> bool {
+        self.name == "*" || name == self.name.as_bytes()
+    }
+
+    fn matches_start(&self, elem: &BytesStart) -> Result<bool, quick_xml::Error> {
+        let Some((attr, expected)) = &self.predicate else {
+            return Ok(true);
+        };
+        let Some(found) = elem.try_get_attribute(attr.as_bytes())? else {
+            return Ok(false);
+        };
+        match expected {
+            Some(expected) => Ok(found.unescape_value()? == expected.as_str()),
+            None => Ok(true),
+        }
+    }
+
+    fn matches_value(&self, value: &Value) -> bool {
+        let Some((attr, expected)) = &self.predicate else {
+            return true;
+        };
+        match value.get(format!("@{}", attr)) {
+            Some(found) => expected
+                .as_ref()
+                .is_none_or(|expected| scalar_text(found).as_ref() == Some(expected)),
+            None => false,
+        }
+    }
+}
+
+/// A parsed extraction path: element steps, optionally ending in `@attr`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Path {
+    steps: Vec<Step>,
+    attribute: Option<String>,
+}
+
+fn parse_path(path: &str) -> Result<Path, XmlDictError> {
+    let invalid = |reason: &str| XmlDictError::InvalidPath(format!("{:?}: {}", path, reason));
+
+    let mut steps = Vec::new();
+    let mut attribute = None;
+    let segments: Vec<&str> = path.split('/').collect();
+    for (pos, segment) in segments.iter().enumerate() {
+        if let Some(attr) = segment.strip_prefix('@') {
+            if pos + 1 != segments.len() || attr.is_empty() {
+                return Err(invalid("an attribute may only be the last step"));
+            }
+            attribute = Some(attr.to_string());
+            continue;
+        }
+
+        let (name, index, predicate) = match segment.split_once('[') {
+            Some((name, rest)) => {
+                let inner = rest
+                    .strip_suffix(']')
+                    .ok_or_else(|| invalid("unclosed `[`"))?;
+                if let Some(predicate) = inner.strip_prefix('@') {
+                    (
+                        name,
+                        None,
+                        Some(parse_predicate(predicate).ok_or_else(|| {
+                            invalid("predicates are `[@attr]` or `[@attr='value']`")
+                        })?),
+                    )
+                } else {
+                    let index = inner
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|idx| *idx > 0)
+                        .ok_or_else(|| {
+                            invalid("indices must be positive integers, e.g. `item[1]`")
+                        })?;
+                    (name, Some(index), None)
+                }
+            }
+            None => (*segment, None, None),
+        };
+        if name.is_empty() {
+            return Err(invalid("empty element name"));
+        }
+        steps.push(Step {
+            name: name.to_string(),
+            index,
+            predicate,
+        });
+    }
+
+    if steps.is_empty() {
+        return Err(invalid("the path must name the root element"));
+    }
+    Ok(Path { steps, attribute })
+}
+
+// `attr` or `attr='value'` (or double quotes), without the leading `@`
+fn parse_predicate(predicate: &str) -> Option<(String, Option<String>)> {
+    let (attr, value) = match predicate.split_once('=') {
+        Some((attr, quoted)) => {
+            let value = ['\'', '"']
+                .iter()
+                .find_map(|&quote| quoted.strip_prefix(quote)?.strip_suffix(quote))?;
+            (attr, Some(value.to_string()))
+        }
+        None => (predicate, None),
+    };
+    (!attr.is_empty()).then(|| (attr.to_string(), value))
+}
+
+/// Extracts a single value from `xml` without building the whole document.
+///
+/// `path` is a minimal XPath-like expression starting at the root element:
+/// `root/child` selects the first `child` element, `root/items/item[2]` the
+/// second `item` (1-based), and a trailing `@attr` selects an attribute. A
+/// `*` step matches any element, `*[1]` the first element of each name, and
+/// `item[@id]` or `item[@id='a']` only the `item` elements having that
+/// attribute, or that value of it.
+/// Elements are returned as `parse_xml` would represent them; `None` is
+/// returned when nothing matches. Parsing stops as soon as the target is found
+/// and subtrees off the path are skipped without being materialized.
+pub fn extract_path(
+    xml: &str,
+    path: &str,
+    options: &ParseOptions,
+) -> Result<Option<Value>, XmlDictError> {
+    let path = parse_path(path)?;
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+    let mut buf = Vec::new();
+    let mut skip_buf = Vec::new();
+
+    // Number of leading steps matched by the currently open elements, and the
+    // count of same-named siblings seen so far at each step.
+    let mut matched = 0;
+    let mut seen = vec![HashMap::<Vec<u8>, usize>::new(); path.steps.len()];
+
+    let parse_err = |reader: &Reader<&[u8]>, e: quick_xml::Error| {
+        XmlDictError::Parse(format!(
+            "Error at position {}: {:?}",
+            reader.buffer_position(),
+            e
+        ))
+    };
+
+    loop {
+        let start = reader.buffer_position() as usize;
+        let (elem, is_empty) = match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => (e.into_owned(), false),
+            Ok(Event::Empty(e)) => (e.into_owned(), true),
+            Ok(Event::End(_)) => {
+                if matched == 0 {
+                    return Ok(None);
+                }
+                matched -= 1;
+                buf.clear();
+                continue;
+            }
+            Ok(Event::Eof) => return Ok(None),
+            Err(e) => return Err(parse_err(&reader, e)),
+            _ => {
+                buf.clear();
+                continue;
+            }
+        };
+        buf.clear();
+
+        let step = &path.steps[matched];
+        let is_match = step.matches_name(elem.name().as_ref())
+            && step
+                .matches_start(&elem)
+                .map_err(|e| parse_err(&reader, e))?
+            && step.index.is_none_or(|index| {
+                let count = seen[matched]
+                    .entry(elem.name().as_ref().to_vec())
+                    .or_default();
+                *count += 1;
+                index == *count
+            });
+
+        if is_match && matched + 1 == path.steps.len() {
+            if let Some(attr) = &path.attribute {
+                return elem
+                    .try_get_attribute(attr.as_bytes())
+                    .map_err(|e| parse_err(&reader, e.into()))?
+                    .map(|a| {
+                        a.unescape_value()
+                            .map(|v| Some(Value::String(v.into_owned())))
+                            .map_err(|e| parse_err(&reader, e))
+                    })
+                    .unwrap_or(Ok(None));
+            }
+
+            if !is_empty {
+                reader
+                    .read_to_end_into(elem.name(), &mut skip_buf)
+                    .map_err(|e| parse_err(&reader, e))?;
+            }
+            let end = reader.buffer_position() as usize;
+            // The subtree holds the matched element alone, whatever its name
+            let options = ParseOptions {
+                strip_root: false,
+                ..options.clone()
+            };
+            let subtree = parse_xml(&xml[start..end], &options)?;
+            let element = match subtree {
+                Value::Object(obj) => obj.into_iter().find(|(key, _)| !key.starts_with('#')),
+                _ => None,
+            };
+            return Ok(element.map(|(_, value)| value));
+        }
+
+        if is_empty {
+            continue;
+        }
+        if is_match {
+            matched += 1;
+            seen[matched].clear();
+        } else if matched == 0 {
+            // The root element does not match the first step
+            return Ok(None);
+        } else {
+            reader
+                .read_to_end_into(elem.name(), &mut skip_buf)
+                .map_err(|e| parse_err(&reader, e))?;
+            skip_buf.clear();
+        }
+    }
+}
+
+/// Parses only the first `tag` element of `xml`, at any depth, returning it
+/// as `{tag: value}` like `parse_xml` would for a document made of it.
+///
+/// Everything before the element is scanned without being materialized and
+/// the rest of the document is not read. Returns `None` when no element is
+/// named `tag`.
+pub fn parse_subtree(
+    xml: &str,
+    tag: &str,
+    options: &ParseOptions,
+) -> Result<Option<Value>, XmlDictError> {
+    let mut reader = Reader::from_str(xml);
+
+    let parse_err = |reader: &Reader<&[u8]>, e: quick_xml::Error| {
+        XmlDictError::Parse(format!(
+            "Error at position {}: {:?}",
+            reader.buffer_position(),
+            e
+        ))
+    };
+
+    loop {
+        let start = reader.buffer_position() as usize;
+        let (elem, is_empty) = match reader.read_event() {
+            Ok(Event::Start(e)) => (e, false),
+            Ok(Event::Empty(e)) => (e, true),
+            Ok(Event::Eof) => return Ok(None),
+            Err(e) => return Err(parse_err(&reader, e)),
+            _ => continue,
+        };
+        if elem.name().as_ref() != tag.as_bytes() {
+            continue;
+        }
+        if !is_empty {
+            reader
+                .read_to_end(elem.name())
+                .map_err(|e| parse_err(&reader, e))?;
+        }
+        let end = reader.buffer_position() as usize;
+        return parse_xml(&xml[start..end], options).map(Some);
+    }
+}
+
+/// Returns all the values of `document`, as returned by `parse_xml`, matching
+/// `path`. They come in the order of `document`: same-named elements keep
+/// their document order, grouped where the first of them appears.
+///
+/// `path` has the syntax of `extract_path`, selecting every match instead of
+/// the first: `survey/*/shot` gives the `shot` children of all the children
+/// of `survey`, `survey/shot[@type='splay']/@id` the `id` of its splay shots.
+/// Indices count same-named siblings, so `*[1]` selects the first child of
+/// each name. With `ordered_content`, the children in `#content` are
+/// searched too.
+pub fn find_all(document: &Value, path: &str) -> Result<Vec<Value>, XmlDictError> {
+    let path = parse_path(path)?;
+    Ok(select(document, &path.steps, path.attribute.as_deref())
+        .into_iter()
+        .cloned()
+        .collect())
+}
+
+// The descendants of `node` reached by `steps`, or their `attribute`
+fn select<'v>(node: &'v Value, steps: &[Step], attribute: Option<&str>) -> Vec<&'v Value> {
+    let mut nodes = vec![node];
+    for step in steps {
+        let mut next = Vec::new();
+        for node in nodes {
+            if let Value::Object(obj) = node {
+                find_children(obj, step, &mut HashMap::new(), &mut next);
+            }
+        }
+        nodes = next;
+    }
+    match attribute {
+        Some(attr) => {
+            let key = format!("@{}", attr);
+            nodes
+                .into_iter()
+                .filter_map(|node| node.get(&key))
+                .collect()
+        }
+        None => nodes,
+    }
+}
+
+// Adds the children of `obj` matching `step` to `found`, `seen` counting the
+// children of each name for indexed steps
+fn find_children<'v>(
+    obj: &'v Map<String, Value>,
+    step: &Step,
+    seen: &mut HashMap<&'v str, usize>,
+    found: &mut Vec<&'v Value>,
+) {
+    for (key, value) in obj {
+        if key == "#content" {
+            let fragments = value.as_array().map(Vec::as_slice).unwrap_or_default();
+            for fragment in fragments {
+                if let Value::Object(fragment) = fragment {
+                    find_children(fragment, step, seen, found);
+                }
+            }
+            continue;
+        }
+        if key.starts_with(['@', '#']) || !step.matches_name(key.as_bytes()) {
+            continue;
+        }
+        let items = match value {
+            Value::Array(items) => items.as_slice(),
+            item => std::slice::from_ref(item),
+        };
+        for item in items {
+            let count = seen.entry(key).or_default();
+            *count += 1;
+            if step.index.is_none_or(|index| index == *count) && step.matches_value(item) {
+                found.push(item);
+            }
+        }
+    }
+}
+
+/// Parses `xml` and returns all the values matching `path`, see `find_all`.
+pub fn xml_find_all(
+    xml: &str,
+    path: &str,
+    options: &ParseOptions,
+) -> Result<Vec<Value>, XmlDictError> {
+    // Paths start at the root element
+    let options = ParseOptions {
+        strip_root: false,
+        ..options.clone()
+    };
+    find_all(&parse_xml(xml, &options)?, path)
+}
+
+// Progress of one `xml_project` path through the open elements
+struct Cursor {
+    path: Path,
+    /// Number of leading steps matched by the open elements.
+    matched: usize,
+    /// Count of same-named siblings seen at each step.
+    seen: Vec<HashMap<Vec<u8>, usize>>,
+    value: Option<Value>,
+}
+
+/// Extracts several values of `xml` in a single pass, returning an object
+/// mapping each key of `schema` to the value its path selects.
+///
+/// Paths have the syntax of `extract_path` and select the first match, keys
+/// without a match mapping to `null`. Like `extract_path`, subtrees off every
+/// path are skipped without being materialized, and reading stops as soon as
+/// all the values are found.
+pub fn xml_project(
+    xml: &str,
+    schema: &HashMap<String, String>,
+    options: &ParseOptions,
+) -> Result<Map<String, Value>, XmlDictError> {
+    let mut cursors = Vec::with_capacity(schema.len());
+    for (key, path) in schema {
+        let path = parse_path(path)?;
+        let seen = vec![HashMap::new(); path.steps.len()];
+        let cursor = Cursor {
+            path,
+            matched: 0,
+            seen,
+            value: None,
+        };
+        cursors.push((key, cursor));
+    }
+    // Subtrees hold the matched element alone, whatever its name
+    let options = ParseOptions {
+        strip_root: false,
+        ..options.clone()
+    };
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+    let mut buf = Vec::new();
+    let mut skip_buf = Vec::new();
+    let mut depth = 0;
+
+    let parse_err = |reader: &Reader<&[u8]>, e: quick_xml::Error| {
+        XmlDictError::Parse(format!(
+            "Error at position {}: {:?}",
+            reader.buffer_position(),
+            e
+        ))
+    };
+
+    while cursors.iter().any(|(_, cursor)| cursor.value.is_none()) {
+        let start = reader.buffer_position() as usize;
+        let (elem, is_empty) = match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => (e.into_owned(), false),
+            Ok(Event::Empty(e)) => (e.into_owned(), true),
+            Ok(Event::End(_)) => {
+                depth -= 1;
+                for (_, cursor) in cursors.iter_mut() {
+                    cursor.matched = cursor.matched.min(depth);
+                }
+                buf.clear();
+                continue;
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(parse_err(&reader, e)),
+            _ => {
+                buf.clear();
+                continue;
+            }
+        };
+        buf.clear();
+
+        // Cursors entering the element, and whether one of them needs it whole
+        let mut entering = Vec::new();
+        let mut needs_subtree = false;
+        for (i, (_, cursor)) in cursors.iter_mut().enumerate() {
+            if cursor.value.is_some() || cursor.matched != depth {
+                continue;
+            }
+            let step = &cursor.path.steps[depth];
+            let is_match = step.matches_name(elem.name().as_ref())
+                && step
+                    .matches_start(&elem)
+                    .map_err(|e| parse_err(&reader, e))?
+                && step.index.is_none_or(|index| {
+                    let count = cursor.seen[depth]
+                        .entry(elem.name().as_ref().to_vec())
+                        .or_default();
+                    *count += 1;
+                    index == *count
+                });
+            if !is_match {
+                continue;
+            }
+            let last = depth + 1 == cursor.path.steps.len();
+            match &cursor.path.attribute {
+                Some(attr) if last => {
+                    let value = elem
+                        .try_get_attribute(attr.as_bytes())
+                        .map_err(|e| parse_err(&reader, e.into()))?
+                        .map(|a| a.unescape_value().map_err(|e| parse_err(&reader, e)))
+                        .transpose()?;
+                    cursor.value = Some(value.map_or(Value::Null, |v| v.into_owned().into()));
+                }
+                _ => {
+                    needs_subtree |= last;
+                    entering.push(i);
+                }
+            }
+        }
+
+        if needs_subtree {
+            if !is_empty {
+                reader
+                    .read_to_end_into(elem.name(), &mut skip_buf)
+                    .map_err(|e| parse_err(&reader, e))?;
+                skip_buf.clear();
+            }
+            let end = reader.buffer_position() as usize;
+            let subtree = parse_xml(&xml[start..end], &options)?;
+            let Some(element) = subtree
+                .as_object()
+                .and_then(|obj| obj.iter().find(|(key, _)| !key.starts_with('#')))
+                .map(|(_, value)| value)
+            else {
+                continue;
+            };
+            // Paths going further down are looked up in the parsed element,
+            // the others keep searching the following siblings
+            for i in entering {
+                let cursor = &mut cursors[i].1;
+                let rest = &cursor.path.steps[depth + 1..];
+                if let Some(value) = select(element, rest, cursor.path.attribute.as_deref())
+                    .into_iter()
+                    .next()
+                {
+                    cursor.value = Some(value.clone());
+                }
+            }
+        } else if is_empty {
+            continue;
+        } else if entering.is_empty() {
+            reader
+                .read_to_end_into(elem.name(), &mut skip_buf)
+                .map_err(|e| parse_err(&reader, e))?;
+            skip_buf.clear();
+        } else {
+            depth += 1;
+            for i in entering {
+                let cursor = &mut cursors[i].1;
+                cursor.matched = depth;
+                cursor.seen[depth].clear();
+            }
+        }
+    }
+
+    Ok(cursors
+        .into_iter()
+        .map(|(key, cursor)| (key.clone(), cursor.value.unwrap_or(Value::Null)))
+        .collect())
+}
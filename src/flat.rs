@@ -0,0 +1,239 @@
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{document_to_xml, parse_xml, ParseOptions, SerializeOptions, XmlDictError};
+
+/// Flattens a `Value` tree into `{dotted.path: scalar}` pairs, e.g.
+/// `survey.shot.0.@id` or `survey.shot.1.#text`.
+///
+/// Path segments are object keys and 0-based array indices. They cannot be
+/// confused with each other, as element names never start with a digit, `@`
+/// or `#`; a `.` inside an element name is escaped as `\.`. Empty objects
+/// and arrays, which have no scalar to hold their place, are kept as `{}`
+/// and `[]` leaves so that `unflatten` restores them.
+pub fn flatten(value: &Value) -> Map<String, Value> {
+    let mut flat = Map::new();
+    flatten_into(value, &mut String::new(), &mut flat);
+    flat
+}
+
+fn flatten_into(value: &Value, path: &mut String, out: &mut Map<String, Value>) {
+    let len = path.len();
+    let push_segment = |path: &mut String, segment: &str| {
+        if len > 0 {
+            path.push('.');
+        }
+        for c in segment.chars() {
+            if c == '.' {
+                path.push('\\');
+            }
+            path.push(c);
+        }
+    };
+    match value {
+        Value::Object(obj) if !obj.is_empty() => {
+            for (key, value) in obj {
+                push_segment(path, key);
+                flatten_into(value, path, out);
+                path.truncate(len);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (i, item) in items.iter().enumerate() {
+                push_segment(path, &i.to_string());
+                flatten_into(item, path, out);
+                path.truncate(len);
+            }
+        }
+        leaf => {
+            out.insert(path.clone(), leaf.clone());
+        }
+    }
+}
+
+/// Parses `xml` and flattens the result, see `flatten`.
+pub fn xml_to_flat(xml: &str, options: &ParseOptions) -> Result<Map<String, Value>, XmlDictError> {
+    Ok(flatten(&parse_xml(xml, options)?))
+}
+
+// Tree being rebuilt from flat paths, before array indices are checked
+enum Node {
+    Unset,
+    Leaf(Value),
+    // Children in first-seen order, with the position of each key
+    Object(Vec<(String, Node)>, HashMap<String, usize>),
+    Array(BTreeMap<usize, Node>),
+}
+
+// Splits a path on `.`, except escaped `\.`
+fn split_path(path: &str) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.clone().next() == Some('.') => {
+                segments.last_mut().unwrap().push('.');
+                chars.next();
+            }
+            '.' => segments.push(String::new()),
+            c => segments.last_mut().unwrap().push(c),
+        }
+    }
+    segments
+}
+
+fn insert(
+    node: &mut Node,
+    segments: &[String],
+    leaf: &Value,
+    path: &str,
+) -> Result<(), XmlDictError> {
+    let conflict = || {
+        XmlDictError::InvalidPath(format!(
+            "{:?} is used both as a value and as a container",
+            path
+        ))
+    };
+    let Some((segment, rest)) = segments.split_first() else {
+        return match node {
+            Node::Unset => {
+                *node = Node::Leaf(leaf.clone());
+                Ok(())
+            }
+            _ => Err(conflict()),
+        };
+    };
+    let index = segment
+        .parse::<usize>()
+        .ok()
+        .filter(|_| segment.bytes().all(|b| b.is_ascii_digit()));
+    if let Node::Unset = node {
+        *node = match index {
+            Some(_) => Node::Array(BTreeMap::new()),
+            None => Node::Object(Vec::new(), HashMap::new()),
+        };
+    }
+    match (node, index) {
+        (Node::Array(items), Some(i)) => {
+            insert(items.entry(i).or_insert(Node::Unset), rest, leaf, path)
+        }
+        (Node::Object(children, positions), None) => {
+            let i = *positions.entry(segment.clone()).or_insert_with(|| {
+                children.push((segment.clone(), Node::Unset));
+                children.len() - 1
+            });
+            insert(&mut children[i].1, rest, leaf, path)
+        }
+        _ => Err(conflict()),
+    }
+}
+
+fn into_value(node: Node, path: &str) -> Result<Value, XmlDictError> {
+    match node {
+        Node::Unset => Ok(Value::Null),
+        Node::Leaf(value) => Ok(value),
+        Node::Object(children, _) => {
+            let mut result = Map::new();
+            for (key, child) in children {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                result.insert(key, into_value(child, &child_path)?);
+            }
+            Ok(Value::Object(result))
+        }
+        Node::Array(items) => {
+            let mut result = Vec::with_capacity(items.len());
+            for (expected, (i, child)) in items.into_iter().enumerate() {
+                if i != expected {
+                    return Err(XmlDictError::InvalidPath(format!(
+                        "{:?} has no item {} before item {}",
+                        path, expected, i
+                    )));
+                }
+                result.push(into_value(child, &format!("{}.{}", path, i))?);
+            }
+            Ok(Value::Array(result))
+        }
+    }
+}
+
+/// Rebuilds the `Value` tree of `flatten` from its dotted paths.
+///
+/// Numeric segments are array positions, which must be contiguous from 0. A
+/// path that is both a value and the prefix of another path, or whose items
+/// are both indexed and named, is an `XmlDictError::InvalidPath`.
+pub fn unflatten(flat: &Map<String, Value>) -> Result<Value, XmlDictError> {
+    let mut root = Node::Unset;
+    for (path, leaf) in flat {
+        if path.is_empty() {
+            insert(&mut root, &[], leaf, path)?;
+        } else {
+            insert(&mut root, &split_path(path), leaf, path)?;
+        }
+    }
+    into_value(root, "")
+}
+
+/// Looks up `path` in `value`, with the dotted paths of `flatten`.
+pub fn get_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    split_path(path)
+        .iter()
+        .try_fold(value, |value, segment| match value {
+            Value::Object(obj) => obj.get(segment),
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+// Number at `path`, from a JSON number or from text such as `"42"` when
+// types were not coerced, looking into `#text` for elements with attributes
+fn number_at<T: std::str::FromStr>(
+    value: &Value,
+    path: &str,
+    from_number: impl Fn(&serde_json::Number) -> Option<T>,
+) -> Option<T> {
+    let value = match get_path(value, path)? {
+        Value::Object(obj) => obj.get("#text")?,
+        value => value,
+    };
+    match value {
+        Value::Number(number) => from_number(number),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// The value at `path` as an `i64`, see `get_path`.
+///
+/// Numbers are read whether they were coerced or kept as text, and elements
+/// with attributes by their `#text`. `None` if the path is missing or the
+/// value is not an integer in range.
+pub fn as_i64_path(value: &Value, path: &str) -> Option<i64> {
+    number_at(value, path, serde_json::Number::as_i64)
+}
+
+/// The value at `path` as a `u64`, like `as_i64_path`.
+pub fn as_u64_path(value: &Value, path: &str) -> Option<u64> {
+    number_at(value, path, serde_json::Number::as_u64)
+}
+
+/// The value at `path` as an `f64`, like `as_i64_path`. Integers are
+/// converted, possibly losing precision beyond 2^53.
+pub fn as_f64_path(value: &Value, path: &str) -> Option<f64> {
+    number_at(value, path, serde_json::Number::as_f64)
+}
+
+/// Rebuilds a document from its flat form and serializes it, see
+/// `unflatten` and `document_to_xml`.
+pub fn flat_to_xml(
+    flat: &Map<String, Value>,
+    options: &SerializeOptions,
+) -> Result<String, XmlDictError> {
+    document_to_xml(&unflatten(flat)?, options)
+}
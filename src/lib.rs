@@ -1,399 +1,2512 @@
-use pyo3::{
-    exceptions::PyValueError,
-    prelude::*,
-    types::{PyDict, PyFloat, PyList, PyString},
-};
+#[cfg(feature = "encodings")]
+use encoding_rs::{EncoderResult, Encoding};
 use quick_xml::events::Event;
 use quick_xml::Reader;
 use serde_json::{Map, Value};
-use std::collections::HashMap;
+use std::borrow::Cow;
+
+mod coerce;
+pub mod diff;
+pub mod error;
+pub mod extract;
+pub mod flat;
+pub mod options;
+#[cfg(feature = "python")]
+mod python;
+pub mod visit;
+
+pub use diff::{diff_values, xml_diff, Difference};
+pub use error::XmlDictError;
+pub use extract::{extract_path, find_all, parse_subtree, xml_find_all, xml_project};
+pub use flat::{
+    as_f64_path, as_i64_path, as_u64_path, flat_to_xml, flatten, get_path, unflatten, xml_to_flat,
+};
+pub use options::{
+    BigIntMode, ControlCharPolicy, DecodeErrors, DuplicatePolicy, EmptyElementValue, NameCase,
+    NonFiniteFloats, ParseOptions, ParsePath, SerializeOptions, WhitespaceMode,
+};
+pub use visit::{walk, Visitor};
+
+// Handle duplicate keys following `duplicate_policy`
+fn insert_child(
+    parent: &mut Map<String, Value>,
+    name: String,
+    value: Value,
+    options: &ParseOptions,
+) -> Result<(), XmlDictError> {
+    let forced = options.force_list.contains(&name);
+    if let Some(existing) = parent.get_mut(&name) {
+        match options.duplicate_policy {
+            DuplicatePolicy::Array => {
+                if let Value::Array(ref mut arr) = existing {
+                    check_array_len(&name, arr.len() + 1, options)?;
+                    arr.push(value);
+                } else {
+                    check_array_len(&name, 2, options)?;
+                    let existing_val = existing.take();
+                    parent.insert(name, Value::Array(vec![existing_val, value]));
+                }
+            }
+            DuplicatePolicy::LastWins if forced => {
+                check_array_len(&name, 1, options)?;
+                *existing = Value::Array(vec![value]);
+            }
+            DuplicatePolicy::LastWins => *existing = value,
+            DuplicatePolicy::FirstWins => {}
+            DuplicatePolicy::Error => {
+                return Err(XmlDictError::Parse(format!(
+                    "Element <{}> is repeated, which duplicate_policy \"error\" rejects",
+                    name
+                )))
+            }
+        }
+    } else if forced {
+        check_array_len(&name, 1, options)?;
+        parent.insert(name, Value::Array(vec![value]));
+    } else {
+        parent.insert(name, value);
+    }
+    Ok(())
+}
+
+// Fails before an array of repeated elements is created or grown to `len`
+// items past `max_array_len`
+fn check_array_len(name: &str, len: usize, options: &ParseOptions) -> Result<(), XmlDictError> {
+    match options.max_array_len {
+        Some(max) if len > max => Err(XmlDictError::LimitExceeded(format!(
+            "element <{}> is repeated more than max_array_len={} times",
+            name, max
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// With `key_by`, elements gather in an object keyed by their key attribute.
+// Repeated keys follow `duplicate_policy`, like repeated elements
+fn insert_keyed(
+    parent: &mut Map<String, Value>,
+    name: String,
+    key: String,
+    value: Value,
+    options: &ParseOptions,
+) -> Result<(), XmlDictError> {
+    let Value::Object(keyed) = parent
+        .entry(name.clone())
+        .or_insert_with(|| Value::Object(Map::new()))
+    else {
+        return Err(XmlDictError::Parse(format!(
+            "Element <{}> collides with another value of the same name",
+            name
+        )));
+    };
+    let Some(existing) = keyed.get_mut(&key) else {
+        keyed.insert(key, value);
+        return Ok(());
+    };
+    match options.duplicate_policy {
+        DuplicatePolicy::Array => match existing {
+            Value::Array(items) => {
+                check_array_len(&name, items.len() + 1, options)?;
+                items.push(value)
+            }
+            _ => {
+                check_array_len(&name, 2, options)?;
+                *existing = Value::Array(vec![existing.take(), value]);
+            }
+        },
+        DuplicatePolicy::LastWins => *existing = value,
+        DuplicatePolicy::FirstWins => {}
+        DuplicatePolicy::Error => {
+            return Err(XmlDictError::Parse(format!(
+                "Element <{}> with key {:?} is repeated, which duplicate_policy \"error\" rejects",
+                name, key
+            )))
+        }
+    }
+    Ok(())
+}
+
+// The value of the `key_by` attribute of an element keyed by it
+fn element_key(
+    name: &str,
+    attrs: &Map<String, Value>,
+    options: &ParseOptions,
+) -> Result<Option<String>, XmlDictError> {
+    let Some(attr) = options.key_by.get(name) else {
+        return Ok(None);
+    };
+    if options.ordered_content {
+        return Ok(None);
+    }
+    match attrs.get(&format!("@{}", attr)).and_then(scalar_text) {
+        Some(key) => Ok(Some(key)),
+        None => Err(XmlDictError::Parse(format!(
+            "Element <{}> has no {:?} attribute to key it by",
+            name, attr
+        ))),
+    }
+}
+
+/// Counters collected while parsing a document, for cheap observability.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParseStats {
+    /// Number of elements, including the root.
+    pub elements: usize,
+    /// Number of attributes across all elements.
+    pub attributes: usize,
+    /// Deepest element nesting level (the root is at depth 1).
+    pub max_depth: usize,
+    /// Total size in bytes of the (unescaped) text content.
+    pub text_bytes: usize,
+}
+
+/// An error `parse_xml_collecting_errors` recovered from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseIssue {
+    /// Byte offset in the document where the problem was found.
+    pub position: u64,
+    pub message: String,
+}
 
 // XML to Dict implementation with optional null field preservation
 
-fn parse_xml(xml: &str, keep_null: bool) -> Result<Value, String> {
-    let mut reader = Reader::from_str(xml);
-    reader.config_mut().trim_text(true);
-    let mut stack: Vec<(String, Option<Value>, HashMap<String, Value>)> = Vec::new();
-    let mut root: Option<Value> = None;
-    let mut current_value: Option<Value> = None;
-    let mut current_attrs: HashMap<String, Value> = HashMap::new();
-    let mut buf = Vec::new();
-    let mut root_name = String::new();
+pub fn parse_xml(xml: &str, options: &ParseOptions) -> Result<Value, XmlDictError> {
+    parse_xml_with_stats(xml, options).map(|(value, _)| value)
+}
+
+// Element being built while its content is parsed
+#[derive(Debug)]
+struct Frame {
+    name: String,
+    attrs: Map<String, Value>,
+    children: Map<String, Value>,
+    // Text runs and `{name: value}` children in document order, only
+    // collected with `ordered_content`
+    content: Vec<Value>,
+    has_text: bool,
+    has_comment: bool,
+    // Some of the text came from a CDATA section
+    has_cdata: bool,
+    // Inside an `xml:space="preserve"` subtree
+    preserve_space: bool,
+    // Expected number of children, from the `presize` scan
+    child_capacity: usize,
+    // Text since the last markup, not trimmed yet
+    pending_text: String,
+    // Text runs joined by `text_separator`, before coercion
+    merged_text: String,
+    // Offset of the start tag, for `track_positions`
+    start: u64,
+}
+
+impl Frame {
+    fn new(name: String, attrs: Map<String, Value>, preserve_space: bool) -> Self {
+        Frame {
+            name,
+            attrs,
+            children: Map::new(),
+            content: Vec::new(),
+            has_text: false,
+            has_comment: false,
+            has_cdata: false,
+            preserve_space,
+            child_capacity: 0,
+            pending_text: String::new(),
+            merged_text: String::new(),
+            start: 0,
+        }
+    }
+
+    fn push_text(&mut self, text: String, options: &ParseOptions) -> Result<(), XmlDictError> {
+        // Before any text, `text_key` can only have been set by a child
+        if !self.has_text
+            && !options.ordered_content
+            && self.children.contains_key(&options.text_key)
+        {
+            return Err(self.text_key_collision(options));
+        }
+        let had_text = std::mem::replace(&mut self.has_text, true);
+        if let (Some(separator), false) = (&options.text_separator, options.ordered_content) {
+            // The runs around children are coerced together
+            if had_text {
+                self.merged_text.push_str(separator);
+            }
+            self.merged_text.push_str(&text);
+            let text = coerce::coerce_text(self.merged_text.clone(), options)?;
+            self.children.insert(options.text_key.clone(), text);
+            return Ok(());
+        }
+        let text = coerce::coerce_text(text, options)?;
+        if options.ordered_content {
+            self.content.push(text);
+        } else {
+            self.children.insert(options.text_key.clone(), text);
+        }
+        Ok(())
+    }
+
+    // Comments are always gathered, whatever `duplicate_policy` says
+    fn push_comment(&mut self, comment: String, options: &ParseOptions) {
+        self.has_comment = true;
+        let comment = Value::String(comment);
+        if options.ordered_content {
+            let mut fragment = Map::new();
+            fragment.insert("#comment".to_string(), comment);
+            self.content.push(Value::Object(fragment));
+        } else {
+            match self.children.get_mut("#comment") {
+                Some(Value::Array(comments)) => comments.push(comment),
+                Some(existing) => *existing = Value::Array(vec![existing.take(), comment]),
+                None => {
+                    self.children.insert("#comment".to_string(), comment);
+                }
+            }
+        }
+    }
+
+    fn push_child(
+        &mut self,
+        name: String,
+        value: Value,
+        options: &ParseOptions,
+    ) -> Result<(), XmlDictError> {
+        if options.ordered_content {
+            let mut fragment = Map::new();
+            fragment.insert(name, value);
+            self.content.push(Value::Object(fragment));
+        } else if self.has_text && name == options.text_key {
+            return Err(self.text_key_collision(options));
+        } else if self.child_capacity > 0 {
+            // Arrays of repeated children start at their expected size
+            let key = name.clone();
+            insert_child(&mut self.children, name, value, options)?;
+            if let Some(Value::Array(items)) = self.children.get_mut(&key) {
+                items.reserve(self.child_capacity.saturating_sub(items.len()));
+            }
+        } else {
+            insert_child(&mut self.children, name, value, options)?;
+        }
+        Ok(())
+    }
+
+    // A child element named like `text_key` next to text content
+    fn text_key_collision(&self, options: &ParseOptions) -> XmlDictError {
+        XmlDictError::Parse(format!(
+            "Element <{}> has both text and a <{}> child, which collide on text_key {:?}; \
+             choose a text_key that is not an element name",
+            self.name, options.text_key, options.text_key
+        ))
+    }
+
+    fn into_value(self, options: &ParseOptions, end: u64) -> Result<Value, XmlDictError> {
+        // Comments keep their position among the other content
+        let mixed = (self.has_text && self.content.iter().any(Value::is_object))
+            || (self.has_comment && self.content.len() > 1);
+        let mut obj = if mixed {
+            let mut m = Map::new();
+            m.insert("#content".to_string(), Value::Array(self.content));
+            m
+        } else if options.ordered_content {
+            // Not mixed: fall back to the simple representation
+            let mut m = Map::new();
+            for fragment in self.content {
+                match fragment {
+                    Value::Object(child) => {
+                        for (name, value) in child {
+                            insert_child(&mut m, name, value, options)?;
+                        }
+                    }
+                    text => {
+                        m.insert(options.text_key.clone(), text);
+                    }
+                }
+            }
+            m
+        } else {
+            self.children
+        };
+
+        if let Some(attr) = &options.honor_index {
+            restore_order(&mut obj, attr, options)?;
+        }
+
+        // Wrapper elements holding a single child name become lists
+        if options.auto_list
+            && self.attrs.is_empty()
+            && obj.len() == 1
+            && !obj.contains_key(&options.text_key)
+            && !obj.contains_key("#content")
+        {
+            if let Some((name, child)) = obj.iter_mut().next() {
+                if !child.is_array() {
+                    check_array_len(name, 1, options)?;
+                    *child = Value::Array(vec![child.take()]);
+                }
+            }
+        }
+
+        if obj.is_empty() && self.attrs.is_empty() && !options.track_positions {
+            if let Some(value) = empty_element_value(options) {
+                return Ok(value);
+            }
+        }
+
+        // `<a></a>` read as empty text, so that it is written back as a pair
+        let empty_text = options.keep_empty_text && obj.is_empty();
+        if empty_text {
+            obj.insert(options.text_key.clone(), Value::String(String::new()));
+        }
+
+        if options.cdata_marker && self.has_cdata && self.has_text {
+            obj.insert("#is_cdata".to_string(), Value::Bool(true));
+        }
+
+        // Attributes go first, as in the start tag
+        let mut attrs = self.attrs;
+        attrs.extend(obj);
+        let mut obj = attrs;
+        if options.track_positions {
+            obj.insert("#pos".to_string(), source_range(self.start, end));
+        }
+
+        // A lone child named like `text_key` is not text, keep it as a child
+        if !self.has_text && !empty_text && obj.contains_key(&options.text_key) {
+            return Ok(Value::Object(obj));
+        }
+        Ok(collapse_object(obj, options))
+    }
+}
+
+// Value of empty elements set by `empty_element_value`, if any
+fn empty_element_value(options: &ParseOptions) -> Option<Value> {
+    match options.empty_element_value {
+        EmptyElementValue::Object => None,
+        EmptyElementValue::String => Some(Value::String(String::new())),
+        EmptyElementValue::Null => Some(Value::Null),
+    }
+}
+
+// `#pos` of an element spanning `start..end` in the source
+fn source_range(start: u64, end: u64) -> Value {
+    Value::Array(vec![start.into(), end.into()])
+}
+
+// Text-only elements collapse to their text and, with
+// `collapse_single_attr`, attribute-only elements to their attribute value
+fn collapse_object(obj: Map<String, Value>, options: &ParseOptions) -> Value {
+    // The `honor_index` attribute is only removed by the parent
+    let collapsible_attr = |k: &str| {
+        k.strip_prefix('@')
+            .is_some_and(|attr| options.honor_index.as_deref() != Some(attr))
+    };
+    let collapsible = obj.len() == 1
+        && obj.keys().all(|k| {
+            *k == options.text_key || (options.collapse_single_attr && collapsible_attr(k))
+        });
+    if collapsible {
+        let (_, value) = obj.into_iter().next().unwrap();
+        return value;
+    }
+    Value::Object(obj)
+}
+
+// Sorts the arrays of children by their `honor_index` attribute, removed
+// from all of them
+fn restore_order(
+    obj: &mut Map<String, Value>,
+    attr: &str,
+    options: &ParseOptions,
+) -> Result<(), XmlDictError> {
+    let key = format!("@{}", attr);
+    for (name, value) in obj.iter_mut() {
+        if name.starts_with(['@', '#']) {
+            continue;
+        }
+        match value {
+            Value::Array(items) => {
+                let mut indexed = items
+                    .drain(..)
+                    .map(|item| take_index(item, &key, options))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if indexed.iter().all(|(index, _)| index.is_some()) {
+                    indexed.sort_by_key(|(index, _)| *index);
+                }
+                items.extend(indexed.into_iter().map(|(_, item)| item));
+            }
+            item => *item = take_index(item.take(), &key, options)?.1,
+        }
+    }
+    Ok(())
+}
+
+// Removes the index attribute `key` of an element value, collapsing what
+// remains as if the element never had it
+fn take_index(
+    item: Value,
+    key: &str,
+    options: &ParseOptions,
+) -> Result<(Option<usize>, Value), XmlDictError> {
+    let Value::Object(mut obj) = item else {
+        return Ok((None, item));
+    };
+    let Some(index) = obj.shift_remove(key) else {
+        return Ok((None, Value::Object(obj)));
+    };
+    let index = scalar_text(&index)
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(|| XmlDictError::Parse(format!("Invalid {} value {}", key, index)))?;
+    let value = if obj.is_empty() && options.keep_null {
+        Value::Null
+    } else {
+        collapse_object(obj, options)
+    };
+    Ok((Some(index), value))
+}
+
+// Value of an element without text, attributes or (kept) children
+fn is_empty_element(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Object(obj) => obj.keys().all(|k| k == "#pos"),
+        Value::String(text) => text.is_empty(),
+        _ => false,
+    }
+}
+
+// Whitespace as defined by the XML spec, unlike `char::is_whitespace`
+fn is_xml_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n')
+}
+
+// XSD `collapse` normalization: whitespace runs become a single space, and
+// leading and trailing whitespace is dropped
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    for word in text.split(is_xml_whitespace).filter(|w| !w.is_empty()) {
+        if !collapsed.is_empty() {
+            collapsed.push(' ');
+        }
+        collapsed.push_str(word);
+    }
+    collapsed
+}
+
+// Decodes raw bytes as UTF-8 according to `options.errors`
+fn decode_utf8<'a>(bytes: &'a [u8], options: &ParseOptions) -> Result<Cow<'a, str>, XmlDictError> {
+    match options.errors {
+        DecodeErrors::Strict => {
+            Ok(Cow::Borrowed(std::str::from_utf8(bytes).map_err(|e| {
+                XmlDictError::Parse(format!("Invalid UTF-8: {}", e))
+            })?))
+        }
+        DecodeErrors::Replace => Ok(String::from_utf8_lossy(bytes)),
+        DecodeErrors::Ignore => match std::str::from_utf8(bytes) {
+            Ok(text) => Ok(Cow::Borrowed(text)),
+            Err(_) => Ok(Cow::Owned(
+                bytes.utf8_chunks().map(|chunk| chunk.valid()).collect(),
+            )),
+        },
+    }
+}
+
+// Decodes and unescapes text or an attribute value according to
+// `options.errors`: unknown entities are replaced by U+FFFD or removed, and
+// other malformed references are kept as written
+fn decode_text<'a>(raw: &'a [u8], options: &ParseOptions) -> Result<Cow<'a, str>, XmlDictError> {
+    let text = decode_utf8(raw, options)?;
+    if options.lenient_html {
+        return Ok(unescape_html(text));
+    }
+    let fallback = match options.errors {
+        DecodeErrors::Strict => None,
+        DecodeErrors::Replace => Some("\u{fffd}"),
+        DecodeErrors::Ignore => Some(""),
+    };
+    let unescaped = quick_xml::escape::unescape_with(&text, |entity| {
+        quick_xml::escape::resolve_predefined_entity(entity).or(fallback)
+    });
+    match unescaped {
+        Ok(Cow::Owned(unescaped)) => Ok(Cow::Owned(unescaped)),
+        Ok(Cow::Borrowed(_)) => Ok(text),
+        Err(e) if options.errors == DecodeErrors::Strict => Err(XmlDictError::Parse(format!(
+            "Invalid escape sequence: {}",
+            e
+        ))),
+        Err(_) => Ok(text),
+    }
+}
+
+// Unescapes predefined, HTML and character references, keeping a `&` that
+// does not start a known reference as written
+fn unescape_html(text: Cow<'_, str>) -> Cow<'_, str> {
+    if !text.contains('&') {
+        return text;
+    }
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text.as_ref();
+    while let Some(amp) = rest.find('&') {
+        unescaped.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let reference = rest[1..]
+            .find(|c: char| c == ';' || c == '&' || c.is_whitespace())
+            .filter(|&end| rest[1 + end..].starts_with(';'))
+            .map(|end| &rest[..end + 2]);
+        let resolved = reference.and_then(|reference| {
+            quick_xml::escape::unescape_with(reference, |entity| {
+                quick_xml::escape::resolve_predefined_entity(entity)
+                    .or_else(|| quick_xml::escape::resolve_html5_entity(entity))
+            })
+            .ok()
+        });
+        match (reference, resolved) {
+            (Some(reference), Some(resolved)) => {
+                unescaped.push_str(&resolved);
+                rest = &rest[reference.len()..];
+            }
+            _ => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    Cow::Owned(unescaped)
+}
+
+// Drops the namespace prefix of `name`, except the reserved `xml:` one
+fn strip_prefix(name: &[u8]) -> &[u8] {
+    match name.iter().position(|&b| b == b':') {
+        Some(i) if &name[..i] != b"xml" => &name[i + 1..],
+        _ => name,
+    }
+}
+
+// Decodes an element or attribute name according to `options.errors`
+fn decode_name(
+    bytes: &[u8],
+    case: NameCase,
+    options: &ParseOptions,
+) -> Result<String, XmlDictError> {
+    if let Some(max) = options.max_name_len {
+        if bytes.len() > max {
+            return Err(XmlDictError::LimitExceeded(format!(
+                "name of {} bytes starting with {:?} is longer than max_name_len={}",
+                bytes.len(),
+                String::from_utf8_lossy(&bytes[..max.min(32)]),
+                max
+            )));
+        }
+    }
+    let bytes = if options.strip_namespaces {
+        strip_prefix(bytes)
+    } else {
+        bytes
+    };
+    let name = if options.errors == DecodeErrors::Strict {
+        String::from_utf8(bytes.to_vec())?
+    } else {
+        decode_utf8(bytes, options)?.into_owned()
+    };
+    Ok(apply_case(name, case))
+}
+
+fn apply_case(mut name: String, case: NameCase) -> String {
+    match case {
+        NameCase::Preserve => name,
+        NameCase::Lower if name.is_ascii() => {
+            name.make_ascii_lowercase();
+            name
+        }
+        NameCase::Upper if name.is_ascii() => {
+            name.make_ascii_uppercase();
+            name
+        }
+        NameCase::Lower => name.to_lowercase(),
+        NameCase::Upper => name.to_uppercase(),
+    }
+}
+
+// Rejects elements outside of `allowed_tags`, when set
+fn check_allowed_tag(name: &str, options: &ParseOptions) -> Result<(), XmlDictError> {
+    match &options.allowed_tags {
+        Some(allowed) if !allowed.contains(name) => Err(XmlDictError::Parse(format!(
+            "Element <{}> is not in allowed_tags",
+            name
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// Handle attributes
+fn element_attributes(
+    e: &quick_xml::events::BytesStart,
+    options: &ParseOptions,
+    malformed: Option<&mut Vec<String>>,
+) -> Result<Map<String, Value>, XmlDictError> {
+    let mut malformed = malformed;
+    let mut attrs = Map::new();
+    if options.ignore_attributes {
+        return Ok(attrs);
+    }
+    let attributes = if options.lenient_attributes || options.lenient_html {
+        e.html_attributes()
+    } else {
+        e.attributes()
+    };
+    for (count, attr) in attributes.enumerate() {
+        if options.max_attr_count.is_some_and(|max| count >= max) {
+            return Err(XmlDictError::LimitExceeded(format!(
+                "element <{}> has more than max_attr_count={} attributes",
+                String::from_utf8_lossy(e.name().as_ref()),
+                count
+            )));
+        }
+        // Malformed attributes are skipped unless decoding is strict, and
+        // reported in `malformed` when collecting errors
+        let a = match attr {
+            Ok(a) => a,
+            Err(err) if malformed.is_some() => {
+                if let Some(malformed) = malformed.as_deref_mut() {
+                    malformed.push(format!("Invalid attribute: {}", err));
+                }
+                continue;
+            }
+            Err(err) if options.errors == DecodeErrors::Strict => {
+                return Err(XmlDictError::Parse(format!("Invalid attribute: {}", err)))
+            }
+            Err(_) => continue,
+        };
+        if options.strip_namespaces
+            && (a.key.as_ref() == b"xmlns" || a.key.as_ref().starts_with(b"xmlns:"))
+        {
+            continue;
+        }
+        // `xml:space`, `xmlns`, ... are defined in lowercase
+        let reserved = a.key.as_ref() == b"xmlns"
+            || a.key.as_ref().starts_with(b"xmlns:")
+            || a.key.as_ref().starts_with(b"xml:");
+        let case = if reserved {
+            NameCase::Preserve
+        } else {
+            options.attr_case
+        };
+        let key = decode_name(a.key.as_ref(), case, options)?;
+        let declaration = key == "xmlns" || key.starts_with("xmlns:");
+        if let (Some(allowed), false) = (&options.allowed_attributes, declaration) {
+            if !allowed.contains(&*key) {
+                return Err(XmlDictError::Parse(format!(
+                    "Attribute {:?} of <{}> is not in allowed_attributes",
+                    key,
+                    String::from_utf8_lossy(e.name().as_ref())
+                )));
+            }
+        }
+        let mut name = String::with_capacity(key.len() + 1);
+        name.push('@');
+        name.push_str(&key);
+        let value = decode_text(&a.value, options)
+            .map_err(|err| {
+                XmlDictError::Parse(format!("Invalid value for attribute {:?}: {}", key, err))
+            })?
+            .into_owned();
+        if value.is_empty() && options.empty_attr_as_none {
+            attrs.insert(name, Value::Null);
+            continue;
+        }
+        #[cfg(feature = "regex")]
+        if let Some(pattern) = &options.attr_preserve_pattern {
+            if pattern.is_match(&value) {
+                attrs.insert(name, Value::String(value));
+                continue;
+            }
+        }
+        let value = coerce::coerce_text(value, options).map_err(|err| match err {
+            XmlDictError::AmbiguousNumber { text, .. } => XmlDictError::AmbiguousNumber {
+                path: name.clone(),
+                text,
+            },
+            other => other,
+        })?;
+        attrs.insert(name, value);
+    }
+    Ok(attrs)
+}
+
+// Builds the `Value` tree from a stream of events, independently of whether
+// the events borrow from the input or from a read buffer
+struct TreeBuilder<'o, 's> {
+    options: &'o ParseOptions,
+    stack: &'s mut Vec<Frame>,
+    // The root element, or all top-level elements with `fragment`
+    top: Map<String, Value>,
+    // Kept apart from `top`, which only holds elements
+    declaration: Option<Map<String, Value>>,
+    doctype: Option<String>,
+    // Expected number of children of the root element, with `presize`
+    root_children: usize,
+    stats: ParseStats,
+    // Recoverable errors, only collected when set
+    issues: Option<&'s mut Vec<ParseIssue>>,
+    // Offsets of the start and the end of the event being handled, locating
+    // the collected errors and the elements with `track_positions`
+    position: u64,
+    event_end: u64,
+}
+
+impl<'o, 's> TreeBuilder<'o, 's> {
+    fn new(options: &'o ParseOptions, stack: &'s mut Vec<Frame>) -> Self {
+        stack.clear();
+        TreeBuilder {
+            options,
+            stack,
+            top: Map::new(),
+            declaration: None,
+            doctype: None,
+            root_children: 0,
+            stats: ParseStats::default(),
+            issues: None,
+            position: 0,
+            event_end: 0,
+        }
+    }
+
+    // Records a recoverable error when collecting them, fails otherwise
+    fn recover(&mut self, message: String) -> Result<(), XmlDictError> {
+        match self.issues.as_deref_mut() {
+            Some(issues) => {
+                issues.push(ParseIssue {
+                    position: self.position,
+                    message,
+                });
+                Ok(())
+            }
+            None => Err(XmlDictError::Parse(message)),
+        }
+    }
+
+    // Records an error of the reader. Ill-formed markup is skipped, a
+    // mismatched end tag closing the current element; after any other error
+    // the reader stops and the open elements are closed at its end
+    fn recover_read_error(
+        &mut self,
+        position: u64,
+        err: quick_xml::Error,
+    ) -> Result<(), XmlDictError> {
+        self.position = position;
+        let mismatched = matches!(
+            err,
+            quick_xml::Error::IllFormed(quick_xml::errors::IllFormedError::MismatchedEndTag { .. })
+        );
+        self.recover(err.to_string())?;
+        if mismatched {
+            self.close_element()?;
+        }
+        Ok(())
+    }
+
+    fn push_value(
+        &mut self,
+        name: String,
+        key: Option<String>,
+        value: Value,
+    ) -> Result<(), XmlDictError> {
+        if let Some(parent) = self.stack.last_mut() {
+            if self.options.skip_empty
+                && is_empty_element(&value)
+                && !self.options.force_list.contains(&name)
+            {
+                return Ok(());
+            }
+            match key {
+                Some(key) => insert_keyed(&mut parent.children, name, key, value, self.options)?,
+                None => parent.push_child(name, value, self.options)?,
+            }
+        } else if self.options.fragment || !self.top.is_empty() {
+            // Elements after the root are only kept when collecting errors
+            match key {
+                Some(key) => insert_keyed(&mut self.top, name, key, value, self.options)?,
+                None => insert_child(&mut self.top, name, value, self.options)?,
+            }
+        } else {
+            self.top.insert(name, value);
+        }
+        Ok(())
+    }
+
+    // Prefixes the path of an ambiguous number with the open elements and
+    // `name`, the element being read if not opened yet
+    fn locate(&self, err: XmlDictError, name: Option<&str>) -> XmlDictError {
+        let XmlDictError::AmbiguousNumber { path, text } = err else {
+            return err;
+        };
+        let mut steps: Vec<&str> = self.stack.iter().map(|f| f.name.as_str()).collect();
+        steps.extend(name);
+        if !path.is_empty() {
+            steps.push(&path);
+        }
+        XmlDictError::AmbiguousNumber {
+            path: steps.join("/"),
+            text,
+        }
+    }
+
+    // Adds the text gathered since the last markup to the current element.
+    // Whitespace is kept verbatim under `xml:space="preserve"`
+    fn flush_text(&mut self) -> Result<(), XmlDictError> {
+        let options = self.options;
+        let Some(frame) = self.stack.last_mut() else {
+            return Ok(());
+        };
+        if frame.pending_text.is_empty() {
+            return Ok(());
+        }
+        let mut text = std::mem::take(&mut frame.pending_text);
+        if !frame.preserve_space {
+            if text.trim().is_empty() {
+                // Reuse the allocation for the next text
+                text.clear();
+                frame.pending_text = text;
+                return Ok(());
+            }
+            match options.effective_whitespace_mode() {
+                WhitespaceMode::TrimEnds => {
+                    let end = text.trim_end_matches(is_xml_whitespace).len();
+                    text.truncate(end);
+                    let start = text.len() - text.trim_start_matches(is_xml_whitespace).len();
+                    text.drain(..start);
+                }
+                WhitespaceMode::Collapse => text = collapse_whitespace(&text),
+                WhitespaceMode::Preserve => {}
+            }
+        }
+        self.stats.text_bytes += text.len();
+        let pushed = frame.push_text(text, options);
+        pushed.map_err(|err| self.locate(err, None))
+    }
+
+    // Only one root element is allowed, unless parsing a fragment
+    fn check_top_level(&mut self, name: &str) -> Result<(), XmlDictError> {
+        if self.stack.is_empty() && !self.top.is_empty() && !self.options.fragment {
+            return self.recover(format!(
+                "Unexpected element <{}> after the root element",
+                name
+            ));
+        }
+        Ok(())
+    }
+
+    // Reads attributes, reporting malformed ones when collecting errors
+    fn attributes(
+        &mut self,
+        e: &quick_xml::events::BytesStart,
+        name: &str,
+    ) -> Result<Map<String, Value>, XmlDictError> {
+        let mut malformed = Vec::new();
+        let collecting = self.issues.is_some().then_some(&mut malformed);
+        let attrs = element_attributes(e, self.options, collecting)
+            .map_err(|err| self.locate(err, Some(name)))?;
+        for message in malformed {
+            self.recover(message)?;
+        }
+        Ok(attrs)
+    }
+
+    fn close_element(&mut self) -> Result<(), XmlDictError> {
+        self.flush_text()?;
+        let Some(frame) = self.stack.pop() else {
+            return Ok(());
+        };
+        let name = frame.name.clone();
+        let key = element_key(&name, &frame.attrs, self.options)?;
+        let new_value = frame.into_value(self.options, self.event_end)?;
+        self.push_value(name, key, new_value)
+    }
+
+    // Returns `false` once the end of the document is reached
+    fn handle(&mut self, event: Event<'_>) -> Result<bool, XmlDictError> {
+        let options = self.options;
+        match event {
+            Event::Start(e) => {
+                self.flush_text()?;
+                let name = decode_name(e.name().as_ref(), options.element_case, options)?;
+                self.check_top_level(&name)?;
+                check_allowed_tag(&name, options)?;
+
+                let attrs = self.attributes(&e, &name)?;
+                self.stats.elements += 1;
+                self.stats.attributes += attrs.len();
+
+                // `xml:space` is inherited until overridden by a descendant
+                let preserve_space = match attrs.get("@xml:space").and_then(Value::as_str) {
+                    Some("preserve") => true,
+                    Some("default") => false,
+                    _ => self.stack.last().is_some_and(|f| f.preserve_space),
+                };
+                let mut frame = Frame::new(name, attrs, preserve_space);
+                frame.start = self.position;
+                if self.stack.is_empty() && self.root_children > 0 {
+                    frame.child_capacity = self.root_children;
+                    if options.ordered_content {
+                        frame.content.reserve(self.root_children);
+                    }
+                }
+                self.stack.push(frame);
+                self.stats.max_depth = self.stats.max_depth.max(self.stack.len());
+            }
+            Event::Text(e) => {
+                let text = decode_text(&e, options)?;
+                if self.stack.is_empty() && !options.fragment && !text.trim().is_empty() {
+                    let message = format!(
+                        "Unexpected text {:?} outside of the root element",
+                        text.trim()
+                    );
+                    return self.recover(message).map(|_| true);
+                }
+                // Runs split by comments or processing instructions are one text
+                if let Some(frame) = self.stack.last_mut() {
+                    frame.pending_text.push_str(&text);
+                }
+            }
+            Event::CData(e) => {
+                // Taken verbatim, as part of the surrounding text
+                let text = decode_utf8(&e, options)?;
+                if self.stack.is_empty() && !options.fragment && !text.trim().is_empty() {
+                    let message = format!(
+                        "Unexpected CDATA {:?} outside of the root element",
+                        text.trim()
+                    );
+                    return self.recover(message).map(|_| true);
+                }
+                if let Some(frame) = self.stack.last_mut() {
+                    frame.pending_text.push_str(&text);
+                    frame.has_cdata = true;
+                }
+            }
+            Event::End(_) => self.close_element()?,
+            Event::Empty(e) => {
+                self.flush_text()?;
+                let name = decode_name(e.name().as_ref(), options.element_case, options)?;
+                self.check_top_level(&name)?;
+                check_allowed_tag(&name, options)?;
+
+                let attrs = self.attributes(&e, &name)?;
+                self.stats.elements += 1;
+                self.stats.attributes += attrs.len();
+                self.stats.max_depth = self.stats.max_depth.max(self.stack.len() + 1);
+
+                let key = element_key(&name, &attrs, options)?;
+                let new_value = if options.track_positions {
+                    let mut obj: Map<String, Value> = attrs.into_iter().collect();
+                    obj.insert(
+                        "#pos".to_string(),
+                        source_range(self.position, self.event_end),
+                    );
+                    Value::Object(obj)
+                } else if let Some(value) =
+                    empty_element_value(options).filter(|_| attrs.is_empty())
+                {
+                    value
+                } else if options.keep_null && attrs.is_empty() {
+                    Value::Null
+                } else {
+                    collapse_object(attrs.into_iter().collect(), options)
+                };
+                self.push_value(name, key, new_value)?;
+            }
+            Event::Comment(e) if options.keep_comments => {
+                // Only ordered content keeps the comment between the text runs
+                if options.ordered_content {
+                    self.flush_text()?;
+                }
+                // Comments outside of the root element are dropped
+                if let Some(frame) = self.stack.last_mut() {
+                    frame.push_comment(decode_utf8(&e, options)?.into_owned(), options);
+                }
+            }
+            Event::Decl(d) if options.keep_declaration => {
+                let decl_error = |e: quick_xml::Error| {
+                    XmlDictError::Parse(format!("Invalid XML declaration: {}", e))
+                };
+                let mut declaration = Map::new();
+                let version = d.version().map_err(decl_error)?;
+                declaration.insert(
+                    "version".to_string(),
+                    decode_utf8(&version, options)?.into(),
+                );
+                let pseudo_attributes =
+                    [("encoding", d.encoding()), ("standalone", d.standalone())];
+                for (name, value) in pseudo_attributes {
+                    if let Some(value) = value {
+                        let value = value.map_err(|e| decl_error(e.into()))?;
+                        declaration.insert(name.to_string(), decode_utf8(&value, options)?.into());
+                    }
+                }
+                self.declaration = Some(declaration);
+            }
+            Event::DocType(e) if options.keep_doctype => {
+                self.doctype = Some(decode_utf8(&e, options)?.trim().to_string());
+            }
+            Event::Eof => {
+                if self.issues.is_some() {
+                    while let Some(frame) = self.stack.last() {
+                        let message = format!("Unclosed element <{}>", frame.name);
+                        self.recover(message)?;
+                        self.close_element()?;
+                    }
+                }
+                return Ok(false);
+            }
+            _ => (),
+        }
+        Ok(true)
+    }
+
+    fn finish(self) -> Result<(Value, ParseStats), XmlDictError> {
+        if self.top.is_empty() && !self.options.allow_empty {
+            return Err(XmlDictError::Parse("Empty XML document".to_string()));
+        }
+        let mut top = self.top;
+        if self.options.strip_root && !self.options.fragment && top.len() == 1 {
+            let (_, root) = top.into_iter().next().unwrap();
+            let mut root = strip_root(root, self.options)?;
+            if let Value::Object(obj) = &mut root {
+                if let Some(declaration) = self.declaration {
+                    obj.insert("#xml".to_string(), Value::Object(declaration));
+                }
+                if let Some(doctype) = self.doctype {
+                    obj.insert("#doctype".to_string(), Value::String(doctype));
+                }
+            }
+            return Ok((root, self.stats));
+        }
+        if let Some(declaration) = self.declaration {
+            top.insert("#xml".to_string(), Value::Object(declaration));
+        }
+        if let Some(doctype) = self.doctype {
+            top.insert("#doctype".to_string(), Value::String(doctype));
+        }
+        Ok((Value::Object(top), self.stats))
+    }
+}
+
+// Value of the root element without its attributes, which are dropped or
+// moved under `root_attr_key`
+fn strip_root(root: Value, options: &ParseOptions) -> Result<Value, XmlDictError> {
+    let Value::Object(obj) = root else {
+        return Ok(root);
+    };
+    let (attrs, mut rest): (Map<String, Value>, Map<String, Value>) =
+        obj.into_iter().partition(|(k, _)| k.starts_with('@'));
+    if let Some(key) = options.root_attr_key.as_ref().filter(|_| !attrs.is_empty()) {
+        if rest.contains_key(key) {
+            return Err(XmlDictError::Parse(format!(
+                "root_attr_key {:?} is also a child of the root element",
+                key
+            )));
+        }
+        rest.insert(key.clone(), Value::Object(attrs));
+    }
+    Ok(collapse_object(rest, options))
+}
+
+fn parse_error<R>(reader: &Reader<R>, e: quick_xml::Error) -> XmlDictError {
+    XmlDictError::Parse(format!(
+        "Error at position {}: {:?}",
+        reader.buffer_position(),
+        e
+    ))
+}
+
+/// Same as `parse_xml`, also returning the `ParseStats` of the document.
+///
+/// Events borrow directly from `xml`: names, attribute values and text are
+/// only copied once, into the resulting `Value`, and unescaping allocates
+/// only when the input actually contains entities.
+pub fn parse_xml_with_stats(
+    xml: &str,
+    options: &ParseOptions,
+) -> Result<(Value, ParseStats), XmlDictError> {
+    parse_str(xml, options, &mut Vec::new(), &mut Vec::new(), None)
+}
+
+/// Same as `parse_xml`, continuing past recoverable errors instead of failing
+/// on the first one. Returns a best-effort `Value` along with the errors met,
+/// in document order.
+///
+/// Recoverable errors are:
+/// - mismatched end tags, closing the current element, and unmatched ones,
+///   which are skipped, as is any other ill-formed markup,
+/// - malformed attributes, which are skipped whatever `errors` says,
+/// - text outside of the root element, which is dropped,
+/// - elements after the root element, kept as with `fragment`,
+/// - elements left open at the end of the input, which are closed there.
+///
+/// Any other syntax error ends the document where it occurs, closing the
+/// open elements. Errors caused by options (limits, `strict_coercion`, the
+/// `"error"` duplicate policy, ...) and empty documents still fail.
+pub fn parse_xml_collecting_errors(
+    xml: &str,
+    options: &ParseOptions,
+) -> Result<(Value, Vec<ParseIssue>), XmlDictError> {
+    let mut issues = Vec::new();
+    let (value, _) = parse_str(
+        xml,
+        options,
+        &mut Vec::new(),
+        &mut Vec::new(),
+        Some(&mut issues),
+    )?;
+    Ok((value, issues))
+}
+
+/// Parses XML read from `source` (a file, a zip entry, ...) with the same
+/// semantics as `parse_xml`.
+///
+/// Events are decoded through an internal buffer that is reused for the
+/// whole document, so the input never has to be loaded in memory at once.
+pub fn parse_xml_reader<R: std::io::BufRead>(
+    source: R,
+    options: &ParseOptions,
+) -> Result<Value, XmlDictError> {
+    parse_buffered(source, options, &mut Vec::new(), &mut Vec::new(), None).map(|(value, _)| value)
+}
+
+/// Same as `parse_xml` over raw bytes, e.g. a memory-mapped file.
+///
+/// Valid UTF-8 input is parsed in place like a `&str`; otherwise the bytes
+/// are read like `parse_xml_reader` input, decoding text according to
+/// `ParseOptions::errors`.
+pub fn parse_xml_bytes(xml: &[u8], options: &ParseOptions) -> Result<Value, XmlDictError> {
+    match std::str::from_utf8(xml) {
+        Ok(xml) => parse_xml(xml, options),
+        Err(_) => parse_xml_reader(xml, options),
+    }
+}
+
+/// Checks that `xml` is a well-formed document without building any `Value`.
+///
+/// Besides the errors reported by the XML reader (mismatched or unclosed
+/// tags, bad syntax, ...), the document must have exactly one root element
+/// and no text outside of it. Stops at the first error, which is returned.
+pub fn validate_well_formed(xml: &str) -> Result<(), XmlDictError> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+    let mut depth = 0usize;
+    let mut roots = 0usize;
+    let ill_formed = |reader: &Reader<&[u8]>, msg: &str| {
+        XmlDictError::Parse(format!(
+            "Error at position {}: {}",
+            reader.buffer_position(),
+            msg
+        ))
+    };
+
+    loop {
+        let event = reader.read_event().map_err(|e| parse_error(&reader, e))?;
+        match &event {
+            Event::Start(_) | Event::Empty(_) if depth == 0 && roots > 0 => {
+                return Err(ill_formed(&reader, "more than one root element"));
+            }
+            Event::Start(e) | Event::Empty(e) => {
+                // Attributes are only parsed on access
+                for attr in e.attributes() {
+                    attr.map_err(|err| parse_error(&reader, err.into()))?;
+                }
+                if matches!(event, Event::Start(_)) {
+                    depth += 1;
+                }
+                roots = 1;
+            }
+            Event::End(_) => depth -= 1,
+            Event::Text(_) | Event::CData(_) if depth == 0 => {
+                return Err(ill_formed(&reader, "text outside of the root element"));
+            }
+            Event::Eof if depth > 0 => {
+                return Err(ill_formed(&reader, "unclosed element"));
+            }
+            Event::Eof if roots == 0 => {
+                return Err(ill_formed(&reader, "no root element"));
+            }
+            Event::Eof => return Ok(()),
+            _ => (),
+        }
+    }
+}
+
+/// Returns whether `xml` is a well-formed document, see
+/// `validate_well_formed`.
+pub fn is_well_formed(xml: &str) -> bool {
+    validate_well_formed(xml).is_ok()
+}
+
+fn max_bytes_exceeded(max: usize) -> XmlDictError {
+    XmlDictError::LimitExceeded(format!("document is larger than max_bytes={}", max))
+}
+
+// Source adaptor failing once more than `remaining` bytes would be read, so
+// that `max_bytes` also bounds what a single event can buffer
+struct LimitedSource<R> {
+    inner: R,
+    remaining: usize,
+    exceeded: bool,
+}
+
+impl<R: std::io::BufRead> std::io::Read for LimitedSource<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = std::io::BufRead::fill_buf(self)?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        std::io::BufRead::consume(self, n);
+        Ok(n)
+    }
+}
+
+impl<R: std::io::BufRead> std::io::BufRead for LimitedSource<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        let available = self.inner.fill_buf()?;
+        if self.remaining == 0 && !available.is_empty() {
+            self.exceeded = true;
+            return Err(std::io::Error::other("max_bytes exceeded"));
+        }
+        Ok(&available[..available.len().min(self.remaining)])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.remaining -= amt;
+        self.inner.consume(amt);
+    }
+}
+
+// Shape of a document estimated by `size_hint`
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SizeHint {
+    max_depth: usize,
+    root_children: usize,
+}
+
+// Estimates the shape of `xml` from its tags alone, without decoding or
+// allocating anything. Malformed input only yields a poor estimate, the
+// parser reports the actual error.
+fn size_hint(xml: &str) -> SizeHint {
+    let bytes = xml.as_bytes();
+    let find = |from: usize, pattern: &[u8]| {
+        bytes[from.min(bytes.len())..]
+            .windows(pattern.len())
+            .position(|w| w == pattern)
+            .map_or(bytes.len(), |i| from + i + pattern.len())
+    };
+    let mut hint = SizeHint::default();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while let Some(offset) = bytes[i..].iter().position(|&b| b == b'<') {
+        i += offset + 1;
+        match bytes.get(i) {
+            Some(b'/') => {
+                depth = depth.saturating_sub(1);
+                i += bytes[i..].iter().position(|&b| b == b'>').unwrap_or(0);
+            }
+            Some(b'?') => i = find(i, b"?>"),
+            Some(b'!') if bytes[i..].starts_with(b"!--") => i = find(i, b"-->"),
+            Some(b'!') if bytes[i..].starts_with(b"![CDATA[") => i = find(i, b"]]>"),
+            Some(b'!') => i = find(i, b">"),
+            Some(_) => {
+                if depth == 1 {
+                    hint.root_children += 1;
+                }
+                // Attribute values may contain `>`
+                let mut quote = None;
+                while let Some(&b) = bytes.get(i) {
+                    i += 1;
+                    match (quote, b) {
+                        (None, b'"' | b'\'') => quote = Some(b),
+                        (Some(q), _) if q == b => quote = None,
+                        (None, b'>') => break,
+                        _ => (),
+                    }
+                }
+                if bytes[..i].ends_with(b"/>") {
+                    hint.max_depth = hint.max_depth.max(depth + 1);
+                } else {
+                    depth += 1;
+                    hint.max_depth = hint.max_depth.max(depth);
+                }
+            }
+            None => break,
+        }
+    }
+    hint
+}
+
+fn parse_str<'s>(
+    xml: &str,
+    options: &ParseOptions,
+    stack: &'s mut Vec<Frame>,
+    buf: &mut Vec<u8>,
+    issues: Option<&'s mut Vec<ParseIssue>>,
+) -> Result<(Value, ParseStats), XmlDictError> {
+    if options.parse_path == ParsePath::Buffered {
+        return parse_buffered(xml.as_bytes(), options, stack, buf, issues);
+    }
+    if let Some(max) = options.max_bytes.filter(|max| xml.len() > *max) {
+        return Err(max_bytes_exceeded(max));
+    }
+    // Text is trimmed by the builder, which knows about `xml:space`
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().expand_empty_elements = options.expand_empty_elements;
+    let mut builder = TreeBuilder::new(options, stack);
+    builder.issues = issues;
+    if options.presize {
+        let hint = size_hint(xml);
+        builder.stack.reserve(hint.max_depth);
+        // With `fragment`, depth 1 counts the children of every top-level element
+        if !options.fragment {
+            builder.root_children = hint.root_children;
+        }
+    }
+
+    loop {
+        builder.position = reader.buffer_position();
+        let event = match reader.read_event() {
+            Ok(event) => event,
+            Err(e) if builder.issues.is_some() => {
+                builder.recover_read_error(reader.error_position(), e)?;
+                continue;
+            }
+            Err(e) => return Err(parse_error(&reader, e)),
+        };
+        builder.event_end = reader.buffer_position();
+        if !builder.handle(event)? {
+            break;
+        }
+    }
+    builder.finish()
+}
+
+fn parse_buffered<'s, R: std::io::BufRead>(
+    source: R,
+    options: &ParseOptions,
+    stack: &'s mut Vec<Frame>,
+    buf: &mut Vec<u8>,
+    issues: Option<&'s mut Vec<ParseIssue>>,
+) -> Result<(Value, ParseStats), XmlDictError> {
+    let source = LimitedSource {
+        inner: source,
+        remaining: options.max_bytes.unwrap_or(usize::MAX),
+        exceeded: false,
+    };
+    let mut reader = Reader::from_reader(source);
+    reader.config_mut().expand_empty_elements = options.expand_empty_elements;
+    let mut builder = TreeBuilder::new(options, stack);
+    builder.issues = issues;
 
     loop {
-        match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(e)) => {
-                let name = e.name().as_ref().to_vec();
-                let name = String::from_utf8_lossy(&name).into_owned();
+        buf.clear();
+        builder.position = reader.buffer_position();
+        let event = match reader.read_event_into(buf) {
+            Ok(event) => event,
+            Err(_) if reader.get_ref().exceeded => {
+                return Err(max_bytes_exceeded(options.max_bytes.unwrap_or_default()))
+            }
+            Err(e) if builder.issues.is_some() => {
+                builder.recover_read_error(reader.error_position(), e)?;
+                continue;
+            }
+            Err(e) => return Err(parse_error(&reader, e)),
+        };
+        builder.event_end = reader.buffer_position();
+        if !builder.handle(event)? {
+            break;
+        }
+    }
+    builder.finish()
+}
 
-                if root_name.is_empty() {
-                    root_name = name.clone();
-                }
+/// Parses at most `max_elements` sibling elements of `xml`, starting at byte
+/// `offset`, which must be the start of an element or of the text before it.
+///
+/// The elements are gathered like the children of an element, repeated
+/// names becoming arrays. Also returns the offset to resume from after them,
+/// or `None` once the end of their parent element or of the document is
+/// reached. Large documents can so be consumed a few records at a time:
+/// start at the first child of the root element and pass each returned
+/// offset to the next call.
+pub fn parse_elements(
+    xml: &str,
+    offset: usize,
+    max_elements: usize,
+    options: &ParseOptions,
+) -> Result<(Value, Option<usize>), XmlDictError> {
+    let rest = xml.get(offset..).ok_or_else(|| {
+        XmlDictError::InvalidOption(format!(
+            "offset {} is not a character boundary of the document",
+            offset
+        ))
+    })?;
+    let options = ParseOptions {
+        fragment: true,
+        allow_empty: true,
+        ..options.clone()
+    };
+    let mut reader = Reader::from_str(rest);
+    reader.config_mut().expand_empty_elements = options.expand_empty_elements;
+    // The end tag of the parent is met without its start tag
+    reader.config_mut().allow_unmatched_ends = true;
+    let mut stack = Vec::new();
+    let mut builder = TreeBuilder::new(&options, &mut stack);
 
-                // Handle attributes
-                let attrs: HashMap<String, Value> = e
-                    .attributes()
-                    .filter_map(|a| a.ok())
-                    .map(|a| {
-                        let key = String::from_utf8_lossy(a.key.as_ref()).to_string();
-                        let value = a.unescape_value().unwrap_or_default().to_string();
-                        (format!("@{}", key), Value::String(value))
-                    })
-                    .collect();
-
-                stack.push((name, current_value, current_attrs));
-                current_attrs = attrs;
-                current_value = Some(Value::Object(Map::new()));
-            }
-            Ok(Event::Text(e)) => {
-                let text = e.unescape().unwrap_or_default().to_string();
-                if !text.trim().is_empty() {
-                    current_value = Some(Value::String(text));
-                }
+    let mut count = 0;
+    while count < max_elements {
+        // Positions are offsets in the whole document
+        builder.position = offset as u64 + reader.buffer_position();
+        let event = reader.read_event().map_err(|e| parse_error(&reader, e))?;
+        builder.event_end = offset as u64 + reader.buffer_position();
+        let closes_sibling = match &event {
+            Event::End(_) if builder.stack.is_empty() => {
+                return Ok((builder.finish()?.0, None));
             }
-            Ok(Event::End(_)) => {
-                let (name, parent_val, parent_attrs) = stack.pop().unwrap();
-                let mut obj = match current_value.take() {
-                    Some(Value::Object(m)) => m,
-                    Some(v) => {
-                        let mut m = Map::new();
-                        m.insert("#text".to_string(), v);
-                        m
-                    }
-                    None => Map::new(),
-                };
+            Event::End(_) => builder.stack.len() == 1,
+            // Most likely an offset in the middle of markup
+            Event::Text(e)
+                if builder.stack.is_empty() && !e.iter().all(u8::is_ascii_whitespace) =>
+            {
+                return Err(XmlDictError::Parse(format!(
+                    "Unexpected text at offset {}, which should be an element boundary",
+                    offset
+                )));
+            }
+            Event::Empty(_) => builder.stack.is_empty(),
+            _ => false,
+        };
+        if !builder.handle(event)? {
+            if let Some(frame) = builder.stack.last() {
+                return Err(XmlDictError::Parse(format!(
+                    "Unclosed element <{}>",
+                    frame.name
+                )));
+            }
+            return Ok((builder.finish()?.0, None));
+        }
+        if closes_sibling {
+            count += 1;
+        }
+    }
+    let next = offset + reader.buffer_position() as usize;
+    Ok((builder.finish()?.0, Some(next)))
+}
 
-                // Merge attributes
-                for (k, v) in current_attrs.drain() {
-                    obj.insert(k, v);
-                }
+/// A reusable parser for workloads parsing many documents with the same
+/// options.
+///
+/// The element stack and the read buffer are kept between calls, so their
+/// allocations are amortized over all the documents parsed.
+#[derive(Debug, Default)]
+pub struct XmlParser {
+    options: ParseOptions,
+    stack: Vec<Frame>,
+    buf: Vec<u8>,
+}
 
-                current_value = parent_val;
-                current_attrs = parent_attrs;
+impl XmlParser {
+    pub fn new(options: ParseOptions) -> Self {
+        XmlParser {
+            options,
+            stack: Vec::new(),
+            buf: Vec::new(),
+        }
+    }
 
-                let new_value = if obj.len() == 1 && obj.contains_key("#text") {
-                    obj.remove("#text").unwrap()
-                } else {
-                    Value::Object(obj)
-                };
+    pub fn options(&self) -> &ParseOptions {
+        &self.options
+    }
 
-                if let Some(Value::Object(ref mut parent)) = current_value {
-                    // Handle duplicate keys by converting to array
-                    if let Some(existing) = parent.get_mut(&name) {
-                        if let Value::Array(ref mut arr) = existing {
-                            arr.push(new_value);
-                        } else {
-                            let existing_val = existing.take();
-                            parent.insert(name, Value::Array(vec![existing_val, new_value]));
-                        }
-                    } else {
-                        parent.insert(name, new_value);
-                    }
-                } else {
-                    root = Some(new_value);
-                }
-            }
-            Ok(Event::Empty(e)) => {
-                let name = e.name().as_ref().to_vec();
-                let name = String::from_utf8_lossy(&name).into_owned();
+    /// Same as `parse_xml` with this parser's options.
+    pub fn parse(&mut self, xml: &str) -> Result<Value, XmlDictError> {
+        self.parse_with_stats(xml).map(|(value, _)| value)
+    }
 
-                if root_name.is_empty() {
-                    root_name = name.clone();
-                }
+    /// Same as `parse_xml_with_stats` with this parser's options.
+    pub fn parse_with_stats(&mut self, xml: &str) -> Result<(Value, ParseStats), XmlDictError> {
+        parse_str(xml, &self.options, &mut self.stack, &mut self.buf, None)
+    }
 
-                // Handle attributes
-                let attrs: HashMap<String, Value> = e
-                    .attributes()
-                    .filter_map(|a| a.ok())
-                    .map(|a| {
-                        let key = String::from_utf8_lossy(a.key.as_ref()).to_string();
-                        let value = a.unescape_value().unwrap_or_default().to_string();
-                        (format!("@{}", key), Value::String(value))
-                    })
-                    .collect();
-
-                let mut obj = Map::new();
-                for (k, v) in attrs {
-                    obj.insert(k, v);
-                }
+    /// Same as `parse_xml_reader` with this parser's options.
+    pub fn parse_reader<R: std::io::BufRead>(&mut self, source: R) -> Result<Value, XmlDictError> {
+        parse_buffered(source, &self.options, &mut self.stack, &mut self.buf, None)
+            .map(|(value, _)| value)
+    }
+}
 
-                let new_value = if keep_null {
-                    Value::Null
-                } else {
-                    Value::Object(obj)
-                };
+// Builds the start tag, quoting attribute values with the configured quote
+// `attributes` hold escaped values, see `escape_value`
+fn start_tag<'a>(
+    name: &'a str,
+    attributes: &[(String, String)],
+    options: &SerializeOptions,
+) -> quick_xml::events::BytesStart<'a> {
+    if options.attr_quote == '"' {
+        let mut elem = quick_xml::events::BytesStart::new(name);
+        for (name, value) in attributes {
+            elem.push_attribute(quick_xml::events::attributes::Attribute {
+                key: quick_xml::name::QName(name.as_bytes()),
+                value: Cow::Borrowed(value.as_bytes()),
+            });
+        }
+        return elem;
+    }
 
-                if let Some(Value::Object(ref mut parent)) = current_value {
-                    // Handle duplicate keys by converting to array
-                    if let Some(existing) = parent.get_mut(&name) {
-                        if let Value::Array(ref mut arr) = existing {
-                            arr.push(new_value);
-                        } else {
-                            let existing_val = existing.take();
-                            parent.insert(name, Value::Array(vec![existing_val, new_value]));
-                        }
-                    } else {
-                        parent.insert(name, new_value);
-                    }
-                } else {
-                    root = Some(new_value);
-                }
+    let mut content = name.to_string();
+    for (name, value) in attributes {
+        content.push(' ');
+        content.push_str(name);
+        content.push('=');
+        content.push(options.attr_quote);
+        content.push_str(value);
+        content.push(options.attr_quote);
+    }
+    quick_xml::events::BytesStart::from_content(content, name.len())
+}
+
+// Characters XML 1.0 forbids, even as character references
+fn is_forbidden_char(c: char) -> bool {
+    matches!(c, '\0'..='\x08' | '\x0B' | '\x0C' | '\x0E'..='\x1F' | '\u{FFFE}' | '\u{FFFF}')
+}
+
+// Escapes markup characters of a text or attribute value, applying
+// `control_char_policy` to forbidden characters
+fn escape_value<'a>(
+    text: &'a str,
+    options: &SerializeOptions,
+) -> Result<Cow<'a, str>, XmlDictError> {
+    if !text.contains(is_forbidden_char) {
+        return Ok(quick_xml::escape::escape(text));
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for run in text.split_inclusive(is_forbidden_char) {
+        let (legal, forbidden) = match run.strip_suffix(is_forbidden_char) {
+            Some(legal) => (legal, run[legal.len()..].chars().next()),
+            None => (run, None),
+        };
+        escaped.push_str(&quick_xml::escape::escape(legal));
+        let Some(c) = forbidden else { continue };
+        match options.control_char_policy {
+            ControlCharPolicy::Strip => {}
+            ControlCharPolicy::NumericEscape if !matches!(c, '\0' | '\u{FFFE}' | '\u{FFFF}') => {
+                escaped.push_str(&format!("&#x{:X};", c as u32));
             }
-            Ok(Event::Eof) => break,
-            Err(e) => {
-                return Err(format!(
-                    "Error at position {}: {:?}",
-                    reader.buffer_position(),
-                    e
-                ))
+            _ => {
+                return Err(XmlDictError::Write(format!(
+                    "Character U+{:04X} is not allowed in XML (control_char_policy {:?})",
+                    c as u32,
+                    options.control_char_policy.as_str()
+                )))
             }
-            _ => (),
         }
-        buf.clear();
     }
-
-    root.map(|r| {
-        let mut root_obj = Map::new();
-        root_obj.insert(root_name, r);
-        Value::Object(root_obj)
-    })
-    .ok_or_else(|| "Empty XML document".to_string())
+    Ok(Cow::Owned(escaped))
 }
 
 // Dict to XML implementation with root node preservation
 
-fn value_to_xml(
+// Text form of a scalar value, `None` for null and containers
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn write_text<W: std::io::Write>(
+    text: &str,
+    writer: &mut quick_xml::Writer<W>,
+    options: &SerializeOptions,
+) -> Result<(), XmlDictError> {
+    let escaped = escape_value(text, options)?;
+    writer
+        .write_event(Event::Text(quick_xml::events::BytesText::from_escaped(
+            escaped,
+        )))
+        .map_err(|e| XmlDictError::Write(e.to_string()))?;
+    Ok(())
+}
+
+// Text as a CDATA section, split where it holds `]]>`, unless it is empty or
+// holds characters forbidden by XML, which only escaped text can handle.
+// Canonical XML replaces CDATA sections with their escaped text
+fn write_text_or_cdata<W: std::io::Write>(
+    text: &str,
+    cdata: bool,
+    writer: &mut quick_xml::Writer<W>,
+    options: &SerializeOptions,
+) -> Result<(), XmlDictError> {
+    if !cdata || options.canonical || text.is_empty() || text.contains(is_forbidden_char) {
+        return write_text(text, writer, options);
+    }
+    for section in quick_xml::events::BytesCData::escaped(text) {
+        writer
+            .write_event(Event::CData(section))
+            .map_err(|e| XmlDictError::Write(e.to_string()))?;
+    }
+    Ok(())
+}
+
+// A `#comment` string, or list of them
+fn write_comments<W: std::io::Write>(
+    value: &Value,
+    writer: &mut quick_xml::Writer<W>,
+) -> Result<(), XmlDictError> {
+    let comments = match value {
+        Value::Array(comments) => comments.as_slice(),
+        comment => std::slice::from_ref(comment),
+    };
+    for comment in comments {
+        let text = scalar_text(comment).unwrap_or_default();
+        if text.contains("--") || text.ends_with('-') {
+            return Err(XmlDictError::Write(format!(
+                "Comment {:?} cannot contain \"--\" or end with \"-\"",
+                text
+            )));
+        }
+        writer
+            .write_event(Event::Comment(quick_xml::events::BytesText::from_escaped(
+                text,
+            )))
+            .map_err(|e| XmlDictError::Write(e.to_string()))?;
+    }
+    Ok(())
+}
+
+// An array nested in an array has no XML representation of its own: it is
+// written as a wrapper element holding its items, named with
+// `array_item_name` or else after the wrapper
+fn write_item<W: std::io::Write>(
+    name: &str,
+    value: &Value,
+    writer: &mut quick_xml::Writer<W>,
+    options: &SerializeOptions,
+) -> Result<(), XmlDictError> {
+    let Value::Array(arr) = value else {
+        return value_to_xml(value, name, writer, options);
+    };
+    let start = quick_xml::events::BytesStart::new(name);
+    if arr.is_empty() {
+        return writer
+            .write_event(Event::Empty(start))
+            .map_err(|e| XmlDictError::Write(e.to_string()));
+    }
+    writer
+        .write_event(Event::Start(start))
+        .map_err(|e| XmlDictError::Write(e.to_string()))?;
+    let item_name = options.array_item_name.as_deref().unwrap_or(name);
+    for (index, item) in arr.iter().enumerate() {
+        write_item(
+            item_name,
+            &indexed_item(item, index, options),
+            writer,
+            options,
+        )?;
+    }
+    writer
+        .write_event(Event::End(quick_xml::events::BytesEnd::new(name)))
+        .map_err(|e| XmlDictError::Write(e.to_string()))?;
+    Ok(())
+}
+
+// Item `index` of an array, with its `index_attribute` when set. Nested
+// arrays are written as wrapper elements, which have no index
+fn indexed_item<'v>(item: &'v Value, index: usize, options: &SerializeOptions) -> Cow<'v, Value> {
+    match &options.index_attribute {
+        Some(attr) if !item.is_array() => {
+            let mut item = item.clone();
+            add_attribute(&mut item, format!("@{}", attr), &Value::from(index));
+            Cow::Owned(item)
+        }
+        _ => Cow::Borrowed(item),
+    }
+}
+
+// Arrays become repeated elements named after their key, or items of a
+// wrapper element named after the key with `array_item_name`
+fn write_child<W: std::io::Write>(
+    name: &str,
+    value: &Value,
+    writer: &mut quick_xml::Writer<W>,
+    options: &SerializeOptions,
+) -> Result<(), XmlDictError> {
+    if name == "#comment" {
+        return write_comments(value, writer);
+    }
+    match (value, &options.array_item_name) {
+        (Value::Array(arr), Some(item_name)) => {
+            let start = quick_xml::events::BytesStart::new(name);
+            if arr.is_empty() {
+                return writer
+                    .write_event(Event::Empty(start))
+                    .map_err(|e| XmlDictError::Write(e.to_string()));
+            }
+            writer
+                .write_event(Event::Start(start))
+                .map_err(|e| XmlDictError::Write(e.to_string()))?;
+            for (index, item) in arr.iter().enumerate() {
+                write_item(
+                    item_name,
+                    &indexed_item(item, index, options),
+                    writer,
+                    options,
+                )?;
+            }
+            writer
+                .write_event(Event::End(quick_xml::events::BytesEnd::new(name)))
+                .map_err(|e| XmlDictError::Write(e.to_string()))?;
+            Ok(())
+        }
+        (Value::Array(arr), None) => {
+            for (index, item) in arr.iter().enumerate() {
+                write_item(name, &indexed_item(item, index, options), writer, options)?;
+            }
+            Ok(())
+        }
+        _ => value_to_xml(value, name, writer, options),
+    }
+}
+
+// Sets an attribute on an element value, or on each item of an array
+fn add_attribute(value: &mut Value, key: String, attr: &Value) {
+    match value {
+        Value::Object(obj) => {
+            obj.insert(key, attr.clone());
+        }
+        Value::Array(items) => {
+            for item in items {
+                add_attribute(item, key.clone(), attr);
+            }
+        }
+        Value::Null => *value = Value::Object(Map::from_iter([(key, attr.clone())])),
+        _ => {
+            let text = value.take();
+            *value = Value::Object(Map::from_iter([
+                ("#text".to_string(), text),
+                (key, attr.clone()),
+            ]));
+        }
+    }
+}
+
+/// Writes `value` as the element `parent_name`.
+///
+/// Objects map `@`-prefixed keys to attributes, `#text` to text content,
+/// `#comment` to comments and any other key to child elements; scalars
+/// become the element's text. The `#pos` offsets recorded by
+/// `ParseOptions::track_positions` are ignored. An
+/// `@xmlns` object maps prefixes to namespace URIs and is written as
+/// `xmlns`/`xmlns:prefix` declarations, the `""` prefix standing for the
+/// default namespace. Declarations read by `parse_xml`, which keeps them as
+/// plain `@xmlns`/`@xmlns:prefix` attributes, are written back unchanged.
+/// Either way, declarations are written ahead of the other attributes.
+///
+/// With `SerializeOptions::flat_attributes`, a `child@attr` key sets the
+/// attribute `attr` of the child element `child`, so that
+/// `{"length": 5, "length@unit": "m"}` is written like
+/// `{"length": {"#text": 5, "@unit": "m"}}` as `<length unit="m">5</length>`.
+///
+/// Arrays are written as repeated elements named after their key. An array
+/// nested in an array becomes a wrapper element, named like the items of the
+/// outer array, around its own items: `{"m": [[1, 2]]}` is written as
+/// `<m><m>1</m><m>2</m></m>`, or `<m><item>1</item><item>2</item></m>` with
+/// `SerializeOptions::array_item_name` set to `item`.
+///
+/// Attributes set to `null` are omitted, or written empty with
+/// `SerializeOptions::none_attr_as_empty`.
+///
+/// Numbers are written as `serde_json` formats them. A `Value` cannot hold
+/// NaN or infinities, which `SerializeOptions::nonfinite_floats` handles when
+/// converting Python floats.
+///
+/// An element is self-closing only when truly empty: `{}` or `null` give
+/// `<a/>`, while an empty string, as `""` or `{"#text": ""}`, gives
+/// `<a></a>`.
+///
+/// In this simple representation `#text` is always written before the
+/// children, so text that followed a child element in the source document
+/// moves ahead of it. Values parsed with `ParseOptions::ordered_content` carry
+/// a `#content` list instead, whose text runs and children are written in
+/// their recorded order.
+///
+/// The text of an object holding `"#is_cdata": true`, as kept with
+/// `ParseOptions::cdata_marker`, is written as CDATA sections.
+///
+/// With `SerializeOptions::canonical`, the start tag holds the namespace
+/// declarations sorted by prefix (`xmlns` first), then the other attributes
+/// sorted by name; the content follows in the order of its keys (document
+/// order for values read by `parse_xml`), `#text` ahead of the children.
+pub fn value_to_xml<W: std::io::Write>(
     value: &Value,
     parent_name: &str,
-    writer: &mut quick_xml::Writer<Vec<u8>>,
-) -> Result<(), String> {
+    writer: &mut quick_xml::Writer<W>,
+    options: &SerializeOptions,
+) -> Result<(), XmlDictError> {
+    let mut declarations = Vec::new();
     let mut attributes = Vec::new();
-    let mut children = Map::new();
+    let mut children: Vec<(&str, Cow<'_, Value>)> = Vec::new();
+    let mut content: &[Value] = &[];
     let mut text = None;
+    let mut cdata = false;
+    let attr_value = |v: &Value| {
+        let text = scalar_text(v).unwrap_or_default();
+        escape_value(&text, options).map(Cow::into_owned)
+    };
 
     if let Value::Object(obj) = value {
+        // Children only known by their `child@attr` keys so far
+        let mut attributes_only: Vec<&str> = Vec::new();
         for (k, v) in obj {
-            if k.starts_with('@') {
+            if k.starts_with('@') && v.is_null() && !options.none_attr_as_empty {
+                // An optional attribute left unset
+                continue;
+            }
+            if let ("@xmlns", Value::Object(namespaces)) = (k.as_str(), v) {
+                // Namespace declaration map, `""` being the default namespace
+                for (prefix, uri) in namespaces {
+                    let attr_name = if prefix.is_empty() {
+                        "xmlns".to_string()
+                    } else {
+                        format!("xmlns:{}", prefix)
+                    };
+                    declarations.push((attr_name, attr_value(uri)?));
+                }
+            } else if k == "@xmlns" || k.starts_with("@xmlns:") {
+                // A declaration kept as a plain attribute by `parse_xml`
+                declarations.push((k[1..].to_string(), attr_value(v)?));
+            } else if k.starts_with('@') {
                 let attr_name = k.trim_start_matches('@');
-                attributes.push((
-                    attr_name.to_string(),
-                    v.as_str().unwrap_or_default().to_string(),
-                ));
+                attributes.push((attr_name.to_string(), attr_value(v)?));
             } else if k == "#text" {
-                text = Some(v.as_str().unwrap_or_default().to_string());
+                // A `null` text is no text, unlike an empty string
+                text = scalar_text(v);
+            } else if let ("#content", Value::Array(fragments)) = (k.as_str(), v) {
+                content = fragments;
+            } else if k == "#pos" {
+                // Source offsets recorded with `ParseOptions::track_positions`
+            } else if k == "#is_cdata" {
+                // Set with `ParseOptions::cdata_marker`
+                cdata = v.as_bool() == Some(true);
+            } else if let Some((child, attr)) =
+                k.split_once('@').filter(|_| options.flat_attributes)
+            {
+                let key = format!("@{}", attr);
+                match children.iter_mut().rev().find(|(name, _)| *name == child) {
+                    Some((_, value)) => add_attribute(value.to_mut(), key, v),
+                    None => {
+                        attributes_only.push(child);
+                        children.push((
+                            child,
+                            Cow::Owned(Value::Object(Map::from_iter([(key, v.clone())]))),
+                        ));
+                    }
+                }
+            } else if let Some(i) = attributes_only.iter().position(|name| name == k) {
+                // Its `child@attr` keys came first: they apply to the value
+                attributes_only.swap_remove(i);
+                if let Some((_, value)) = children.iter_mut().find(|(name, _)| name == k) {
+                    let attrs = std::mem::replace(value, Cow::Borrowed(v)).into_owned();
+                    for (key, attr) in attrs.as_object().into_iter().flatten() {
+                        add_attribute(value.to_mut(), key.clone(), attr);
+                    }
+                }
             } else {
-                children.insert(k.clone(), v.clone());
+                children.push((k.as_str(), Cow::Borrowed(v)));
             }
         }
+    } else {
+        text = scalar_text(value);
     }
 
-    let mut elem = quick_xml::events::BytesStart::new(parent_name);
-    for (name, value) in attributes {
-        elem.push_attribute((name.as_str(), value.as_str()));
+    if options.sort_attributes || options.canonical {
+        attributes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    }
+    if options.canonical {
+        // `xmlns` sorts ahead of every `xmlns:prefix`
+        declarations.sort_by(|(a, _), (b, _)| a.cmp(b));
+    }
+    // Namespace declarations come first, as they scope the other attributes
+    if !declarations.is_empty() {
+        declarations.append(&mut attributes);
+        attributes = declarations;
     }
+    let elem = start_tag(parent_name, &attributes, options);
 
-    if children.is_empty() && text.is_none() {
+    if children.is_empty() && text.is_none() && content.is_empty() {
         writer
             .write_event(Event::Empty(elem))
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| XmlDictError::Write(e.to_string()))?;
     } else {
         writer
             .write_event(Event::Start(elem))
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| XmlDictError::Write(e.to_string()))?;
+
+        // Indenting the content of an element holding text would add to it
+        let inline = options.pretty
+            && (text.is_some() || content.iter().any(|fragment| !fragment.is_object()));
 
         if let Some(text_content) = text {
-            writer
-                .write_event(Event::Text(quick_xml::events::BytesText::new(
-                    &text_content,
-                )))
-                .map_err(|e| e.to_string())?;
+            write_text_or_cdata(&text_content, cdata, writer, options)?;
         }
 
-        for (name, value) in children {
-            match value {
-                Value::Array(arr) => {
-                    for item in arr {
-                        value_to_xml(&item, &name, writer)?;
+        for fragment in content {
+            match fragment {
+                Value::Object(child) => {
+                    for (name, value) in child {
+                        write_child(name, value, writer, options)?;
+                        if inline {
+                            keep_inline(writer)?;
+                        }
+                    }
+                }
+                _ => {
+                    if let Some(text_content) = scalar_text(fragment) {
+                        write_text_or_cdata(&text_content, cdata, writer, options)?;
                     }
                 }
-                _ => value_to_xml(&value, &name, writer)?,
+            }
+        }
+
+        // Canonical output keeps the order of the keys
+        let element_order = options
+            .element_order
+            .get(parent_name)
+            .filter(|_| !options.canonical);
+        if let Some(order) = element_order {
+            // Stable, so unlisted children keep their order after the others
+            children.sort_by_key(|(name, _)| {
+                order
+                    .iter()
+                    .position(|listed| listed == *name)
+                    .unwrap_or(order.len())
+            });
+        } else if options.sort_keys && !options.canonical {
+            // An array is a single entry, its items keep their order
+            children.sort_by_key(|(name, _)| *name);
+        }
+        for (name, value) in children {
+            write_child(name, &value, writer, options)?;
+            if inline {
+                keep_inline(writer)?;
             }
         }
 
         writer
             .write_event(Event::End(quick_xml::events::BytesEnd::new(parent_name)))
-            .map_err(|e| e.to_string())?;
+            .map_err(|e| XmlDictError::Write(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+// The root element value with the `SerializeOptions::namespaces` declared on
+// it, once every prefix used in the tree is known to be declared
+fn with_namespaces<'v>(
+    value: &'v Value,
+    root_name: &str,
+    options: &SerializeOptions,
+) -> Result<Cow<'v, Value>, XmlDictError> {
+    if options.namespaces.is_empty() {
+        return Ok(Cow::Borrowed(value));
+    }
+    check_prefixes(root_name, value, &declared_prefixes(options), options)?;
+
+    let mut root = match value {
+        Value::Object(obj) => obj.clone(),
+        Value::Null => Map::new(),
+        Value::Array(_) => return Ok(Cow::Borrowed(value)),
+        scalar => Map::from_iter([("#text".to_string(), scalar.clone())]),
+    };
+    for (prefix, attr_name, uri) in namespace_declarations(options) {
+        let in_map = match root.get("@xmlns") {
+            Some(Value::Object(map)) => map.contains_key(prefix),
+            _ => false,
+        };
+        let key = format!("@{}", attr_name);
+        if !in_map && !root.contains_key(&key) {
+            root.insert(key, Value::from(uri));
+        }
     }
+    Ok(Cow::Owned(Value::Object(root)))
+}
+
+fn declared_prefixes(options: &SerializeOptions) -> Vec<&str> {
+    options.namespaces.keys().map(String::as_str).collect()
+}
+
+// `(prefix, attribute name, uri)` of each of `SerializeOptions::namespaces`,
+// sorted by prefix so that the output is reproducible
+fn namespace_declarations(options: &SerializeOptions) -> Vec<(&str, String, &str)> {
+    let mut declarations: Vec<_> = options
+        .namespaces
+        .iter()
+        .map(|(prefix, uri)| {
+            let attr_name = match prefix.as_str() {
+                "" => "xmlns".to_string(),
+                prefix => format!("xmlns:{}", prefix),
+            };
+            (prefix.as_str(), attr_name, uri.as_str())
+        })
+        .collect();
+    declarations.sort();
+    declarations
+}
+
+// Fails on the first element or attribute name whose prefix is not declared
+// by `declared` or an `@xmlns` key of an enclosing element
+fn check_prefixes<'v>(
+    name: &str,
+    value: &'v Value,
+    declared: &[&'v str],
+    options: &SerializeOptions,
+) -> Result<(), XmlDictError> {
+    let check = |name: &str, declared: &[&str]| match name.split_once(':') {
+        Some((prefix, _)) if prefix != "xml" && !declared.contains(&prefix) => {
+            Err(XmlDictError::Write(format!(
+                "Namespace prefix {:?} of {:?} is not declared",
+                prefix, name
+            )))
+        }
+        _ => Ok(()),
+    };
+    let obj = match value {
+        Value::Array(items) => {
+            for item in items {
+                check_prefixes(name, item, declared, options)?;
+            }
+            return Ok(());
+        }
+        Value::Object(obj) => obj,
+        _ => return check(name, declared),
+    };
 
+    // The declarations of an element also scope its own name
+    let mut scope = declared.to_vec();
+    for key in obj.keys() {
+        if let Some(prefix) = key.strip_prefix("@xmlns:") {
+            scope.push(prefix);
+        }
+    }
+    if let Some(Value::Object(map)) = obj.get("@xmlns") {
+        scope.extend(map.keys().map(String::as_str));
+    }
+    check(name, &scope)?;
+    for (key, child) in obj {
+        if let Some(attr) = key.strip_prefix('@') {
+            if attr != "xmlns" && !attr.starts_with("xmlns:") {
+                check(attr, &scope)?;
+            }
+        } else if key == "#content" {
+            for fragment in child.as_array().into_iter().flatten() {
+                for (name, child) in fragment.as_object().into_iter().flatten() {
+                    check_prefixes(name, child, &scope, options)?;
+                }
+            }
+        } else if !key.starts_with('#') {
+            match key.split_once('@').filter(|_| options.flat_attributes) {
+                Some((name, attr)) => {
+                    check(name, &scope)?;
+                    check(attr, &scope)?;
+                }
+                None => check_prefixes(key, child, &scope, options)?,
+            }
+        }
+    }
     Ok(())
 }
 
-// Python bindings with optional null field preservation
+// Keeps the indenting writer from breaking the line before the next markup,
+// as it does after anything but text
+fn keep_inline<W: std::io::Write>(writer: &mut quick_xml::Writer<W>) -> Result<(), XmlDictError> {
+    writer
+        .write_event(Event::Text(quick_xml::events::BytesText::from_escaped("")))
+        .map_err(|e| XmlDictError::Write(e.to_string()))
+}
+
+// Sink replacing the line breaks of the indenting writer with
+// `line_ending`. These are written on their own, unlike the line breaks of
+// text, which are part of a larger write unless the text is a lone line
+// break (which XML parsers read back as `\n` either way)
+struct LineEndings<W> {
+    inner: W,
+    line_ending: &'static [u8],
+}
+
+impl<W: std::io::Write> std::io::Write for LineEndings<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf == b"\n" {
+            self.inner.write_all(self.line_ending)?;
+            return Ok(1);
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Writer of a whole document, indenting it with `pretty`. The indentation
+// character is known to be ASCII once the options are validated
+fn document_writer<W: std::io::Write>(
+    inner: W,
+    options: &SerializeOptions,
+) -> quick_xml::Writer<LineEndings<W>> {
+    let line_ending: &'static [u8] = match options.line_ending.as_str() {
+        "\r\n" => b"\r\n",
+        "\r" => b"\r",
+        _ => b"\n",
+    };
+    let inner = LineEndings { inner, line_ending };
+    if options.pretty {
+        quick_xml::Writer::new_with_indent(inner, options.indent_char as u8, options.indent_size)
+    } else {
+        quick_xml::Writer::new(inner)
+    }
+}
 
-#[pyfunction]
-#[pyo3(signature = (xml_str, keep_null=true))]
-fn xml_str_to_dict(xml_str: &str, keep_null: bool) -> PyResult<PyObject> {
-    let value = parse_xml(xml_str, keep_null)
-        .map_err(|e| PyValueError::new_err(format!("XML parsing error: {}", e)))?;
-    Python::with_gil(|py| value_to_pyobject(&value, py))
+// The DOCTYPE and `processing_instructions` of `options`, once the
+// declaration is written
+fn write_prolog<W: std::io::Write>(
+    writer: &mut quick_xml::Writer<W>,
+    doctype: Option<&str>,
+    options: &SerializeOptions,
+) -> Result<(), XmlDictError> {
+    if let Some(doctype) = options.doctype.as_deref().or(doctype) {
+        writer
+            .write_event(Event::DocType(quick_xml::events::BytesText::from_escaped(
+                doctype,
+            )))
+            .map_err(|e| XmlDictError::Write(e.to_string()))?;
+    }
+    for (target, data) in &options.processing_instructions {
+        let content = if data.is_empty() {
+            target.clone()
+        } else {
+            format!("{} {}", target, data)
+        };
+        writer
+            .write_event(Event::PI(quick_xml::events::BytesPI::new(content)))
+            .map_err(|e| XmlDictError::Write(e.to_string()))?;
+    }
+    Ok(())
 }
 
-#[pyfunction]
-fn dict_to_xml_str(data: &Bound<'_, PyDict>, root_name: &str) -> PyResult<String> {
-    let value = pyobject_to_value(data)?;
-    let mut writer = quick_xml::Writer::new(Vec::new());
+fn write_document<W: std::io::Write>(
+    value: &Value,
+    root_name: &str,
+    encoding: &str,
+    writer: &mut quick_xml::Writer<W>,
+    options: &SerializeOptions,
+) -> Result<(), XmlDictError> {
     writer
         .write_event(Event::Decl(quick_xml::events::BytesDecl::new(
             "1.0",
-            Some("utf-8"),
+            Some(encoding),
             None,
         )))
-        .map_err(|e| PyValueError::new_err(format!("XML writing error: {}", e)))?;
+        .map_err(|e| XmlDictError::Write(e.to_string()))?;
+    write_prolog(writer, None, options)?;
+    let root = with_namespaces(value, root_name, options)?;
+    value_to_xml(&root, root_name, writer, options)
+}
 
-    value_to_xml(&value, root_name, &mut writer)
-        .map_err(|e| PyValueError::new_err(format!("XML generation error: {}", e)))?;
+/// Serializes `value` as a complete XML document: an XML declaration
+/// announcing `encoding`, followed by the `root_name` element.
+pub fn value_to_xml_document(
+    value: &Value,
+    root_name: &str,
+    encoding: &str,
+    options: &SerializeOptions,
+) -> Result<String, XmlDictError> {
+    options.validate()?;
+    let mut writer = document_writer(Vec::new(), options);
+    write_document(value, root_name, encoding, &mut writer, options)?;
+    Ok(String::from_utf8(writer.into_inner().inner)?)
+}
 
-    String::from_utf8(writer.into_inner())
-        .map_err(|e| PyValueError::new_err(format!("UTF-8 conversion error: {}", e)))
+/// Serializes `value` as the `root_name` element alone, without an XML
+/// declaration or prolog, honoring the formatting options.
+pub fn value_to_xml_string(
+    value: &Value,
+    root_name: &str,
+    options: &SerializeOptions,
+) -> Result<String, XmlDictError> {
+    options.validate()?;
+    let mut writer = document_writer(Vec::new(), options);
+    let root = with_namespaces(value, root_name, options)?;
+    value_to_xml(&root, root_name, &mut writer, options)?;
+    Ok(String::from_utf8(writer.into_inner().inner)?)
 }
 
-// Updated helper functions for Python/Rust type conversion
+/// Same as `value_to_xml_document`, streaming the UTF-8 document to `sink`.
+///
+/// Output is buffered and handed to `sink` whenever more than
+/// `SerializeOptions::flush_bytes` are pending, so that memory use does not
+/// grow with the size of the document.
+pub fn value_to_xml_writer<W: std::io::Write>(
+    value: &Value,
+    root_name: &str,
+    sink: W,
+    options: &SerializeOptions,
+) -> Result<(), XmlDictError> {
+    options.validate()?;
+    let buffered = std::io::BufWriter::with_capacity(options.flush_bytes, sink);
+    let mut writer = document_writer(buffered, options);
+    write_document(value, root_name, "utf-8", &mut writer, options)?;
+    std::io::Write::flush(&mut writer.into_inner().inner)
+        .map_err(|e| XmlDictError::Write(e.to_string()))
+}
 
-// Function to handle conversion of serde_json::Value
-fn value_to_pyobject(value: &Value, py: Python<'_>) -> PyResult<PyObject> {
-    match value {
-        Value::Null => Ok(py.None()),
-        Value::Bool(b) => Ok(b.into_pyobject(py).unwrap().to_owned().into()),
-        Value::Number(num) => {
-            if let Some(i) = num.as_i64() {
-                Ok(i.into_pyobject(py).unwrap().to_owned().into())
-            } else if let Some(f) = num.as_f64() {
-                Ok(PyFloat::new(py, f).into())
-            } else {
-                Err(pyo3::exceptions::PyValueError::new_err("Invalid number"))
+/// Streams records as the `item_name` children of a single `root_name`
+/// element, for documents too large to be built as one `Value`.
+///
+/// The declaration and the root start tag are written on creation, each
+/// record on `write`, and the root end tag on `finish`. Output is buffered
+/// like with `value_to_xml_writer`, reaching `sink` every
+/// `SerializeOptions::flush_bytes`.
+pub struct RecordWriter<W: std::io::Write> {
+    writer: quick_xml::Writer<LineEndings<std::io::BufWriter<W>>>,
+    root_name: String,
+    item_name: String,
+    options: SerializeOptions,
+}
+
+impl<W: std::io::Write> RecordWriter<W> {
+    pub fn new(
+        sink: W,
+        root_name: &str,
+        item_name: &str,
+        options: &SerializeOptions,
+    ) -> Result<Self, XmlDictError> {
+        options.validate()?;
+        let buffered = std::io::BufWriter::with_capacity(options.flush_bytes, sink);
+        let mut writer = document_writer(buffered, options);
+        writer
+            .write_event(Event::Decl(quick_xml::events::BytesDecl::new(
+                "1.0",
+                Some("utf-8"),
+                None,
+            )))
+            .map_err(|e| XmlDictError::Write(e.to_string()))?;
+        write_prolog(&mut writer, None, options)?;
+        let mut declarations = Vec::new();
+        if !options.namespaces.is_empty() {
+            check_prefixes(
+                root_name,
+                &Value::Null,
+                &declared_prefixes(options),
+                options,
+            )?;
+            for (_, attr_name, uri) in namespace_declarations(options) {
+                declarations.push((attr_name, escape_value(uri, options)?.into_owned()));
             }
         }
-        Value::String(s) => Ok(PyString::new(py, s).into()),
-        // Value::Array(arr) => {
-        //     let py_list = PyList::new(py, &[] as &[PyObject]).expect("Invalid `ExactSizeIterator`");
-        //     for item in arr {
-        //         let py_item = value_to_pyobject(py, item)?;
-        //         py_list.append(py_item)?;
-        //     }
-        //     py_list.into_py_any(py)
-        // }
-        Value::Array(arr) => {
-            let list = PyList::empty(py);
-            for item in arr {
-                list.append(value_to_pyobject(item, py)?)?;
-            }
-            Ok(list.into())
+        writer
+            .write_event(Event::Start(start_tag(root_name, &declarations, options)))
+            .map_err(|e| XmlDictError::Write(e.to_string()))?;
+        Ok(RecordWriter {
+            writer,
+            root_name: root_name.to_string(),
+            item_name: item_name.to_string(),
+            options: options.clone(),
+        })
+    }
+
+    /// Writes `record` as the next `item_name` element.
+    pub fn write(&mut self, record: &Value) -> Result<(), XmlDictError> {
+        if !self.options.namespaces.is_empty() {
+            let declared = declared_prefixes(&self.options);
+            check_prefixes(&self.item_name, record, &declared, &self.options)?;
         }
+        value_to_xml(record, &self.item_name, &mut self.writer, &self.options)
+    }
 
-        // Value::Object(_) => value_to_pydict(py, val),
-        Value::Object(obj) => {
-            let dict = PyDict::new(py);
-            for (k, v) in obj {
-                dict.set_item(k, value_to_pyobject(v, py)?)?;
-            }
-            Ok(dict.into())
-        }
-        // Handle other serde_json::Value types as needed
-        // ...
-        _ => Ok(PyString::new(py, "Unsupported serde_json::Value type").into()),
-    }
-}
-
-fn pyobject_to_value(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
-    if let Ok(s) = obj.extract::<String>() {
-        Ok(Value::String(s))
-    } else if let Ok(n) = obj.extract::<f64>() {
-        Ok(Value::from(n))
-    } else if let Ok(b) = obj.extract::<bool>() {
-        Ok(Value::Bool(b))
-    } else if obj.is_none() {
-        Ok(Value::Null)
-    } else if let Ok(list) = obj.downcast::<PyList>() {
-        // let mut arr = Vec::new();
-        // for item in list.iter() {
-        //     arr.push(pyobject_to_value(&item)?);
-        // }
-        let arr: Vec<_> = list
-            .iter()
-            .map(|item| pyobject_to_value(&item))
-            .collect::<Result<Vec<_>, _>>()?;
-        Ok(Value::Array(arr))
-    } else if let Ok(dict) = obj.downcast::<PyDict>() {
-        // let mut map = Map::new();
-        // for (k, v) in dict.iter() {
-        //     let key: String = k.extract()?;
-        //     map.insert(key, pyobject_to_value(&v)?);
-        // }
-        let map: Map<String, Value> = dict
-            .iter()
-            .filter_map(|(k, v)| {
-                let key = k.extract().ok();
-                let value = pyobject_to_value(&v).ok();
-                key.zip(value).map(|(k, v)| (k, v))
-            })
-            .collect();
-        Ok(Value::Object(map))
-    } else {
-        Err(PyValueError::new_err("Unsupported Python type").into())
+    /// Closes the root element and flushes the output, returning the sink.
+    pub fn finish(mut self) -> Result<W, XmlDictError> {
+        self.writer
+            .write_event(Event::End(quick_xml::events::BytesEnd::new(
+                self.root_name.as_str(),
+            )))
+            .map_err(|e| XmlDictError::Write(e.to_string()))?;
+        self.writer
+            .into_inner()
+            .inner
+            .into_inner()
+            .map_err(|e| XmlDictError::Write(e.to_string()))
     }
 }
 
-/// Reads the contents of the "Data.xml" file from a zip archive.
+/// Serializes a parsed document, as returned by `parse_xml`, back to XML.
 ///
-/// # Arguments
+/// The declaration is written from the `#xml` object kept with
+/// `ParseOptions::keep_declaration`, defaulting to version `1.0` and encoding
+/// `utf-8`, and the DOCTYPE from the `#doctype` string kept with
+/// `ParseOptions::keep_doctype`, unless `SerializeOptions::doctype` is set.
+/// Top-level `#comment` keys are written as comments, and the only other key
+/// as the root element.
 ///
-/// * `path`: The path to the zip archive.
-///
-/// # Returns
-///
-/// The contents of the "Data.xml" file as a string.
-#[pyfunction]
-fn load_ariane_tml_file_to_dict(path: &str) -> PyResult<PyObject> {
-    let file = std::fs::File::open(path).map_err(|e| {
-        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to open file: {}", e))
-    })?;
-    let reader = std::io::BufReader::new(file);
+/// The output is UTF-8, so an `#xml` encoding other than UTF-8 is an
+/// `XmlDictError::Write`: drop it and use `value_to_xml_bytes` to write
+/// another encoding. Any other `#` key, or a number of root elements other
+/// than one, is an `XmlDictError::Write` too.
+pub fn document_to_xml(
+    document: &Value,
+    options: &SerializeOptions,
+) -> Result<String, XmlDictError> {
+    options.validate()?;
+    let Value::Object(document) = document else {
+        return Err(XmlDictError::Write(
+            "A document must be an object mapping its root element name to its value".to_string(),
+        ));
+    };
+    let declaration = document.get("#xml");
+    let pseudo_attribute = |name: &str| declaration.and_then(|d| d.get(name)).and_then(scalar_text);
+    let version = pseudo_attribute("version").unwrap_or_else(|| "1.0".to_string());
+    let encoding = pseudo_attribute("encoding").unwrap_or_else(|| "utf-8".to_string());
+    if !is_utf_8_label(&encoding) {
+        return Err(XmlDictError::Write(format!(
+            "document_to_xml writes UTF-8, not the #xml encoding {:?}",
+            encoding
+        )));
+    }
+    let standalone = pseudo_attribute("standalone");
+    if let Some(key) = document.keys().find(|key| {
+        key.starts_with('#') && !matches!(key.as_str(), "#xml" | "#doctype" | "#comment")
+    }) {
+        return Err(XmlDictError::Write(format!(
+            "Unexpected top-level key {:?} in a document",
+            key
+        )));
+    }
+    let roots = document.keys().filter(|key| !key.starts_with('#')).count();
+    if roots != 1 {
+        return Err(XmlDictError::Write(format!(
+            "A document must have exactly one root element, got {}",
+            roots
+        )));
+    }
+    let doctype = match document.get("#doctype") {
+        Some(Value::String(doctype)) => {
+            options::validate_doctype(doctype)?;
+            Some(doctype.as_str())
+        }
+        Some(other) => {
+            return Err(XmlDictError::Write(format!(
+                "#doctype must be a string, got {}",
+                other
+            )))
+        }
+        None => None,
+    };
 
-    let mut archive = zip::ZipArchive::new(reader).map_err(|e| {
-        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to open zip archive: {}", e))
-    })?;
+    let mut writer = document_writer(Vec::new(), options);
+    writer
+        .write_event(Event::Decl(quick_xml::events::BytesDecl::new(
+            &version,
+            Some(&encoding),
+            standalone.as_deref(),
+        )))
+        .map_err(|e| XmlDictError::Write(e.to_string()))?;
+    write_prolog(&mut writer, doctype, options)?;
+    for (name, value) in document
+        .iter()
+        .filter(|(name, _)| !matches!(name.as_str(), "#xml" | "#doctype"))
+    {
+        let root = match name.starts_with('#') {
+            true => Cow::Borrowed(value),
+            false => with_namespaces(value, name, options)?,
+        };
+        write_child(name, &root, &mut writer, options)?;
+    }
 
-    let mut xml_file = archive.by_name("Data.xml").map_err(|e| {
-        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
-            "Failed to find file in zip archive: {}",
-            e
-        ))
-    })?;
+    Ok(String::from_utf8(writer.into_inner().inner)?)
+}
 
-    let mut xml_contents_vec = Vec::new();
-    std::io::copy(&mut xml_file, &mut xml_contents_vec).map_err(|e| {
-        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read file: {}", e))
-    })?;
+/// Serializes `value` as a complete XML document encoded as `encoding`
+/// (any WHATWG encoding label, e.g. `utf-8` or `windows-1252`).
+///
+/// The XML declaration is updated to match the requested encoding. Content
+/// that cannot be represented in the target encoding is rejected with
+/// `XmlDictError::Unencodable` naming the offending character.
+///
+/// Encodings other than UTF-8 need the `encodings` crate feature (enabled by
+/// `python`), without which they are `XmlDictError::UnsupportedEncoding`.
+pub fn value_to_xml_bytes(
+    value: &Value,
+    root_name: &str,
+    encoding: &str,
+    options: &SerializeOptions,
+) -> Result<Vec<u8>, XmlDictError> {
+    encode_document(value, root_name, encoding, options)
+}
 
-    // Re-Allocation to change type from Vec<u8> to String
-    let xml_contents = String::from_utf8(xml_contents_vec).map_err(|e| {
-        PyErr::new::<pyo3::exceptions::PyUnicodeError, _>(format!(
-            "Failed to convert bytes to string: {}",
-            e
-        ))
-    })?;
+#[cfg(feature = "encodings")]
+fn encode_document(
+    value: &Value,
+    root_name: &str,
+    encoding: &str,
+    options: &SerializeOptions,
+) -> Result<Vec<u8>, XmlDictError> {
+    let target = Encoding::for_label(encoding.as_bytes())
+        .filter(|enc| enc.output_encoding() == *enc)
+        .ok_or_else(|| XmlDictError::UnsupportedEncoding(encoding.to_string()))?;
+
+    let xml = value_to_xml_document(value, root_name, &target.name().to_lowercase(), options)?;
+    if target == encoding_rs::UTF_8 {
+        return Ok(xml.into_bytes());
+    }
 
-    // Convert str to dict
-    let data = parse_xml(xml_contents.as_str(), true)
-        .map_err(|e| PyValueError::new_err(format!("XML parsing error: {}", e)))?;
+    let mut encoder = target.new_encoder();
+    let mut output = Vec::with_capacity(xml.len());
+    let mut input = xml.as_str();
+    loop {
+        let (result, read) =
+            encoder.encode_from_utf8_to_vec_without_replacement(input, &mut output, true);
+        input = &input[read..];
+        match result {
+            EncoderResult::InputEmpty => return Ok(output),
+            EncoderResult::OutputFull => output.reserve(input.len().max(16)),
+            EncoderResult::Unmappable(ch) => {
+                return Err(XmlDictError::Unencodable {
+                    ch,
+                    encoding: target.name().to_string(),
+                })
+            }
+        }
+    }
+}
 
-    Python::with_gil(|py| value_to_pyobject(&data, py))
+// Whether `encoding` is one of the WHATWG labels of UTF-8
+fn is_utf_8_label(encoding: &str) -> bool {
+    const UTF_8_LABELS: &[&str] = &[
+        "unicode-1-1-utf-8",
+        "unicode11utf8",
+        "unicode20utf8",
+        "utf-8",
+        "utf8",
+        "x-unicode20utf8",
+    ];
+    let label = encoding.trim_matches(|c: char| c.is_ascii_whitespace());
+    UTF_8_LABELS
+        .iter()
+        .any(|utf_8| label.eq_ignore_ascii_case(utf_8))
 }
 
-#[pymodule]
-fn openspeleo_core(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(xml_str_to_dict, m)?)?;
-    m.add_function(wrap_pyfunction!(dict_to_xml_str, m)?)?;
-    m.add_function(wrap_pyfunction!(load_ariane_tml_file_to_dict, m)?)?;
-    Ok(())
+// Only UTF-8, under any of its WHATWG labels
+#[cfg(not(feature = "encodings"))]
+fn encode_document(
+    value: &Value,
+    root_name: &str,
+    encoding: &str,
+    options: &SerializeOptions,
+) -> Result<Vec<u8>, XmlDictError> {
+    if !is_utf_8_label(encoding) {
+        return Err(XmlDictError::UnsupportedEncoding(encoding.to_string()));
+    }
+    value_to_xml_document(value, root_name, "utf-8", options).map(String::into_bytes)
 }
@@ -0,0 +1,1433 @@
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "python")]
+use crate::python::extract_names;
+use crate::XmlDictError;
+
+#[cfg(feature = "python")]
+fn compile_pattern(pattern: &str) -> Result<regex::Regex, XmlDictError> {
+    regex::Regex::new(pattern)
+        .map_err(|e| XmlDictError::InvalidOption(format!("Invalid pattern {:?}: {}", pattern, e)))
+}
+
+// Declares an options struct, a Python class with the `python` feature. The
+// `#[pyo3(...)]` field attributes are dropped without it, which `cfg_attr`
+// cannot do as `pyclass` does not expand it on fields
+macro_rules! options_struct {
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[doc = $doc:expr])*
+                $(#[cfg($cfg:meta)])?
+                $(#[pyo3($($pyo3:tt)*)])?
+                pub $field:ident: $ty:ty,
+            )*
+        }
+    ) => {
+        #[cfg(feature = "python")]
+        #[pyclass(module = "openspeleo_core")]
+        $(#[$attr])*
+        pub struct $name {
+            $(
+                $(#[doc = $doc])*
+                $(#[cfg($cfg)])?
+                $(#[pyo3($($pyo3)*)])?
+                pub $field: $ty,
+            )*
+        }
+
+        #[cfg(not(feature = "python"))]
+        $(#[$attr])*
+        pub struct $name {
+            $(
+                $(#[doc = $doc])*
+                $(#[cfg($cfg)])?
+                pub $field: $ty,
+            )*
+        }
+    };
+}
+
+/// How `coerce_types` treats integers that fit neither `i64` nor `u64`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BigIntMode {
+    /// Keep the original text, so that no digit is lost.
+    #[default]
+    String,
+    /// Convert to the nearest `f64`, losing precision beyond 2^53.
+    Float,
+}
+
+impl BigIntMode {
+    /// The name of the variant, as parsed by `FromStr`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BigIntMode::String => "string",
+            BigIntMode::Float => "float",
+        }
+    }
+}
+
+impl std::str::FromStr for BigIntMode {
+    type Err = XmlDictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "string" => Ok(BigIntMode::String),
+            "float" => Ok(BigIntMode::Float),
+            _ => Err(XmlDictError::InvalidOption(format!(
+                "big_int_mode must be \"string\" or \"float\", got {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// How invalid input is decoded, mirroring the `errors` argument of Python
+/// codecs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DecodeErrors {
+    /// Fail on invalid UTF-8, unknown entities and malformed attributes.
+    Strict,
+    /// Replace invalid UTF-8 and unknown entities by U+FFFD.
+    #[default]
+    Replace,
+    /// Drop invalid UTF-8 and unknown entities.
+    Ignore,
+}
+
+impl DecodeErrors {
+    /// The name of the variant, as parsed by `FromStr`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DecodeErrors::Strict => "strict",
+            DecodeErrors::Replace => "replace",
+            DecodeErrors::Ignore => "ignore",
+        }
+    }
+}
+
+impl std::str::FromStr for DecodeErrors {
+    type Err = XmlDictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(DecodeErrors::Strict),
+            "replace" => Ok(DecodeErrors::Replace),
+            "ignore" => Ok(DecodeErrors::Ignore),
+            _ => Err(XmlDictError::InvalidOption(format!(
+                "errors must be \"strict\", \"replace\" or \"ignore\", got {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// What `parse_xml` does with an element repeated under the same parent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// Gather the repeated elements in an array.
+    #[default]
+    Array,
+    /// Keep the last element only.
+    LastWins,
+    /// Keep the first element only.
+    FirstWins,
+    /// Fail with `XmlDictError`.
+    Error,
+}
+
+impl DuplicatePolicy {
+    /// The name of the variant, as parsed by `FromStr`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DuplicatePolicy::Array => "array",
+            DuplicatePolicy::LastWins => "last-wins",
+            DuplicatePolicy::FirstWins => "first-wins",
+            DuplicatePolicy::Error => "error",
+        }
+    }
+}
+
+impl std::str::FromStr for DuplicatePolicy {
+    type Err = XmlDictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "array" => Ok(DuplicatePolicy::Array),
+            "last-wins" => Ok(DuplicatePolicy::LastWins),
+            "first-wins" => Ok(DuplicatePolicy::FirstWins),
+            "error" => Ok(DuplicatePolicy::Error),
+            _ => Err(XmlDictError::InvalidOption(format!(
+                "duplicate_policy must be \"array\", \"last-wins\", \"first-wins\" or \"error\", \
+                 got {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Which reader `parse_xml` runs over an in-memory document.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParsePath {
+    /// Pick the fastest path for the input. The benchmarks (`cargo bench
+    /// --bench parse`) put the borrowed path ahead for documents of every
+    /// size, so it is always picked for valid UTF-8.
+    #[default]
+    Auto,
+    /// Borrow names and text from the input, allocating only for text
+    /// holding entities.
+    Borrowed,
+    /// Copy each event through a read buffer, like `parse_xml_reader`.
+    Buffered,
+}
+
+impl ParsePath {
+    /// The name of the variant, as parsed by `FromStr`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ParsePath::Auto => "auto",
+            ParsePath::Borrowed => "borrowed",
+            ParsePath::Buffered => "buffered",
+        }
+    }
+}
+
+impl std::str::FromStr for ParsePath {
+    type Err = XmlDictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ParsePath::Auto),
+            "borrowed" => Ok(ParsePath::Borrowed),
+            "buffered" => Ok(ParsePath::Buffered),
+            _ => Err(XmlDictError::InvalidOption(format!(
+                "parse_path must be \"auto\", \"borrowed\" or \"buffered\", got {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Value `parse_xml` gives elements without attributes or content.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyElementValue {
+    /// `{}`, or `null` for `<a/>` with `keep_null` and `""` for `<a></a>`
+    /// with `keep_empty_text`.
+    #[default]
+    Object,
+    /// `""`, for both `<a/>` and `<a></a>`.
+    String,
+    /// `null`, for both `<a/>` and `<a></a>`.
+    Null,
+}
+
+impl EmptyElementValue {
+    /// The name of the variant, as parsed by `FromStr`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EmptyElementValue::Object => "object",
+            EmptyElementValue::String => "string",
+            EmptyElementValue::Null => "null",
+        }
+    }
+}
+
+impl std::str::FromStr for EmptyElementValue {
+    type Err = XmlDictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "object" => Ok(EmptyElementValue::Object),
+            "string" => Ok(EmptyElementValue::String),
+            "null" => Ok(EmptyElementValue::Null),
+            _ => Err(XmlDictError::InvalidOption(format!(
+                "empty_element_value must be \"object\", \"string\" or \"null\", got {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Case applied by `parse_xml` to element or attribute names.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NameCase {
+    /// Keep names as written.
+    #[default]
+    Preserve,
+    /// Lowercase names.
+    Lower,
+    /// Uppercase names.
+    Upper,
+}
+
+impl NameCase {
+    /// The name of the variant, as parsed by `FromStr`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NameCase::Preserve => "preserve",
+            NameCase::Lower => "lower",
+            NameCase::Upper => "upper",
+        }
+    }
+}
+
+impl std::str::FromStr for NameCase {
+    type Err = XmlDictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "preserve" => Ok(NameCase::Preserve),
+            "lower" => Ok(NameCase::Lower),
+            "upper" => Ok(NameCase::Upper),
+            _ => Err(XmlDictError::InvalidOption(format!(
+                "name case must be \"preserve\", \"lower\" or \"upper\", got {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// How `parse_xml` treats the whitespace of text, see
+/// `ParseOptions::whitespace_mode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhitespaceMode {
+    /// Strip leading and trailing whitespace, keeping internal newlines and
+    /// runs of spaces.
+    TrimEnds,
+    /// Strip leading and trailing whitespace and collapse internal runs to a
+    /// single space.
+    Collapse,
+    /// Keep text as written.
+    Preserve,
+}
+
+impl WhitespaceMode {
+    /// The name of the variant, as parsed by `FromStr`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WhitespaceMode::TrimEnds => "trim-ends",
+            WhitespaceMode::Collapse => "collapse",
+            WhitespaceMode::Preserve => "preserve",
+        }
+    }
+}
+
+impl std::str::FromStr for WhitespaceMode {
+    type Err = XmlDictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "trim-ends" => Ok(WhitespaceMode::TrimEnds),
+            "collapse" => Ok(WhitespaceMode::Collapse),
+            "preserve" => Ok(WhitespaceMode::Preserve),
+            _ => Err(XmlDictError::InvalidOption(format!(
+                "whitespace_mode must be \"trim-ends\", \"collapse\" or \"preserve\", got {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// How serialization treats characters that XML 1.0 forbids in text and
+/// attribute values: C0 controls other than tab, line feed and carriage
+/// return, U+FFFE and U+FFFF.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ControlCharPolicy {
+    /// Fail with `XmlDictError`.
+    #[default]
+    Reject,
+    /// Drop the characters.
+    Strip,
+    /// Write C0 controls as character references (`&#xC;`), as allowed by
+    /// XML 1.1; NUL, U+FFFE and U+FFFF have no representation and are
+    /// rejected.
+    NumericEscape,
+}
+
+impl ControlCharPolicy {
+    /// The name of the variant, as parsed by `FromStr`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ControlCharPolicy::Reject => "reject",
+            ControlCharPolicy::Strip => "strip",
+            ControlCharPolicy::NumericEscape => "numeric-escape",
+        }
+    }
+}
+
+impl std::str::FromStr for ControlCharPolicy {
+    type Err = XmlDictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(ControlCharPolicy::Reject),
+            "strip" => Ok(ControlCharPolicy::Strip),
+            "numeric-escape" => Ok(ControlCharPolicy::NumericEscape),
+            _ => Err(XmlDictError::InvalidOption(format!(
+                "control_char_policy must be \"reject\", \"strip\" or \"numeric-escape\", got {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// How serialization treats NaN and infinite floats, which have no standard
+/// representation. `Value` numbers are always finite, so this applies when
+/// converting Python objects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NonFiniteFloats {
+    /// Fail with `XmlDictError`.
+    #[default]
+    Error,
+    /// Write `NaN`, `Infinity` or `-Infinity` as text.
+    String,
+    /// Write the value as `None`.
+    Null,
+}
+
+impl NonFiniteFloats {
+    /// The name of the variant, as parsed by `FromStr`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NonFiniteFloats::Error => "error",
+            NonFiniteFloats::String => "string",
+            NonFiniteFloats::Null => "null",
+        }
+    }
+}
+
+impl std::str::FromStr for NonFiniteFloats {
+    type Err = XmlDictError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(NonFiniteFloats::Error),
+            "string" => Ok(NonFiniteFloats::String),
+            "null" => Ok(NonFiniteFloats::Null),
+            _ => Err(XmlDictError::InvalidOption(format!(
+                "nonfinite_floats must be \"error\", \"string\" or \"null\", got {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+options_struct! {
+    /// Options controlling how `parse_xml` maps XML onto a `Value` tree.
+    ///
+    /// Exposed to Python as `openspeleo_core.ParseOptions`, accepted through the
+    /// `options` argument of the parsing functions.
+    #[derive(Clone, Debug)]
+    pub struct ParseOptions {
+        /// Represent self-closing elements without attributes (`<a/>`) as `null`
+        /// instead of `{}`.
+        #[pyo3(get, set)]
+        pub keep_null: bool,
+        /// Element names that are always represented as a list, even when they
+        /// occur only once in their parent.
+        #[pyo3(get)]
+        pub force_list: HashSet<String>,
+        /// Represent the children of a wrapper element as a list when they all
+        /// share one name (`<books><book/></books>`), even for a single child.
+        /// Wrappers carrying attributes or text are left untouched.
+        #[pyo3(get, set)]
+        pub auto_list: bool,
+        /// Represent mixed content (text interleaved with child elements) as an
+        /// ordered `#content` list of text runs and `{name: value}` children, so
+        /// that `value_to_xml` can restore the original order. Elements without
+        /// mixed content keep the simple representation.
+        #[pyo3(get, set)]
+        pub ordered_content: bool,
+        /// Replace elements carrying exactly one attribute and nothing else by
+        /// the attribute value: `<ref id="5"/>` becomes `{"ref": "5"}` instead of
+        /// `{"ref": {"@id": "5"}}`.
+        ///
+        /// This is lossy: the attribute name is dropped, so the collapsed value
+        /// serializes back as element text (`<ref>5</ref>`).
+        #[pyo3(get, set)]
+        pub collapse_single_attr: bool,
+        /// Omit empty elements (no text, no attributes, no children) from their
+        /// parent instead of representing them as `null` or `{}`. Elements listed
+        /// in `force_list` are kept, and the root element is never dropped.
+        #[pyo3(get, set)]
+        pub skip_empty: bool,
+        /// Key holding the text content of elements that also carry attributes
+        /// or children. Parsing fails if an element has both text and a child
+        /// element named like this key.
+        #[pyo3(get, set)]
+        pub text_key: String,
+        /// Handling of invalid UTF-8 and undecodable entities in names, text and
+        /// attribute values, `"strict"`, `"replace"` or `"ignore"` from Python.
+        pub errors: DecodeErrors,
+        /// Maximum length in bytes of element and attribute names, unlimited
+        /// when `None`. Guards against pathological untrusted input.
+        #[pyo3(get, set)]
+        pub max_name_len: Option<usize>,
+        /// Maximum number of attributes on a single element, unlimited when
+        /// `None`.
+        #[pyo3(get, set)]
+        pub max_attr_count: Option<usize>,
+        /// Strip leading and trailing whitespace from text. Whitespace-only text
+        /// (indentation) is dropped either way, except inside elements carrying
+        /// `xml:space="preserve"`, whose text is always kept verbatim.
+        /// Overridden by `whitespace_mode`.
+        #[pyo3(get, set)]
+        pub trim_whitespace: bool,
+        /// Convert text content and attribute values that look like booleans
+        /// (see `true_values`/`false_values`) or numbers to JSON booleans and
+        /// numbers instead of strings.
+        #[pyo3(get, set)]
+        pub coerce_types: bool,
+        /// Tokens read as `true` by `coerce_types`.
+        #[pyo3(get, set)]
+        pub true_values: Vec<String>,
+        /// Tokens read as `false` by `coerce_types`.
+        #[pyo3(get, set)]
+        pub false_values: Vec<String>,
+        /// Whether `true_values`/`false_values` are matched case-sensitively.
+        #[pyo3(get, set)]
+        pub bool_case_sensitive: bool,
+        /// Fallback of `coerce_types` for integers too large for 64 bits,
+        /// `"string"` or `"float"` from Python.
+        pub big_int_mode: BigIntMode,
+        /// Return an empty object for documents without a root element (empty,
+        /// whitespace-only, or holding only a declaration, comments or
+        /// processing instructions) instead of failing.
+        #[pyo3(get, set)]
+        pub allow_empty: bool,
+        /// Python only: return elements holding text and attributes (and no
+        /// children) as `ValueWithAttrs` dicts, exposing `value` and `attrs`
+        /// accessors. The Rust `Value` tree is unaffected.
+        #[pyo3(get, set)]
+        pub value_with_attrs: bool,
+        /// Accept several top-level elements and text outside of them, as in a
+        /// document fragment. Top-level elements are gathered like the children
+        /// of an element, and top-level text is ignored. Without it, anything
+        /// but whitespace, comments and processing instructions after the root
+        /// element is an error.
+        #[pyo3(get, set)]
+        pub fragment: bool,
+        /// Read self-closing elements (`<a/>`) as an empty start/end pair
+        /// (`<a></a>`), so both forms give the same result. `keep_null` then
+        /// no longer applies: empty elements are always `{}`.
+        #[pyo3(get, set)]
+        pub expand_empty_elements: bool,
+        /// Maximum size in bytes of the input, unlimited when `None`. Readers
+        /// are not consumed past the limit.
+        #[pyo3(get, set)]
+        pub max_bytes: Option<usize>,
+        /// Keep the XML declaration as a document-level `#xml` object holding
+        /// its `version`, `encoding` and `standalone` pseudo-attributes, next to
+        /// the root element. `document_to_xml` writes it back.
+        #[pyo3(get, set)]
+        pub keep_declaration: bool,
+        /// With `coerce_types`, fail with `XmlDictError` naming the path of the
+        /// value when text looks numeric but cannot be read exactly as a number
+        /// (`007`, `+5`, `1,5`, `1.2.3`, integers too large for `big_int_mode`
+        /// `"string"`...), instead of keeping it as a string.
+        #[pyo3(get, set)]
+        pub strict_coercion: bool,
+        /// Accept HTML-like attributes, which are not well-formed XML: valueless
+        /// ones (`<input disabled/>`), read as `""`, and unquoted values
+        /// (`<td colspan=2>`).
+        #[pyo3(get, set)]
+        pub lenient_attributes: bool,
+        /// Handling of repeated elements, `"array"`, `"last-wins"`,
+        /// `"first-wins"` or `"error"` from Python. Names in `force_list` are
+        /// still wrapped in an array, holding the one kept element unless the
+        /// policy is `"array"`.
+        pub duplicate_policy: DuplicatePolicy,
+        /// Keep the comments of elements under `#comment`, a list when there are
+        /// several. With `ordered_content` they are `{"#comment": text}`
+        /// fragments of `#content` instead, keeping their position among text
+        /// and children. Comments outside of the root element are dropped.
+        #[pyo3(get, set)]
+        pub keep_comments: bool,
+        /// Read empty attribute values (`x=""`) as `None` instead of `""`.
+        #[pyo3(get, set)]
+        pub empty_attr_as_none: bool,
+        /// Skip attributes entirely, which speeds up data-heavy documents that
+        /// have none or whose attributes are not needed. `xml:space` is ignored
+        /// too.
+        #[pyo3(get, set)]
+        pub ignore_attributes: bool,
+        /// Scan the document once before parsing it, to pre-size the parse stack
+        /// and the arrays of the root element's repeated children. Only pays off
+        /// on large documents; ignored when parsing from a reader.
+        #[pyo3(get, set)]
+        pub presize: bool,
+        /// Drop namespace prefixes from element and attribute names
+        /// (`<gml:pos>` becomes `pos`), along with the `xmlns` declarations. The
+        /// reserved `xml:` prefix of `xml:lang`, `xml:space`, ... is kept.
+        #[pyo3(get, set)]
+        pub strip_namespaces: bool,
+        /// Collapse runs of whitespace in text to a single space and drop leading
+        /// and trailing whitespace, as XSD `xs:token` values are normalized.
+        /// Independent of `trim_whitespace`; attribute values and text under
+        /// `xml:space="preserve"` are left alone. Overridden by `whitespace_mode`.
+        #[pyo3(get, set)]
+        pub normalize_whitespace: bool,
+        /// Reader used for in-memory documents, `"auto"`, `"borrowed"` or
+        /// `"buffered"` from Python. Output is the same either way; input that is
+        /// not valid UTF-8 is always read through the buffered path.
+        pub parse_path: ParsePath,
+        /// Share a single Python `str` between all the occurrences of an element
+        /// or attribute name in the returned dicts, instead of creating one per
+        /// occurrence. Saves memory on large documents repeating the same names,
+        /// at the cost of a lookup per key. Only affects the Python bindings.
+        #[pyo3(get, set)]
+        pub intern_names: bool,
+        /// Element names mapped to the name of an attribute identifying them.
+        /// Such elements gather in an object keyed by that attribute instead of
+        /// an array: with `{"user": "id"}`, `<user id="a"/><user id="b"/>` gives
+        /// `{"user": {"a": {"@id": "a"}, "b": {"@id": "b"}}}`. Repeated keys
+        /// follow `duplicate_policy` and an element missing the attribute is an
+        /// error. Takes precedence over `force_list`; ignored with
+        /// `ordered_content`.
+        #[pyo3(get, set)]
+        pub key_by: HashMap<String, String>,
+        /// Read elements written as an empty start/end pair (`<a></a>`) as empty
+        /// text, `""` or `{"@x": ..., "#text": ""}`, rather than as `{}` like
+        /// `<a/>` without `keep_null`. The serializer writes empty text as a pair
+        /// and a textless element as self-closing, so both forms round-trip.
+        /// With `expand_empty_elements`, `<a/>` is read as `""` too.
+        #[pyo3(get, set)]
+        pub keep_empty_text: bool,
+        /// Name of an attribute holding the position of repeated elements, as
+        /// written with `SerializeOptions::index_attribute`. The elements of an
+        /// array are sorted by it when they all have it, and it is removed from
+        /// the elements. A value that is not a non-negative integer is an error.
+        #[pyo3(get, set)]
+        pub honor_index: Option<String>,
+        /// Record where each element is in the source under `#pos`, as the byte
+        /// offsets `[start, end]` of its start tag and past its end tag, so that
+        /// `xml[start:end]` is the element. Elements always become objects, even
+        /// text-only or empty ones.
+        #[pyo3(get, set)]
+        pub track_positions: bool,
+        /// Return the value of the root element instead of `{root: value}`:
+        /// `<survey><shot/></survey>` gives `{"shot": None}`. The attributes of
+        /// the root are dropped unless `root_attr_key` is set; a kept declaration
+        /// is still returned under `#xml`. Ignored with `fragment`.
+        #[pyo3(get, set)]
+        pub strip_root: bool,
+        /// With `strip_root`, key under which the attributes of the root element
+        /// are kept: `"root"` gives `{"root": {"@unit": "m"}, "shot": None}` for
+        /// `<survey unit="m"><shot/></survey>`. A child of the same name is an
+        /// error.
+        #[pyo3(get, set)]
+        pub root_attr_key: Option<String>,
+        /// Case of element names, `"preserve"`, `"lower"` or `"upper"` from
+        /// Python. Applied after `strip_namespaces`.
+        pub element_case: NameCase,
+        /// Case of attribute names, independently of `element_case`. The
+        /// reserved `xml:` and `xmlns` names are left alone.
+        pub attr_case: NameCase,
+        /// Value of elements without attributes or content, `<a/>` as well as
+        /// `<a></a>`: `"object"` (default), `"string"` or `"null"` from Python.
+        /// Other than `"object"`, takes precedence over `keep_null` and
+        /// `keep_empty_text`. With `track_positions`, elements are never empty.
+        pub empty_element_value: EmptyElementValue,
+        /// Read HTML-like markup that strict XML rejects: attributes as with
+        /// `lenient_attributes`, HTML entities such as `&nbsp;` in text and
+        /// attribute values, and a `&` that starts no known reference kept as
+        /// written. Elements must still be closed. Malformed input is read on a
+        /// best-effort basis, and the result may not write back to the same XML.
+        #[pyo3(get, set)]
+        pub lenient_html: bool,
+        /// Maximum number of repeated elements gathered in one array, unlimited
+        /// when `None`, like `max_attr_count`. Bounds the memory of documents
+        /// with huge sibling lists, which `parse_elements` reads in batches.
+        #[pyo3(get, set)]
+        pub max_array_len: Option<usize>,
+        /// Keep the `<!DOCTYPE ...>` declaration as a top-level `#doctype` key
+        /// holding its content, e.g. `"survey SYSTEM \"survey.dtd\""`, which
+        /// `document_to_xml` writes back. The DTD itself is not applied.
+        #[pyo3(get, set)]
+        pub keep_doctype: bool,
+        /// With `coerce_types`, attribute values matching this regular
+        /// expression are kept as strings, e.g. `^[0-9A-Fa-f]+$` for hex
+        /// identifiers that would otherwise read as numbers (`1E3`). The pattern
+        /// matches anywhere in the value unless anchored. Set from Python as a
+        /// pattern string. Needs the `regex` crate feature (enabled by
+        /// `python`).
+        #[cfg(feature = "regex")]
+        pub attr_preserve_pattern: Option<regex::Regex>,
+        /// With `coerce_types`, keep numbers exactly as written instead of
+        /// rounding them to `f64`: `0.10` stays `0.10` and integers of any size
+        /// are numbers, regardless of `big_int_mode`. `value_to_xml` writes the
+        /// same digits back, and Python gets `int` and `decimal.Decimal` values.
+        /// Needs the `exact_numbers` crate feature, without which it is an
+        /// `XmlDictError::InvalidOption`.
+        #[pyo3(get, set)]
+        pub exact_numbers: bool,
+        /// Without `ordered_content`, join the text runs of an element separated
+        /// by children (`<p>a<b/>c</p>`) with this separator into one `text_key`
+        /// value, `"a c"` with `" "`, instead of keeping only the last run. Where
+        /// the children stood is lost, and each run is trimmed on its own.
+        #[pyo3(get, set)]
+        pub text_separator: Option<String>,
+        /// Return elements marked `encoding="base64"` as Python `bytes`,
+        /// decoded from their text, the way bytes are serialized. Only
+        /// elements without other attributes or children are decoded; content
+        /// that is not valid base64 is an error. The `Value` tree is unchanged.
+        #[pyo3(get, set)]
+        pub decode_base64: bool,
+        /// Whitespace handling of text: `trim-ends`, `collapse` or `preserve`.
+        /// When set, it takes precedence over `trim_whitespace` and
+        /// `normalize_whitespace`, the round-trip preset included. In every
+        /// mode whitespace-only text is dropped and `xml:space="preserve"` text
+        /// kept verbatim.
+        pub whitespace_mode: Option<WhitespaceMode>,
+        /// Add `"#is_cdata": true` next to the text of elements whose text came,
+        /// at least in part, from CDATA sections, so that `value_to_xml` writes
+        /// it back as CDATA. The text of CDATA sections is merged with the
+        /// surrounding text either way.
+        #[pyo3(get, set)]
+        pub cdata_marker: bool,
+        /// When set, elements whose name is not in this set are rejected with
+        /// `XmlDictError`, restricting documents to a known vocabulary. Names
+        /// are compared as they appear in the result, after `strip_namespaces`
+        /// and `element_case`.
+        #[pyo3(get)]
+        pub allowed_tags: Option<HashSet<String>>,
+        /// Same as `allowed_tags` for attribute names, given without `@`.
+        /// Namespace declarations (`xmlns`, `xmlns:prefix`) are always allowed.
+        #[pyo3(get)]
+        pub allowed_attributes: Option<HashSet<String>>,
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            keep_null: true,
+            force_list: HashSet::new(),
+            auto_list: false,
+            ordered_content: false,
+            collapse_single_attr: false,
+            skip_empty: false,
+            text_key: "#text".to_string(),
+            errors: DecodeErrors::default(),
+            max_name_len: None,
+            max_attr_count: None,
+            trim_whitespace: true,
+            coerce_types: false,
+            true_values: vec!["true".to_string()],
+            false_values: vec!["false".to_string()],
+            bool_case_sensitive: true,
+            big_int_mode: BigIntMode::default(),
+            allow_empty: false,
+            value_with_attrs: false,
+            fragment: false,
+            expand_empty_elements: false,
+            max_bytes: None,
+            keep_declaration: false,
+            strict_coercion: false,
+            lenient_attributes: false,
+            duplicate_policy: DuplicatePolicy::default(),
+            keep_comments: false,
+            empty_attr_as_none: false,
+            ignore_attributes: false,
+            presize: false,
+            strip_namespaces: false,
+            normalize_whitespace: false,
+            parse_path: ParsePath::default(),
+            intern_names: false,
+            key_by: HashMap::new(),
+            keep_empty_text: false,
+            honor_index: None,
+            track_positions: false,
+            strip_root: false,
+            root_attr_key: None,
+            element_case: NameCase::default(),
+            attr_case: NameCase::default(),
+            empty_element_value: EmptyElementValue::default(),
+            lenient_html: false,
+            max_array_len: None,
+            keep_doctype: false,
+            #[cfg(feature = "regex")]
+            attr_preserve_pattern: None,
+            exact_numbers: false,
+            text_separator: None,
+            decode_base64: false,
+            whitespace_mode: None,
+            cdata_marker: false,
+            allowed_tags: None,
+            allowed_attributes: None,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Options reading a document as losslessly as the dict representation
+    /// allows, so that `document_to_xml` writes it back unchanged. Starting
+    /// from the defaults, this sets:
+    ///
+    /// - `ordered_content`, keeping text and children in document order,
+    /// - `keep_comments`, keeping comments in place,
+    /// - `keep_declaration`, keeping the `<?xml ...?>` declaration,
+    /// - `keep_doctype`, keeping the `<!DOCTYPE ...>` declaration,
+    /// - `cdata_marker`, writing CDATA sections back as such,
+    /// - `keep_null`, reading `<a/>` as `None`,
+    /// - `keep_empty_text`, reading `<a></a>` as `""`,
+    /// - `trim_whitespace` to `false`, keeping the whitespace around text.
+    ///
+    /// Still lost are whitespace-only text outside `xml:space="preserve"`,
+    /// the order and quoting of attributes, character and entity references,
+    /// which are written back decoded, and anything outside the root element
+    /// other than the declarations. Available from Python as
+    /// `ParseOptions(round_trip=True)`.
+    pub fn round_trip() -> Self {
+        ParseOptions::default().with_round_trip()
+    }
+
+    /// The whitespace handling applied to text: `whitespace_mode`, or else the
+    /// mode `trim_whitespace` and `normalize_whitespace` amount to.
+    pub fn effective_whitespace_mode(&self) -> WhitespaceMode {
+        match self.whitespace_mode {
+            Some(mode) => mode,
+            None if self.normalize_whitespace => WhitespaceMode::Collapse,
+            None if self.trim_whitespace => WhitespaceMode::TrimEnds,
+            None => WhitespaceMode::Preserve,
+        }
+    }
+
+    fn with_round_trip(self) -> Self {
+        ParseOptions {
+            ordered_content: true,
+            keep_comments: true,
+            keep_declaration: true,
+            keep_doctype: true,
+            cdata_marker: true,
+            keep_null: true,
+            keep_empty_text: true,
+            trim_whitespace: false,
+            ..self
+        }
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ParseOptions {
+    #[new]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (
+        keep_null=true,
+        force_list=None,
+        auto_list=false,
+        ordered_content=false,
+        collapse_single_attr=false,
+        skip_empty=false,
+        text_key="#text".to_string(),
+        errors="replace",
+        max_name_len=None,
+        max_attr_count=None,
+        trim_whitespace=true,
+        coerce_types=false,
+        true_values=vec!["true".to_string()],
+        false_values=vec!["false".to_string()],
+        bool_case_sensitive=true,
+        big_int_mode="string",
+        allow_empty=false,
+        value_with_attrs=false,
+        fragment=false,
+        expand_empty_elements=false,
+        max_bytes=None,
+        keep_declaration=false,
+        strict_coercion=false,
+        lenient_attributes=false,
+        duplicate_policy="array",
+        keep_comments=false,
+        empty_attr_as_none=false,
+        ignore_attributes=false,
+        presize=false,
+        strip_namespaces=false,
+        normalize_whitespace=false,
+        parse_path="auto",
+        intern_names=false,
+        key_by=HashMap::new(),
+        keep_empty_text=false,
+        honor_index=None,
+        track_positions=false,
+        strip_root=false,
+        root_attr_key=None,
+        element_case="preserve",
+        attr_case="preserve",
+        empty_element_value="object",
+        lenient_html=false,
+        max_array_len=None,
+        keep_doctype=false,
+        attr_preserve_pattern=None,
+        exact_numbers=false,
+        text_separator=None,
+        decode_base64=false,
+        whitespace_mode=None,
+        cdata_marker=false,
+        allowed_tags=None,
+        allowed_attributes=None,
+        round_trip=false,
+    ))]
+    fn py_new(
+        keep_null: bool,
+        force_list: Option<&Bound<'_, PyAny>>,
+        auto_list: bool,
+        ordered_content: bool,
+        collapse_single_attr: bool,
+        skip_empty: bool,
+        text_key: String,
+        errors: &str,
+        max_name_len: Option<usize>,
+        max_attr_count: Option<usize>,
+        trim_whitespace: bool,
+        coerce_types: bool,
+        true_values: Vec<String>,
+        false_values: Vec<String>,
+        bool_case_sensitive: bool,
+        big_int_mode: &str,
+        allow_empty: bool,
+        value_with_attrs: bool,
+        fragment: bool,
+        expand_empty_elements: bool,
+        max_bytes: Option<usize>,
+        keep_declaration: bool,
+        strict_coercion: bool,
+        lenient_attributes: bool,
+        duplicate_policy: &str,
+        keep_comments: bool,
+        empty_attr_as_none: bool,
+        ignore_attributes: bool,
+        presize: bool,
+        strip_namespaces: bool,
+        normalize_whitespace: bool,
+        parse_path: &str,
+        intern_names: bool,
+        key_by: HashMap<String, String>,
+        keep_empty_text: bool,
+        honor_index: Option<String>,
+        track_positions: bool,
+        strip_root: bool,
+        root_attr_key: Option<String>,
+        element_case: &str,
+        attr_case: &str,
+        empty_element_value: &str,
+        lenient_html: bool,
+        max_array_len: Option<usize>,
+        keep_doctype: bool,
+        attr_preserve_pattern: Option<&str>,
+        exact_numbers: bool,
+        text_separator: Option<String>,
+        decode_base64: bool,
+        whitespace_mode: Option<&str>,
+        cdata_marker: bool,
+        allowed_tags: Option<&Bound<'_, PyAny>>,
+        allowed_attributes: Option<&Bound<'_, PyAny>>,
+        round_trip: bool,
+    ) -> PyResult<Self> {
+        let options = ParseOptions {
+            keep_null,
+            force_list: force_list
+                .map(extract_names)
+                .transpose()?
+                .unwrap_or_default(),
+            auto_list,
+            ordered_content,
+            collapse_single_attr,
+            skip_empty,
+            text_key,
+            errors: errors.parse()?,
+            max_name_len,
+            max_attr_count,
+            trim_whitespace,
+            coerce_types,
+            true_values,
+            false_values,
+            bool_case_sensitive,
+            big_int_mode: big_int_mode.parse()?,
+            allow_empty,
+            value_with_attrs,
+            fragment,
+            expand_empty_elements,
+            max_bytes,
+            keep_declaration,
+            strict_coercion,
+            lenient_attributes,
+            duplicate_policy: duplicate_policy.parse()?,
+            keep_comments,
+            empty_attr_as_none,
+            ignore_attributes,
+            presize,
+            strip_namespaces,
+            normalize_whitespace,
+            parse_path: parse_path.parse()?,
+            intern_names,
+            key_by,
+            keep_empty_text,
+            honor_index,
+            track_positions,
+            strip_root,
+            root_attr_key,
+            element_case: element_case.parse()?,
+            attr_case: attr_case.parse()?,
+            empty_element_value: empty_element_value.parse()?,
+            lenient_html,
+            max_array_len,
+            keep_doctype,
+            attr_preserve_pattern: attr_preserve_pattern.map(compile_pattern).transpose()?,
+            exact_numbers,
+            text_separator,
+            decode_base64,
+            whitespace_mode: whitespace_mode.map(str::parse).transpose()?,
+            cdata_marker,
+            allowed_tags: allowed_tags.map(extract_names).transpose()?,
+            allowed_attributes: allowed_attributes.map(extract_names).transpose()?,
+        };
+        // The preset wins over the individual flags it sets
+        Ok(if round_trip {
+            options.with_round_trip()
+        } else {
+            options
+        })
+    }
+
+    #[getter(big_int_mode)]
+    fn py_big_int_mode(&self) -> &'static str {
+        self.big_int_mode.as_str()
+    }
+
+    #[setter(big_int_mode)]
+    fn py_set_big_int_mode(&mut self, mode: &str) -> PyResult<()> {
+        self.big_int_mode = mode.parse()?;
+        Ok(())
+    }
+
+    #[getter(errors)]
+    fn py_errors(&self) -> &'static str {
+        self.errors.as_str()
+    }
+
+    #[setter(errors)]
+    fn py_set_errors(&mut self, errors: &str) -> PyResult<()> {
+        self.errors = errors.parse()?;
+        Ok(())
+    }
+
+    #[getter(duplicate_policy)]
+    fn py_duplicate_policy(&self) -> &'static str {
+        self.duplicate_policy.as_str()
+    }
+
+    #[setter(duplicate_policy)]
+    fn py_set_duplicate_policy(&mut self, policy: &str) -> PyResult<()> {
+        self.duplicate_policy = policy.parse()?;
+        Ok(())
+    }
+
+    #[getter(parse_path)]
+    fn py_parse_path(&self) -> &'static str {
+        self.parse_path.as_str()
+    }
+
+    #[setter(parse_path)]
+    fn py_set_parse_path(&mut self, path: &str) -> PyResult<()> {
+        self.parse_path = path.parse()?;
+        Ok(())
+    }
+
+    #[getter(element_case)]
+    fn py_element_case(&self) -> &'static str {
+        self.element_case.as_str()
+    }
+
+    #[setter(element_case)]
+    fn py_set_element_case(&mut self, case: &str) -> PyResult<()> {
+        self.element_case = case.parse()?;
+        Ok(())
+    }
+
+    #[getter(attr_case)]
+    fn py_attr_case(&self) -> &'static str {
+        self.attr_case.as_str()
+    }
+
+    #[setter(attr_case)]
+    fn py_set_attr_case(&mut self, case: &str) -> PyResult<()> {
+        self.attr_case = case.parse()?;
+        Ok(())
+    }
+
+    #[getter(empty_element_value)]
+    fn py_empty_element_value(&self) -> &'static str {
+        self.empty_element_value.as_str()
+    }
+
+    #[setter(empty_element_value)]
+    fn py_set_empty_element_value(&mut self, value: &str) -> PyResult<()> {
+        self.empty_element_value = value.parse()?;
+        Ok(())
+    }
+
+    #[getter(attr_preserve_pattern)]
+    fn py_attr_preserve_pattern(&self) -> Option<&str> {
+        self.attr_preserve_pattern
+            .as_ref()
+            .map(regex::Regex::as_str)
+    }
+
+    #[setter(attr_preserve_pattern)]
+    fn py_set_attr_preserve_pattern(&mut self, pattern: Option<&str>) -> PyResult<()> {
+        self.attr_preserve_pattern = pattern.map(compile_pattern).transpose()?;
+        Ok(())
+    }
+
+    #[getter(whitespace_mode)]
+    fn py_whitespace_mode(&self) -> Option<&'static str> {
+        self.whitespace_mode.map(WhitespaceMode::as_str)
+    }
+
+    #[setter(whitespace_mode)]
+    fn py_set_whitespace_mode(&mut self, mode: Option<&str>) -> PyResult<()> {
+        self.whitespace_mode = mode.map(str::parse).transpose()?;
+        Ok(())
+    }
+
+    #[setter(force_list)]
+    fn py_set_force_list(&mut self, names: &Bound<'_, PyAny>) -> PyResult<()> {
+        self.force_list = extract_names(names)?;
+        Ok(())
+    }
+
+    #[setter(allowed_tags)]
+    fn py_set_allowed_tags(&mut self, names: Option<&Bound<'_, PyAny>>) -> PyResult<()> {
+        self.allowed_tags = names.map(extract_names).transpose()?;
+        Ok(())
+    }
+
+    #[setter(allowed_attributes)]
+    fn py_set_allowed_attributes(&mut self, names: Option<&Bound<'_, PyAny>>) -> PyResult<()> {
+        self.allowed_attributes = names.map(extract_names).transpose()?;
+        Ok(())
+    }
+}
+
+options_struct! {
+    /// Options controlling how `value_to_xml` writes a `Value` tree as XML.
+    ///
+    /// Exposed to Python as `openspeleo_core.SerializeOptions`, accepted through
+    /// the `options` argument of the serialization functions.
+    #[derive(Clone, Debug)]
+    pub struct SerializeOptions {
+        /// Quote character wrapping attribute values: `"` (default) or `'`.
+        #[pyo3(get, set)]
+        pub attr_quote: char,
+        /// Accept Python sets whose elements cannot be sorted (e.g. mixing `int`
+        /// and `str`), writing them in iteration order. By default set elements
+        /// are sorted so that the output is reproducible, and unorderable sets
+        /// are rejected.
+        #[pyo3(get, set)]
+        pub allow_unordered_sets: bool,
+        /// When set, an array under key `k` is written as a single `<k>` element
+        /// wrapping one element of this name per item, instead of one `<k>`
+        /// element per item: `{"list": [1, 2]}` with `"item"` becomes
+        /// `<list><item>1</item><item>2</item></list>`.
+        #[pyo3(get, set)]
+        pub array_item_name: Option<String>,
+        /// Child element order per parent element name, e.g. from an XSD
+        /// sequence: `{"shot": ["from", "to", "length"]}`. Children missing from
+        /// the list of their parent are written after the listed ones, in their
+        /// original order.
+        #[pyo3(get, set)]
+        pub element_order: HashMap<String, Vec<String>>,
+        /// Write each element and comment on its own line, indented by
+        /// `indent_size` `indent_char` per nesting level. Elements holding text
+        /// stay on one line, comments and children included, since indentation
+        /// would add to their text.
+        #[pyo3(get, set)]
+        pub pretty: bool,
+        /// Indentation character of `pretty` output: a space (default) or any
+        /// other XML whitespace character, e.g. `\t`.
+        #[pyo3(get, set)]
+        pub indent_char: char,
+        /// Number of `indent_char` per nesting level of `pretty` output.
+        #[pyo3(get, set)]
+        pub indent_size: usize,
+        /// Handling of characters forbidden by XML 1.0 in text and attribute
+        /// values, `"reject"`, `"strip"` or `"numeric-escape"` from Python.
+        pub control_char_policy: ControlCharPolicy,
+        /// Number of bytes the streaming serializers (`value_to_xml_writer`,
+        /// `dict_to_xml_file`) buffer before handing them to their sink.
+        #[pyo3(get, set)]
+        pub flush_bytes: usize,
+        /// Write child elements sorted by name, for parents without an
+        /// `element_order`, rather than in the order of their keys. Repeated
+        /// elements keep their relative order.
+        #[pyo3(get, set)]
+        pub sort_keys: bool,
+        /// Write attributes sorted by name, after any namespace declarations,
+        /// rather than in the order of their keys.
+        #[pyo3(get, set)]
+        pub sort_attributes: bool,
+        /// Line break of `pretty` output: `"\n"` (default), `"\r\n"` or `"\r"`.
+        /// Line breaks inside text are written as they are.
+        #[pyo3(get, set)]
+        pub line_ending: String,
+        /// Processing instructions written as `(target, data)` between the XML
+        /// declaration and the root element, e.g.
+        /// `("xml-stylesheet", "type=\"text/xsl\" href=\"style.xsl\"")`.
+        #[pyo3(get, set)]
+        pub processing_instructions: Vec<(String, String)>,
+        /// Deepest nesting of lists, dicts and other containers accepted when
+        /// converting Python objects, which also rejects containers holding
+        /// themselves before the stack overflows.
+        #[pyo3(get, set)]
+        pub max_depth: usize,
+        /// Write every element in a fixed order, for output that can be compared
+        /// byte for byte (e.g. signed documents): namespace declarations sorted by
+        /// prefix, the default namespace first, then the other attributes sorted
+        /// by name, then text and children in the order of their keys, which is
+        /// document order for values read by `parse_xml`. `sort_keys` and
+        /// `element_order` are ignored.
+        #[pyo3(get, set)]
+        pub canonical: bool,
+        /// Read `child@attr` keys as the attribute `attr` of the `child` element,
+        /// allowing `{"length": 5, "length@unit": "m"}` in place of
+        /// `{"length": {"#text": 5, "@unit": "m"}}`.
+        #[pyo3(get, set)]
+        pub flat_attributes: bool,
+        /// Write attributes set to `None`/`null` with an empty value (`id=""`)
+        /// instead of omitting them, the counterpart of
+        /// `ParseOptions::empty_attr_as_none`.
+        #[pyo3(get, set)]
+        pub none_attr_as_empty: bool,
+        /// When set, each element written for an array item gets an attribute of
+        /// this name holding its 0-based position in the array, so that the order
+        /// can be restored with `ParseOptions::honor_index` after going through
+        /// tools that reorder elements.
+        #[pyo3(get, set)]
+        pub index_attribute: Option<String>,
+        /// Handling of NaN and infinite floats, `"error"`, `"string"` or
+        /// `"null"` from Python.
+        pub nonfinite_floats: NonFiniteFloats,
+        /// Content of a `<!DOCTYPE ...>` written between the XML declaration and
+        /// the processing instructions, e.g. `"survey SYSTEM \"survey.dtd\""`.
+        /// Takes precedence over the `#doctype` key of `document_to_xml`.
+        #[pyo3(get, set)]
+        pub doctype: Option<String>,
+        /// Namespaces declared on the root element, mapping each prefix to its
+        /// URI, `""` being the default namespace. Element and attribute names
+        /// can then use the prefixes (`soap:Body`), and a prefix declared
+        /// neither here nor by an `@xmlns` key in scope is an error. Declarations
+        /// already on the root element take precedence.
+        #[pyo3(get, set)]
+        pub namespaces: HashMap<String, String>,
+    }
+}
+
+impl Default for SerializeOptions {
+    fn default() -> Self {
+        SerializeOptions {
+            attr_quote: '"',
+            allow_unordered_sets: false,
+            array_item_name: None,
+            element_order: HashMap::new(),
+            pretty: false,
+            indent_char: ' ',
+            indent_size: 2,
+            control_char_policy: ControlCharPolicy::default(),
+            flush_bytes: 64 * 1024,
+            sort_keys: false,
+            sort_attributes: false,
+            line_ending: "\n".to_string(),
+            processing_instructions: Vec::new(),
+            max_depth: 512,
+            canonical: false,
+            flat_attributes: false,
+            none_attr_as_empty: false,
+            index_attribute: None,
+            nonfinite_floats: NonFiniteFloats::default(),
+            doctype: None,
+            namespaces: HashMap::new(),
+        }
+    }
+}
+
+impl SerializeOptions {
+    /// Checks that the options describe a writable configuration.
+    pub fn validate(&self) -> Result<(), XmlDictError> {
+        if !matches!(self.attr_quote, '"' | '\'') {
+            return Err(XmlDictError::InvalidOption(format!(
+                "attr_quote must be '\"' or \"'\", got {:?}",
+                self.attr_quote
+            )));
+        }
+        if !matches!(self.indent_char, ' ' | '\t' | '\r' | '\n') {
+            return Err(XmlDictError::InvalidOption(format!(
+                "indent_char must be a whitespace character, got {:?}",
+                self.indent_char
+            )));
+        }
+        if !matches!(self.line_ending.as_str(), "\n" | "\r\n" | "\r") {
+            return Err(XmlDictError::InvalidOption(format!(
+                "line_ending must be \"\\n\", \"\\r\\n\" or \"\\r\", got {:?}",
+                self.line_ending
+            )));
+        }
+        for (target, data) in &self.processing_instructions {
+            if target.is_empty()
+                || target.contains(|c: char| c.is_whitespace() || c == '?')
+                || target.eq_ignore_ascii_case("xml")
+            {
+                return Err(XmlDictError::InvalidOption(format!(
+                    "Invalid processing instruction target {:?}",
+                    target
+                )));
+            }
+            if data.contains("?>") {
+                return Err(XmlDictError::InvalidOption(format!(
+                    "Processing instruction data {:?} cannot contain \"?>\"",
+                    data
+                )));
+            }
+        }
+        if let Some(doctype) = &self.doctype {
+            validate_doctype(doctype)?;
+        }
+        for prefix in self.namespaces.keys() {
+            let reserved = prefix.eq_ignore_ascii_case("xml") || prefix == "xmlns";
+            if reserved || prefix.contains(|c: char| c.is_whitespace() || c == ':') {
+                return Err(XmlDictError::InvalidOption(format!(
+                    "Invalid namespace prefix {:?}",
+                    prefix
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that `doctype` can be written as the content of `<!DOCTYPE ...>`:
+/// it starts with the root element name and its internal subset, if any, is
+/// closed.
+pub(crate) fn validate_doctype(doctype: &str) -> Result<(), XmlDictError> {
+    let starts_with_name = doctype
+        .chars()
+        .next()
+        .is_some_and(|c| !c.is_whitespace() && !matches!(c, '[' | '>' | '<'));
+    let subset_closed = match doctype.find('[') {
+        Some(open) => doctype.trim_end().ends_with(']') && doctype.rfind(']') > Some(open),
+        None => !doctype.contains(['>', ']']),
+    };
+    if !starts_with_name || !subset_closed {
+        return Err(XmlDictError::InvalidOption(format!(
+            "Invalid DOCTYPE {:?}",
+            doctype
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl SerializeOptions {
+    #[new]
+    #[pyo3(signature = (
+        attr_quote='"',
+        allow_unordered_sets=false,
+        array_item_name=None,
+        element_order=HashMap::new(),
+        pretty=false,
+        indent_char=' ',
+        indent_size=2,
+        control_char_policy="reject",
+        flush_bytes=64 * 1024,
+        sort_keys=false,
+        sort_attributes=false,
+        line_ending="\n".to_string(),
+        processing_instructions=Vec::new(),
+        max_depth=512,
+        canonical=false,
+        flat_attributes=false,
+        none_attr_as_empty=false,
+        index_attribute=None,
+        nonfinite_floats="error",
+        doctype=None,
+        namespaces=HashMap::new(),
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        attr_quote: char,
+        allow_unordered_sets: bool,
+        array_item_name: Option<String>,
+        element_order: HashMap<String, Vec<String>>,
+        pretty: bool,
+        indent_char: char,
+        indent_size: usize,
+        control_char_policy: &str,
+        flush_bytes: usize,
+        sort_keys: bool,
+        sort_attributes: bool,
+        line_ending: String,
+        processing_instructions: Vec<(String, String)>,
+        max_depth: usize,
+        canonical: bool,
+        flat_attributes: bool,
+        none_attr_as_empty: bool,
+        index_attribute: Option<String>,
+        nonfinite_floats: &str,
+        doctype: Option<String>,
+        namespaces: HashMap<String, String>,
+    ) -> PyResult<Self> {
+        let options = SerializeOptions {
+            attr_quote,
+            allow_unordered_sets,
+            array_item_name,
+            element_order,
+            pretty,
+            indent_char,
+            indent_size,
+            control_char_policy: control_char_policy.parse()?,
+            flush_bytes,
+            sort_keys,
+            sort_attributes,
+            line_ending,
+            processing_instructions,
+            max_depth,
+            canonical,
+            flat_attributes,
+            none_attr_as_empty,
+            index_attribute,
+            nonfinite_floats: nonfinite_floats.parse()?,
+            doctype,
+            namespaces,
+        };
+        options.validate()?;
+        Ok(options)
+    }
+
+    #[getter(control_char_policy)]
+    fn py_control_char_policy(&self) -> &'static str {
+        self.control_char_policy.as_str()
+    }
+
+    #[setter(control_char_policy)]
+    fn py_set_control_char_policy(&mut self, policy: &str) -> PyResult<()> {
+        self.control_char_policy = policy.parse()?;
+        Ok(())
+    }
+
+    #[getter(nonfinite_floats)]
+    fn py_nonfinite_floats(&self) -> &'static str {
+        self.nonfinite_floats.as_str()
+    }
+
+    #[setter(nonfinite_floats)]
+    fn py_set_nonfinite_floats(&mut self, policy: &str) -> PyResult<()> {
+        self.nonfinite_floats = policy.parse()?;
+        Ok(())
+    }
+}
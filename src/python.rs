@@ -0,0 +1,1076 @@
+//! Python bindings, built with the `python` feature.
+
+use base64::Engine;
+use pyo3::{
+    buffer::PyBuffer,
+    exceptions::{PyBufferError, PyValueError},
+    prelude::*,
+    types::{
+        PyBool, PyByteArray, PyBytes, PyDict, PyFloat, PyFrozenSet, PyInt, PyList, PySequence,
+        PySet, PyString, PyTuple, PyType,
+    },
+};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    document_to_xml, extract_path, flat_to_xml, is_well_formed, parse_elements, parse_error,
+    parse_str, parse_subtree, parse_xml, parse_xml_bytes, scalar_text, validate_well_formed,
+    value_to_xml_bytes, value_to_xml_document, value_to_xml_writer, xml_diff, xml_find_all,
+    xml_project, xml_to_flat, NonFiniteFloats, ParseOptions, RecordWriter, SerializeOptions,
+    XmlDictError, XmlParser,
+};
+
+mod exceptions {
+    pyo3::create_exception!(
+        openspeleo_core,
+        XmlDictError,
+        pyo3::exceptions::PyValueError
+    );
+}
+
+impl From<XmlDictError> for PyErr {
+    fn from(err: XmlDictError) -> PyErr {
+        exceptions::XmlDictError::new_err(err.to_string())
+    }
+}
+
+// Explicit keyword arguments override the fields of `options`
+fn build_parse_options(
+    options: Option<PyRef<'_, ParseOptions>>,
+    keep_null: Option<bool>,
+    force_list: Option<&Bound<'_, PyAny>>,
+    auto_list: Option<bool>,
+    ordered_content: Option<bool>,
+    skip_empty: Option<bool>,
+) -> PyResult<ParseOptions> {
+    let mut resolved = options.map(|o| o.clone()).unwrap_or_default();
+    if let Some(keep_null) = keep_null {
+        resolved.keep_null = keep_null;
+    }
+    if let Some(force_list) = force_list {
+        resolved.force_list = extract_names(force_list)?;
+    }
+    if let Some(auto_list) = auto_list {
+        resolved.auto_list = auto_list;
+    }
+    if let Some(ordered_content) = ordered_content {
+        resolved.ordered_content = ordered_content;
+    }
+    if let Some(skip_empty) = skip_empty {
+        resolved.skip_empty = skip_empty;
+    }
+    Ok(resolved)
+}
+
+// Explicit keyword arguments override the fields of `options`
+fn build_serialize_options(
+    options: Option<PyRef<'_, SerializeOptions>>,
+    attr_quote: Option<char>,
+    array_item_name: Option<String>,
+    sort_keys: Option<bool>,
+    processing_instructions: Option<Vec<(String, String)>>,
+    doctype: Option<String>,
+    namespaces: Option<HashMap<String, String>>,
+) -> SerializeOptions {
+    let mut resolved = options.map(|o| o.clone()).unwrap_or_default();
+    if let Some(namespaces) = namespaces {
+        resolved.namespaces = namespaces;
+    }
+    if doctype.is_some() {
+        resolved.doctype = doctype;
+    }
+    if let Some(processing_instructions) = processing_instructions {
+        resolved.processing_instructions = processing_instructions;
+    }
+    if let Some(sort_keys) = sort_keys {
+        resolved.sort_keys = sort_keys;
+    }
+    if let Some(attr_quote) = attr_quote {
+        resolved.attr_quote = attr_quote;
+    }
+    if array_item_name.is_some() {
+        resolved.array_item_name = array_item_name;
+    }
+    resolved
+}
+
+#[pyfunction]
+#[pyo3(signature = (
+    xml_str,
+    keep_null=None,
+    force_list=None,
+    auto_list=None,
+    ordered_content=None,
+    skip_empty=None,
+    return_stats=false,
+    options=None,
+    key_transform=None,
+    collect_errors=false,
+))]
+#[allow(clippy::too_many_arguments)]
+fn xml_str_to_dict(
+    py: Python<'_>,
+    xml_str: &str,
+    keep_null: Option<bool>,
+    force_list: Option<&Bound<'_, PyAny>>,
+    auto_list: Option<bool>,
+    ordered_content: Option<bool>,
+    skip_empty: Option<bool>,
+    return_stats: bool,
+    options: Option<PyRef<'_, ParseOptions>>,
+    key_transform: Option<&Bound<'_, PyAny>>,
+    collect_errors: bool,
+) -> PyResult<PyObject> {
+    let options = build_parse_options(
+        options,
+        keep_null,
+        force_list,
+        auto_list,
+        ordered_content,
+        skip_empty,
+    )?;
+    // The parse only touches Rust data, other Python threads may run meanwhile
+    let mut issues = Vec::new();
+    let (value, stats) = py.allow_threads(|| {
+        let issues = collect_errors.then_some(&mut issues);
+        parse_str(xml_str, &options, &mut Vec::new(), &mut Vec::new(), issues)
+    })?;
+    let data = parsed_to_pyobject(&value, py, &options, key_transform)?;
+    if !return_stats && !collect_errors {
+        return Ok(data);
+    }
+    // Extra results follow the dict in the order of their flags
+    let mut result = vec![data];
+    if return_stats {
+        let py_stats = PyDict::new(py);
+        py_stats.set_item("elements", stats.elements)?;
+        py_stats.set_item("attributes", stats.attributes)?;
+        py_stats.set_item("max_depth", stats.max_depth)?;
+        py_stats.set_item("text_bytes", stats.text_bytes)?;
+        result.push(py_stats.into_any().unbind());
+    }
+    if collect_errors {
+        let errors: Vec<(u64, String)> = issues
+            .into_iter()
+            .map(|issue| (issue.position, issue.message))
+            .collect();
+        result.push(errors.into_pyobject(py)?.into_any().unbind());
+    }
+    Ok(PyTuple::new(py, result)?.into_any().unbind())
+}
+
+/// Python binding of `parse_elements`, offsets being UTF-8 byte offsets.
+#[pyfunction]
+#[pyo3(signature = (xml_str, offset, max_elements, options=None))]
+fn xml_elements_to_dict(
+    py: Python<'_>,
+    xml_str: &str,
+    offset: usize,
+    max_elements: usize,
+    options: Option<PyRef<'_, ParseOptions>>,
+) -> PyResult<(PyObject, Option<usize>)> {
+    let options = options.map(|o| o.clone()).unwrap_or_default();
+    let (value, next) =
+        py.allow_threads(|| parse_elements(xml_str, offset, max_elements, &options))?;
+    Ok((parsed_to_pyobject(&value, py, &options, None)?, next))
+}
+
+/// Parses XML from any object exporting a contiguous byte buffer (`bytes`,
+/// `bytearray`, `memoryview`, `mmap`, ...) without copying it.
+#[pyfunction]
+#[pyo3(signature = (buf, options=None))]
+fn xml_buffer_to_dict(
+    py: Python<'_>,
+    buf: PyBuffer<u8>,
+    options: Option<PyRef<'_, ParseOptions>>,
+) -> PyResult<PyObject> {
+    if !buf.is_c_contiguous() {
+        return Err(PyBufferError::new_err("XML buffer must be contiguous"));
+    }
+    let options = options.map(|o| o.clone()).unwrap_or_default();
+    // SAFETY: the buffer is contiguous and `buf` keeps it exported, so it
+    // stays allocated for the lifetime of the slice
+    let xml = unsafe { std::slice::from_raw_parts(buf.buf_ptr() as *const u8, buf.len_bytes()) };
+    // Other threads could write to a mutable buffer, only release the GIL
+    // for read-only ones
+    let value = if buf.readonly() {
+        py.allow_threads(|| parse_xml_bytes(xml, &options))?
+    } else {
+        parse_xml_bytes(xml, &options)?
+    };
+    parsed_to_pyobject(&value, py, &options, None)
+}
+
+/// A `dict` holding an element's text and attributes, returned for such
+/// elements with `ParseOptions(value_with_attrs=True)`.
+///
+/// It keeps the usual `{"#text": ..., "@attr": ...}` content, so it compares
+/// equal to and serializes like a plain `dict`, and adds the `value` and
+/// `attrs` accessors: `<length unit="m">5</length>` gives `value == "5"` and
+/// `attrs == {"unit": "m"}`.
+#[pyclass(extends = PyDict, module = "openspeleo_core")]
+struct ValueWithAttrs {
+    text_key: String,
+}
+
+#[pymethods]
+impl ValueWithAttrs {
+    /// The element's text content.
+    #[getter]
+    fn value<'py>(slf: &Bound<'py, Self>) -> PyResult<Option<Bound<'py, PyAny>>> {
+        let key = slf.borrow().text_key.clone();
+        slf.as_super().get_item(key)
+    }
+
+    /// The element's attributes, without the `@` prefix.
+    #[getter]
+    fn attrs<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyDict>> {
+        let attrs = PyDict::new(slf.py());
+        for (k, v) in slf.as_super().iter() {
+            if let Some(name) = k.extract::<String>()?.strip_prefix('@') {
+                attrs.set_item(name, v)?;
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+/// Reusable parser keeping its options and buffers between `parse` calls.
+#[pyclass(name = "XmlParser", module = "openspeleo_core")]
+struct PyXmlParser {
+    inner: XmlParser,
+}
+
+#[pymethods]
+impl PyXmlParser {
+    #[new]
+    #[pyo3(signature = (
+        keep_null=None,
+        force_list=None,
+        auto_list=None,
+        ordered_content=None,
+        options=None,
+    ))]
+    fn new(
+        keep_null: Option<bool>,
+        force_list: Option<&Bound<'_, PyAny>>,
+        auto_list: Option<bool>,
+        ordered_content: Option<bool>,
+        options: Option<PyRef<'_, ParseOptions>>,
+    ) -> PyResult<Self> {
+        // Explicit keyword arguments override the matching `options` fields
+        let options = build_parse_options(
+            options,
+            keep_null,
+            force_list,
+            auto_list,
+            ordered_content,
+            None,
+        )?;
+        Ok(PyXmlParser {
+            inner: XmlParser::new(options),
+        })
+    }
+
+    /// A copy of the options shared by all `parse` calls.
+    #[getter]
+    fn options(&self) -> ParseOptions {
+        self.inner.options().clone()
+    }
+
+    fn parse(&mut self, py: Python<'_>, xml_str: &str) -> PyResult<PyObject> {
+        let inner = &mut self.inner;
+        let value = py.allow_threads(|| inner.parse(xml_str))?;
+        parsed_to_pyobject(&value, py, self.inner.options(), None)
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (
+    data,
+    root_name,
+    attr_quote=None,
+    array_item_name=None,
+    options=None,
+    sort_keys=None,
+    default=None,
+    processing_instructions=None,
+    doctype=None,
+    namespaces=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn dict_to_xml_str(
+    py: Python<'_>,
+    data: &Bound<'_, PyDict>,
+    root_name: &str,
+    attr_quote: Option<char>,
+    array_item_name: Option<String>,
+    options: Option<PyRef<'_, SerializeOptions>>,
+    sort_keys: Option<bool>,
+    default: Option<&Bound<'_, PyAny>>,
+    processing_instructions: Option<Vec<(String, String)>>,
+    doctype: Option<String>,
+    namespaces: Option<HashMap<String, String>>,
+) -> PyResult<String> {
+    // Only the conversion to `Value` reads Python objects; writing is pure Rust
+    let options = build_serialize_options(
+        options,
+        attr_quote,
+        array_item_name,
+        sort_keys,
+        processing_instructions,
+        doctype,
+        namespaces,
+    );
+    let value = pyobject_to_value(data, &options, default)?;
+    Ok(py.allow_threads(|| value_to_xml_document(&value, root_name, "utf-8", &options))?)
+}
+
+#[pyfunction]
+#[pyo3(signature = (
+    data,
+    root_name,
+    encoding="utf-8",
+    attr_quote=None,
+    array_item_name=None,
+    options=None,
+    sort_keys=None,
+    default=None,
+    processing_instructions=None,
+    doctype=None,
+    namespaces=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn dict_to_xml_bytes<'py>(
+    data: &Bound<'py, PyDict>,
+    root_name: &str,
+    encoding: &str,
+    attr_quote: Option<char>,
+    array_item_name: Option<String>,
+    options: Option<PyRef<'py, SerializeOptions>>,
+    sort_keys: Option<bool>,
+    default: Option<&Bound<'py, PyAny>>,
+    processing_instructions: Option<Vec<(String, String)>>,
+    doctype: Option<String>,
+    namespaces: Option<HashMap<String, String>>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let py = data.py();
+    let options = build_serialize_options(
+        options,
+        attr_quote,
+        array_item_name,
+        sort_keys,
+        processing_instructions,
+        doctype,
+        namespaces,
+    );
+    let value = pyobject_to_value(data, &options, default)?;
+    let bytes = py.allow_threads(|| value_to_xml_bytes(&value, root_name, encoding, &options))?;
+    Ok(PyBytes::new(py, &bytes))
+}
+
+#[pyfunction]
+#[pyo3(signature = (data, root_name, path, options=None, default=None))]
+fn dict_to_xml_file(
+    py: Python<'_>,
+    data: &Bound<'_, PyDict>,
+    root_name: &str,
+    path: std::path::PathBuf,
+    options: Option<PyRef<'_, SerializeOptions>>,
+    default: Option<&Bound<'_, PyAny>>,
+) -> PyResult<()> {
+    let options = build_serialize_options(options, None, None, None, None, None, None);
+    let value = pyobject_to_value(data, &options, default)?;
+    let file = std::fs::File::create(&path).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to create file: {}", e))
+    })?;
+    Ok(py.allow_threads(|| value_to_xml_writer(&value, root_name, file, &options))?)
+}
+
+/// Writes the dicts yielded by `records` to `path` as `item_name` elements of
+/// a `root_name` root, converting and writing them one at a time.
+#[pyfunction]
+#[pyo3(signature = (records, root_name, item_name, path, options=None, default=None))]
+fn records_to_xml_file(
+    records: &Bound<'_, PyAny>,
+    root_name: &str,
+    item_name: &str,
+    path: std::path::PathBuf,
+    options: Option<PyRef<'_, SerializeOptions>>,
+    default: Option<&Bound<'_, PyAny>>,
+) -> PyResult<()> {
+    let options = build_serialize_options(options, None, None, None, None, None, None);
+    let file = std::fs::File::create(&path).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to create file: {}", e))
+    })?;
+    let mut writer = RecordWriter::new(file, root_name, item_name, &options)?;
+    for record in records.try_iter()? {
+        writer.write(&pyobject_to_value(&record?, &options, default)?)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[pyfunction]
+#[pyo3(signature = (document, options=None, default=None))]
+fn document_to_xml_str(
+    py: Python<'_>,
+    document: &Bound<'_, PyDict>,
+    options: Option<PyRef<'_, SerializeOptions>>,
+    default: Option<&Bound<'_, PyAny>>,
+) -> PyResult<String> {
+    let options = build_serialize_options(options, None, None, None, None, None, None);
+    let value = pyobject_to_value(document, &options, default)?;
+    Ok(py.allow_threads(|| document_to_xml(&value, &options))?)
+}
+
+#[pyfunction]
+#[pyo3(name = "is_well_formed")]
+fn py_is_well_formed(py: Python<'_>, xml_str: &str) -> bool {
+    py.allow_threads(|| is_well_formed(xml_str))
+}
+
+/// Returns the first well-formedness error of `xml_str`, or `None`.
+#[pyfunction]
+#[pyo3(name = "validate_well_formed")]
+fn py_validate_well_formed(py: Python<'_>, xml_str: &str) -> Option<String> {
+    py.allow_threads(|| validate_well_formed(xml_str).err().map(|e| e.to_string()))
+}
+
+/// Calls `callback` for each event of `xml_str` without building a dict.
+///
+/// Events are tuples: `("start", name, attributes)` with the attributes as a
+/// `dict`, `("text", text)` and `("end", name)`. Self-closing elements produce
+/// a `start` immediately followed by an `end`. An exception raised by the
+/// callback stops the parse and is propagated.
+#[pyfunction]
+fn parse_events(py: Python<'_>, xml_str: &str, callback: &Bound<'_, PyAny>) -> PyResult<()> {
+    let mut reader = Reader::from_str(xml_str);
+    reader.config_mut().trim_text(true);
+
+    loop {
+        let (e, is_empty) = match reader.read_event().map_err(|e| parse_error(&reader, e))? {
+            Event::Start(e) => (e, false),
+            Event::Empty(e) => (e, true),
+            Event::Text(e) => {
+                let text = e.unescape().map_err(|err| parse_error(&reader, err))?;
+                callback.call1((("text", text),))?;
+                continue;
+            }
+            Event::End(e) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                callback.call1((("end", name),))?;
+                continue;
+            }
+            Event::Eof => return Ok(()),
+            _ => continue,
+        };
+
+        let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+        let attrs = PyDict::new(py);
+        for attr in e.attributes() {
+            let attr = attr.map_err(|err| parse_error(&reader, err.into()))?;
+            let value = attr
+                .unescape_value()
+                .map_err(|err| parse_error(&reader, err))?;
+            attrs.set_item(String::from_utf8_lossy(attr.key.as_ref()), value)?;
+        }
+        callback.call1((("start", name.as_str(), attrs),))?;
+        if is_empty {
+            callback.call1((("end", name),))?;
+        }
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (xml_str, tag, options=None))]
+fn xml_subtree_to_dict(
+    py: Python<'_>,
+    xml_str: &str,
+    tag: &str,
+    options: Option<PyRef<'_, ParseOptions>>,
+) -> PyResult<PyObject> {
+    let options = options.map(|o| o.clone()).unwrap_or_default();
+    let value = py.allow_threads(|| parse_subtree(xml_str, tag, &options))?;
+    match value {
+        Some(value) => parsed_to_pyobject(&value, py, &options, None),
+        None => Ok(py.None()),
+    }
+}
+
+/// Returns the differences between two documents as `(path, left, right)`
+/// tuples, `left`/`right` being `None` where the path is missing.
+#[pyfunction]
+#[pyo3(name = "xml_diff", signature = (a_xml, b_xml, options=None))]
+fn py_xml_diff(
+    py: Python<'_>,
+    a_xml: &str,
+    b_xml: &str,
+    options: Option<PyRef<'_, ParseOptions>>,
+) -> PyResult<Vec<(String, PyObject, PyObject)>> {
+    let options = options.map(|o| o.clone()).unwrap_or_default();
+    let differences = py.allow_threads(|| xml_diff(a_xml, b_xml, &options))?;
+    let convert = |value: Option<Value>| match value {
+        Some(value) => value_to_pyobject(&value, py),
+        None => Ok(py.None()),
+    };
+    differences
+        .into_iter()
+        .map(|d| Ok((d.path, convert(d.left)?, convert(d.right)?)))
+        .collect()
+}
+
+/// Parses `xml_str` into a flat `{dotted.path: scalar}` dict, see `flatten`.
+#[pyfunction]
+#[pyo3(signature = (xml_str, options=None))]
+fn xml_str_to_flat(
+    py: Python<'_>,
+    xml_str: &str,
+    options: Option<PyRef<'_, ParseOptions>>,
+) -> PyResult<PyObject> {
+    let options = options.map(|o| o.clone()).unwrap_or_default();
+    let flat = py.allow_threads(|| xml_to_flat(xml_str, &options))?;
+    value_to_pyobject(&Value::Object(flat), py)
+}
+
+/// Serializes a flat `{dotted.path: scalar}` dict, see `unflatten`.
+#[pyfunction]
+#[pyo3(signature = (flat_dict, options=None))]
+fn flat_to_xml_str(
+    py: Python<'_>,
+    flat_dict: &Bound<'_, PyDict>,
+    options: Option<PyRef<'_, SerializeOptions>>,
+) -> PyResult<String> {
+    let options = build_serialize_options(options, None, None, None, None, None, None);
+    let Value::Object(flat) = pyobject_to_value(flat_dict, &options, None)? else {
+        unreachable!("dicts convert to objects")
+    };
+    Ok(py.allow_threads(|| flat_to_xml(&flat, &options))?)
+}
+
+#[pyfunction]
+fn xml_extract(py: Python<'_>, xml_str: &str, path: &str) -> PyResult<PyObject> {
+    let value = py.allow_threads(|| extract_path(xml_str, path, &ParseOptions::default()))?;
+    match value {
+        Some(value) => value_to_pyobject(&value, py),
+        None => Ok(py.None()),
+    }
+}
+
+/// Returns the list of all the values of `xml_str` matching `path`, see
+/// `find_all`.
+#[pyfunction]
+#[pyo3(name = "xml_find_all", signature = (xml_str, path, options=None))]
+fn py_xml_find_all(
+    py: Python<'_>,
+    xml_str: &str,
+    path: &str,
+    options: Option<PyRef<'_, ParseOptions>>,
+) -> PyResult<Vec<PyObject>> {
+    let options = options.map(|o| o.clone()).unwrap_or_default();
+    let values = py.allow_threads(|| xml_find_all(xml_str, path, &options))?;
+    values
+        .iter()
+        .map(|value| parsed_to_pyobject(value, py, &options, None))
+        .collect()
+}
+
+/// Returns a dict mapping each key of `schema` to the value of `xml_str` its
+/// path selects, see `xml_project`.
+#[pyfunction]
+#[pyo3(name = "xml_project", signature = (xml_str, schema, options=None))]
+fn py_xml_project(
+    py: Python<'_>,
+    xml_str: &str,
+    schema: HashMap<String, String>,
+    options: Option<PyRef<'_, ParseOptions>>,
+) -> PyResult<PyObject> {
+    let options = options.map(|o| o.clone()).unwrap_or_default();
+    let projected = py.allow_threads(|| xml_project(xml_str, &schema, &options))?;
+    parsed_to_pyobject(&Value::Object(projected), py, &options, None)
+}
+
+// Updated helper functions for Python/Rust type conversion
+
+// Collects any iterable of `str` (list, tuple, set, ...) into a set of names
+pub(crate) fn extract_names(obj: &Bound<'_, PyAny>) -> PyResult<HashSet<String>> {
+    obj.try_iter()?
+        .map(|item| item?.extract::<String>())
+        .collect()
+}
+
+// Integers map to `int` (arbitrarily large ones included) and finite floats
+// to `float`; a number that fits neither is returned as its exact text rather
+// than rounded or rejected
+fn number_to_pyobject(num: &serde_json::Number, py: Python<'_>, exact: bool) -> PyResult<PyObject> {
+    if exact && num.to_string().contains(['.', 'e', 'E']) {
+        let decimal = DECIMAL.import(py, "decimal", "Decimal")?;
+        return Ok(decimal.call1((num.to_string(),))?.unbind());
+    }
+    if let Some(i) = num.as_i64() {
+        return Ok(i.into_pyobject(py)?.into_any().unbind());
+    }
+    if let Some(u) = num.as_u64() {
+        return Ok(u.into_pyobject(py)?.into_any().unbind());
+    }
+    if let (true, Some(f)) = (num.is_f64(), num.as_f64()) {
+        return Ok(PyFloat::new(py, f).into());
+    }
+
+    let text = num.to_string();
+    if text.contains(['.', 'e', 'E']) {
+        Ok(PyString::new(py, &text).into())
+    } else {
+        Ok(py.get_type::<PyInt>().call1((text,))?.unbind())
+    }
+}
+
+// Function to handle conversion of serde_json::Value
+fn value_to_pyobject(value: &Value, py: Python<'_>) -> PyResult<PyObject> {
+    convert_value(value, py, &mut Conversion::default())
+}
+
+// Converts the result of a parse, honouring the Python-only options
+fn parsed_to_pyobject(
+    value: &Value,
+    py: Python<'_>,
+    options: &ParseOptions,
+    key_transform: Option<&Bound<'_, PyAny>>,
+) -> PyResult<PyObject> {
+    let mut conversion = Conversion {
+        text_key: options
+            .value_with_attrs
+            .then_some(options.text_key.as_str()),
+        key_transform,
+        intern_names: options.intern_names,
+        exact_numbers: options.exact_numbers,
+        base64_text_key: options.decode_base64.then_some(options.text_key.as_str()),
+        keys: HashMap::new(),
+    };
+    convert_value(value, py, &mut conversion)
+}
+
+#[derive(Default)]
+struct Conversion<'a, 'py> {
+    // With it, text-and-attributes objects become `ValueWithAttrs`
+    text_key: Option<&'a str>,
+    // Applied to element and attribute names, `#` keys are left alone
+    key_transform: Option<&'a Bound<'py, PyAny>>,
+    // Share one `str` per distinct name between all the dicts
+    intern_names: bool,
+    // Numbers with a fraction or exponent become `Decimal`, not `float`
+    exact_numbers: bool,
+    // With it, elements marked `encoding="base64"` become `bytes`
+    base64_text_key: Option<&'a str>,
+    // Converted keys, so that the callable runs once per distinct name
+    keys: HashMap<String, PyObject>,
+}
+
+impl Conversion<'_, '_> {
+    fn key(&mut self, py: Python<'_>, key: &str) -> PyResult<PyObject> {
+        let transform = self.key_transform.filter(|_| !key.starts_with('#'));
+        if transform.is_none() && !self.intern_names {
+            return Ok(PyString::new(py, key).into_any().unbind());
+        }
+        if let Some(converted) = self.keys.get(key) {
+            return Ok(converted.clone_ref(py));
+        }
+        let Some(transform) = transform else {
+            let interned = PyString::new(py, key).into_any().unbind();
+            self.keys.insert(key.to_string(), interned.clone_ref(py));
+            return Ok(interned);
+        };
+        let (prefix, name) = match key.strip_prefix('@') {
+            Some(name) => ("@", name),
+            None => ("", key),
+        };
+        let transformed: String = transform.call1((name,))?.extract().map_err(|_| {
+            pyo3::exceptions::PyTypeError::new_err(format!(
+                "key_transform must return a str, for {:?}",
+                name
+            ))
+        })?;
+        let transformed = PyString::new(py, &format!("{}{}", prefix, transformed))
+            .into_any()
+            .unbind();
+        self.keys.insert(key.to_string(), transformed.clone_ref(py));
+        Ok(transformed)
+    }
+}
+
+fn convert_value(value: &Value, py: Python<'_>, conversion: &mut Conversion) -> PyResult<PyObject> {
+    match value {
+        Value::Null => Ok(py.None()),
+        Value::Bool(b) => Ok(b.into_pyobject(py).unwrap().to_owned().into()),
+        Value::Number(num) => number_to_pyobject(num, py, conversion.exact_numbers),
+        Value::String(s) => Ok(PyString::new(py, s).into()),
+        // Value::Array(arr) => {
+        //     let py_list = PyList::new(py, &[] as &[PyObject]).expect("Invalid `ExactSizeIterator`");
+        //     for item in arr {
+        //         let py_item = value_to_pyobject(py, item)?;
+        //         py_list.append(py_item)?;
+        //     }
+        //     py_list.into_py_any(py)
+        // }
+        Value::Array(arr) => {
+            let list = PyList::empty(py);
+            for item in arr {
+                list.append(convert_value(item, py, conversion)?)?;
+            }
+            Ok(list.into())
+        }
+
+        // Value::Object(_) => value_to_pydict(py, val),
+        Value::Object(obj) => {
+            if let Some(text) = conversion
+                .base64_text_key
+                .and_then(|key| base64_text(obj, key))
+            {
+                return Ok(PyBytes::new(py, &decode_base64(&text)?).into());
+            }
+            let with_attrs = conversion.text_key.filter(|key| {
+                obj.len() > 1
+                    && obj.contains_key(*key)
+                    && obj.keys().all(|k| k == key || k.starts_with('@'))
+            });
+            let dict = match with_attrs {
+                Some(key) => Bound::new(
+                    py,
+                    ValueWithAttrs {
+                        text_key: key.to_string(),
+                    },
+                )?
+                .into_super()
+                .into_any()
+                .downcast_into::<PyDict>()?,
+                None => PyDict::new(py),
+            };
+            for (k, v) in obj {
+                dict.set_item(conversion.key(py, k)?, convert_value(v, py, conversion)?)?;
+            }
+            Ok(dict.into_any().unbind())
+        }
+    }
+}
+
+// The text of an element holding only `@encoding="base64"` and `text_key`
+fn base64_text(obj: &Map<String, Value>, text_key: &str) -> Option<String> {
+    let encoding = obj.get("@encoding")?.as_str()?;
+    if !encoding.eq_ignore_ascii_case("base64")
+        || obj.keys().any(|k| k != "@encoding" && k != text_key)
+    {
+        return None;
+    }
+    Some(obj.get(text_key).and_then(scalar_text).unwrap_or_default())
+}
+
+// Line breaks and indentation within the text are ignored
+fn decode_base64(text: &str) -> Result<Vec<u8>, XmlDictError> {
+    let compact: String = text.split_ascii_whitespace().collect();
+    base64::engine::general_purpose::STANDARD
+        .decode(compact)
+        .map_err(|e| XmlDictError::Parse(format!("invalid base64 content: {}", e)))
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+static DECIMAL: pyo3::sync::GILOnceCell<Py<PyType>> = pyo3::sync::GILOnceCell::new();
+
+// `int` and finite `Decimal` values, with all their digits
+fn exact_number(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
+    let text = obj.str()?;
+    let text = text.to_str()?;
+    text.parse::<serde_json::Number>()
+        .map(Value::Number)
+        .map_err(|_| {
+            XmlDictError::Write(format!("Cannot serialize {} {}", type_name(obj), text)).into()
+        })
+}
+
+// `default` converts objects of unsupported types, as in `json.dumps`
+fn pyobject_to_value(
+    obj: &Bound<'_, PyAny>,
+    options: &SerializeOptions,
+    default: Option<&Bound<'_, PyAny>>,
+) -> PyResult<Value> {
+    let mut conversion = ValueConversion {
+        options,
+        default,
+        containers: Vec::new(),
+    };
+    conversion.convert(obj)
+}
+
+struct ValueConversion<'a, 'py> {
+    options: &'a SerializeOptions,
+    default: Option<&'a Bound<'py, PyAny>>,
+    // Containers being converted, outermost first, bounding the nesting depth
+    // and detecting containers that hold themselves
+    containers: Vec<*mut pyo3::ffi::PyObject>,
+}
+
+impl<'py> ValueConversion<'_, 'py> {
+    fn convert(&mut self, obj: &Bound<'py, PyAny>) -> PyResult<Value> {
+        if let Ok(s) = obj.extract::<String>() {
+            return Ok(Value::String(s));
+        } else if let Ok(b) = obj.downcast::<PyBool>() {
+            return Ok(Value::Bool(b.is_true()));
+        } else if let Ok(i) = obj.downcast::<PyInt>() {
+            if let Ok(i) = i.extract::<i64>() {
+                return Ok(Value::from(i));
+            }
+            // Subclasses such as `IntEnum` print differently
+            return exact_number(&obj.py().get_type::<PyInt>().call1((i,))?);
+        } else if obj.is_instance(DECIMAL.import(obj.py(), "decimal", "Decimal")?)?
+            && obj.call_method0("is_finite")?.is_truthy()?
+        {
+            return exact_number(obj);
+        } else if let Ok(n) = obj.extract::<f64>() {
+            return self.convert_float(n);
+        } else if let Ok(b) = obj.extract::<bool>() {
+            return Ok(Value::Bool(b));
+        } else if obj.is_none() {
+            return Ok(Value::Null);
+        } else if let Some(bytes) = bytes_of(obj) {
+            // Marked so that `decode_base64` gives the bytes back
+            let mut element = Map::new();
+            element.insert("@encoding".to_string(), Value::from("base64"));
+            element.insert("#text".to_string(), Value::String(encode_base64(&bytes)));
+            return Ok(Value::Object(element));
+        }
+
+        if self.containers.contains(&obj.as_ptr()) {
+            return Err(XmlDictError::Write(format!(
+                "Circular reference detected in {}",
+                type_name(obj)
+            ))
+            .into());
+        }
+        if self.containers.len() >= self.options.max_depth {
+            return Err(XmlDictError::LimitExceeded(format!(
+                "nesting is deeper than max_depth={}",
+                self.options.max_depth
+            ))
+            .into());
+        }
+        self.containers.push(obj.as_ptr());
+        let value = self.convert_container(obj)?;
+        self.containers.pop();
+        Ok(value)
+    }
+
+    // `Value::from` would silently turn NaN and infinities into `null`
+    fn convert_float(&self, n: f64) -> PyResult<Value> {
+        if n.is_finite() {
+            return Ok(Value::from(n));
+        }
+        let text = if n.is_nan() {
+            "NaN"
+        } else if n > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        };
+        match self.options.nonfinite_floats {
+            NonFiniteFloats::Error => Err(XmlDictError::Write(format!(
+                "Cannot serialize {} (set nonfinite_floats to \"string\" or \"null\")",
+                text
+            ))
+            .into()),
+            NonFiniteFloats::String => Ok(Value::String(text.to_string())),
+            NonFiniteFloats::Null => Ok(Value::Null),
+        }
+    }
+
+    fn convert_items(
+        &mut self,
+        items: impl Iterator<Item = PyResult<Bound<'py, PyAny>>>,
+    ) -> PyResult<Value> {
+        let arr = items
+            .map(|item| self.convert(&item?))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(Value::Array(arr))
+    }
+
+    fn convert_container(&mut self, obj: &Bound<'py, PyAny>) -> PyResult<Value> {
+        if let Ok(list) = obj.downcast::<PyList>() {
+            self.convert_items(list.iter().map(Ok))
+        } else if let Ok(tuple) = obj.downcast::<PyTuple>() {
+            self.convert_items(tuple.iter().map(Ok))
+        } else if let Ok(dict) = obj.downcast::<PyDict>() {
+            let map: Map<String, Value> = dict
+                .iter()
+                .map(|(k, v)| self.convert_entry(k.extract()?, &v))
+                .collect::<PyResult<_>>()?;
+            Ok(Value::Object(map))
+        } else if obj.is_instance_of::<PySet>() || obj.is_instance_of::<PyFrozenSet>() {
+            self.convert_set(obj)
+        } else if obj.hasattr("keys")? && obj.hasattr("__getitem__")? {
+            // Any other mapping (frozendict, database rows, ...), read through
+            // the mapping protocol in the order of its keys
+            let map: Map<String, Value> = obj
+                .call_method0("keys")?
+                .try_iter()?
+                .map(|k| {
+                    let k = k?;
+                    let v = obj.get_item(&k)?;
+                    self.convert_entry(k.extract()?, &v)
+                })
+                .collect::<PyResult<_>>()?;
+            Ok(Value::Object(map))
+        } else if let (Ok(seq), false) = (
+            obj.downcast::<PySequence>(),
+            obj.is_instance_of::<PyBytes>() || obj.is_instance_of::<PyByteArray>(),
+        ) {
+            // Any other sequence (range, deque, ...); bytes are not sequences of items
+            self.convert_items(seq.try_iter()?)
+        } else if let Some(default) = self.default {
+            // Applied again to unsupported objects within its result, as in
+            // `json.dumps`. `obj` stays in `containers` meanwhile, which bounds
+            // chains of conversions by `max_depth`
+            let converted = default.call1((obj,))?;
+            if converted.is(obj) {
+                return Err(PyValueError::new_err(format!(
+                    "Unsupported Python type {} returned by default",
+                    type_name(&converted)
+                )));
+            }
+            self.convert(&converted)
+        } else {
+            Err(PyValueError::new_err("Unsupported Python type"))
+        }
+    }
+
+    // An attribute cannot carry the `encoding` marker, its bytes are written
+    // as bare base64
+    fn convert_entry(
+        &mut self,
+        key: String,
+        value: &Bound<'py, PyAny>,
+    ) -> PyResult<(String, Value)> {
+        if key.starts_with('@') {
+            if let Some(bytes) = bytes_of(value) {
+                return Ok((key, Value::String(encode_base64(&bytes))));
+            }
+        }
+        Ok((key, self.convert(value)?))
+    }
+
+    // Sets have no order of their own: elements are sorted so that the output
+    // is reproducible, unless `allow_unordered_sets` accepts the iteration order
+    fn convert_set(&mut self, set: &Bound<'py, PyAny>) -> PyResult<Value> {
+        let py = set.py();
+        let items = match py.import("builtins")?.getattr("sorted")?.call1((set,)) {
+            Ok(sorted) => sorted,
+            Err(_) if self.options.allow_unordered_sets => set.clone(),
+            Err(err) => {
+                return Err(PyValueError::new_err(format!(
+                    "Cannot serialize set deterministically, its elements are not orderable \
+                     ({}); set allow_unordered_sets=True to accept an arbitrary order",
+                    err
+                )))
+            }
+        };
+        self.convert_items(items.try_iter()?)
+    }
+}
+
+fn bytes_of(obj: &Bound<'_, PyAny>) -> Option<Vec<u8>> {
+    if let Ok(bytes) = obj.downcast::<PyBytes>() {
+        Some(bytes.as_bytes().to_vec())
+    } else {
+        obj.downcast::<PyByteArray>()
+            .ok()
+            .map(|bytes| bytes.to_vec())
+    }
+}
+
+fn type_name(obj: &Bound<'_, PyAny>) -> String {
+    obj.get_type()
+        .name()
+        .map(|name| name.to_string())
+        .unwrap_or_else(|_| "?".to_string())
+}
+
+/// Reads the contents of the "Data.xml" file from a zip archive.
+///
+/// # Arguments
+///
+/// * `path`: The path to the zip archive.
+///
+/// # Returns
+///
+/// The contents of the "Data.xml" file as a string.
+#[pyfunction]
+fn load_ariane_tml_file_to_dict(py: Python<'_>, path: &str) -> PyResult<PyObject> {
+    let file = std::fs::File::open(path).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to open file: {}", e))
+    })?;
+    let reader = std::io::BufReader::new(file);
+
+    let mut archive = zip::ZipArchive::new(reader).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to open zip archive: {}", e))
+    })?;
+
+    let mut xml_file = archive.by_name("Data.xml").map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+            "Failed to find file in zip archive: {}",
+            e
+        ))
+    })?;
+
+    let mut xml_contents_vec = Vec::new();
+    std::io::copy(&mut xml_file, &mut xml_contents_vec).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!("Failed to read file: {}", e))
+    })?;
+
+    // Re-Allocation to change type from Vec<u8> to String
+    let xml_contents = String::from_utf8(xml_contents_vec).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyUnicodeError, _>(format!(
+            "Failed to convert bytes to string: {}",
+            e
+        ))
+    })?;
+
+    // Convert str to dict
+    let data = py.allow_threads(|| parse_xml(xml_contents.as_str(), &ParseOptions::default()))?;
+
+    value_to_pyobject(&data, py)
+}
+
+#[pymodule]
+fn openspeleo_core(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(xml_str_to_dict, m)?)?;
+    m.add_function(wrap_pyfunction!(xml_buffer_to_dict, m)?)?;
+    m.add_function(wrap_pyfunction!(xml_elements_to_dict, m)?)?;
+    m.add_function(wrap_pyfunction!(dict_to_xml_str, m)?)?;
+    m.add_function(wrap_pyfunction!(dict_to_xml_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(dict_to_xml_file, m)?)?;
+    m.add_function(wrap_pyfunction!(records_to_xml_file, m)?)?;
+    m.add_function(wrap_pyfunction!(document_to_xml_str, m)?)?;
+    m.add_function(wrap_pyfunction!(xml_extract, m)?)?;
+    m.add_function(wrap_pyfunction!(py_xml_find_all, m)?)?;
+    m.add_function(wrap_pyfunction!(py_xml_project, m)?)?;
+    m.add_function(wrap_pyfunction!(xml_str_to_flat, m)?)?;
+    m.add_function(wrap_pyfunction!(flat_to_xml_str, m)?)?;
+    m.add_function(wrap_pyfunction!(xml_subtree_to_dict, m)?)?;
+    m.add_function(wrap_pyfunction!(py_xml_diff, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_events, m)?)?;
+    m.add_function(wrap_pyfunction!(py_is_well_formed, m)?)?;
+    m.add_function(wrap_pyfunction!(py_validate_well_formed, m)?)?;
+    m.add_class::<PyXmlParser>()?;
+    m.add_class::<ParseOptions>()?;
+    m.add_class::<ValueWithAttrs>()?;
+    m.add_class::<SerializeOptions>()?;
+    m.add_function(wrap_pyfunction!(load_ariane_tml_file_to_dict, m)?)?;
+    m.add(
+        "XmlDictError",
+        m.py().get_type::<exceptions::XmlDictError>(),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use serde_json::{Map, Value};
+
+use crate::scalar_text;
+
+/// Callbacks of `walk`, each doing nothing unless overridden.
+///
+/// Values are read like `value_to_xml` writes them, so a visitor sees the
+/// elements, attributes, text and comments of the XML the tree stands for.
+pub trait Visitor {
+    /// Called on entering the element `name`, before its attributes.
+    fn enter_element(&mut self, _name: &str) {}
+
+    /// Called for each attribute of the element just entered, `name` without
+    /// its `@`. Non-string values are given as written, `null` as `""`.
+    fn attribute(&mut self, _name: &str, _value: &str) {}
+
+    /// Called for the text of the current element: its `#text`, each text run
+    /// of its `#content`, or the element value itself when it is a scalar.
+    fn text(&mut self, _text: &str) {}
+
+    /// Called for each `#comment` of the current element.
+    fn comment(&mut self, _comment: &str) {}
+
+    /// Called after the content of the element `name`.
+    fn exit_element(&mut self, _name: &str) {}
+}
+
+/// Walks a parsed document, as returned by `parse_xml`, calling `visitor`
+/// for every element in document order.
+///
+/// An array is visited as repeated elements of its key. Within an element,
+/// attributes come first, then `#text`, the `#content` fragments, and the
+/// children. Top-level `#` keys other than `#comment`, such as the `#xml`
+/// declaration, are skipped.
+pub fn walk<V: Visitor + ?Sized>(document: &Value, visitor: &mut V) {
+    if let Value::Object(top) = document {
+        walk_children(top, visitor);
+    }
+}
+
+fn walk_children<V: Visitor + ?Sized>(obj: &Map<String, Value>, visitor: &mut V) {
+    for (key, value) in obj {
+        if key == "#comment" {
+            let comments = match value {
+                Value::Array(comments) => comments.as_slice(),
+                comment => std::slice::from_ref(comment),
+            };
+            for comment in comments {
+                visitor.comment(&scalar_text(comment).unwrap_or_default());
+            }
+        } else if !key.starts_with(['@', '#']) {
+            walk_element(key, value, visitor);
+        }
+    }
+}
+
+fn walk_element<V: Visitor + ?Sized>(name: &str, value: &Value, visitor: &mut V) {
+    if let Value::Array(items) = value {
+        for item in items {
+            walk_element(name, item, visitor);
+        }
+        return;
+    }
+
+    visitor.enter_element(name);
+    if let Value::Object(obj) = value {
+        for (key, value) in obj {
+            if let Some(attr) = key.strip_prefix('@') {
+                visitor.attribute(attr, &scalar_text(value).unwrap_or_default());
+            }
+        }
+        if let Some(text) = obj.get("#text").and_then(scalar_text) {
+            visitor.text(&text);
+        }
+        if let Some(Value::Array(fragments)) = obj.get("#content") {
+            for fragment in fragments {
+                match fragment {
+                    Value::Object(child) => walk_children(child, visitor),
+                    _ => {
+                        if let Some(text) = scalar_text(fragment) {
+                            visitor.text(&text);
+                        }
+                    }
+                }
+            }
+        }
+        walk_children(obj, visitor);
+    } else if let Some(text) = scalar_text(value) {
+        visitor.text(&text);
+    }
+    visitor.exit_element(name);
+}
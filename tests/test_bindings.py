@@ -0,0 +1,629 @@
+import collections
+import collections.abc
+import decimal
+import math
+import tracemalloc
+
+import pytest
+
+import openspeleo_core
+
+
+def test_dict_with_tuple_values():
+    data = {"Shot": ({"#text": "A1"}, {"#text": "A2"}), "Team": ("Alice", "Bob")}
+    xml = openspeleo_core.dict_to_xml_str(data, "Survey")
+    assert xml.endswith(
+        "<Survey><Shot>A1</Shot><Shot>A2</Shot>"
+        "<Team>Alice</Team><Team>Bob</Team></Survey>"
+    )
+
+
+def test_dict_with_generic_sequence_values():
+    data = {"Team": collections.deque(["Alice", "Bob"])}
+    xml = openspeleo_core.dict_to_xml_str(data, "Survey")
+    assert xml.endswith("<Survey><Team>Alice</Team><Team>Bob</Team></Survey>")
+
+
+def test_strings_are_not_sequences():
+    xml = openspeleo_core.dict_to_xml_str({"Name": "AB"}, "Survey")
+    assert xml.endswith("<Survey><Name>AB</Name></Survey>")
+
+
+def test_bytes_are_not_sequences():
+    xml = openspeleo_core.dict_to_xml_str({"Blob": b"raw"}, "Survey")
+    assert xml.endswith('<Survey><Blob encoding="base64">cmF3</Blob></Survey>')
+
+
+def test_xml_parser_matches_free_function():
+    assert openspeleo_core.XmlParser.__module__ == "openspeleo_core"
+    parser = openspeleo_core.XmlParser(force_list=["Shot"])
+    for xml in [
+        "<Survey><Shot>A1</Shot></Survey>",
+        "<Survey><Shot/><Shot>A2</Shot></Survey>",
+    ]:
+        expected = openspeleo_core.xml_str_to_dict(xml, force_list=["Shot"])
+        assert parser.parse(xml) == expected
+
+
+def test_xml_parser_with_options():
+    options = openspeleo_core.ParseOptions(
+        force_list={"Shot"}, coerce_types=True, keep_null=False
+    )
+    parser = openspeleo_core.XmlParser(options=options)
+    assert parser.options.coerce_types is True
+    for xml in [
+        "<Survey><Shot><Length>1.5</Length></Shot></Survey>",
+        "<Survey><Shot><Length>2</Length><Note/></Shot><Shot/></Survey>",
+        "<Survey><Name>AB</Name></Survey>",
+    ]:
+        expected = openspeleo_core.xml_str_to_dict(xml, options=options)
+        assert parser.parse(xml) == expected
+
+    # Explicit keyword arguments take precedence over `options`
+    parser = openspeleo_core.XmlParser(keep_null=True, options=options)
+    assert parser.parse("<Survey><Note/></Survey>") == {"Survey": {"Note": None}}
+
+
+def test_parse_options_defaults_match_current_behavior():
+    options = openspeleo_core.ParseOptions()
+    assert options.keep_null is True
+    assert options.force_list == set()
+    assert options.auto_list is False
+    assert options.ordered_content is False
+
+    xml = "<Survey><Shot>A1</Shot><Note/></Survey>"
+    result = openspeleo_core.xml_str_to_dict(xml, options=options)
+    assert result == openspeleo_core.xml_str_to_dict(xml)
+
+
+def test_parse_options_are_honored():
+    options = openspeleo_core.ParseOptions(keep_null=False, force_list=["Shot"])
+    xml = "<Survey><Shot>A1</Shot><Note/></Survey>"
+    result = openspeleo_core.xml_str_to_dict(xml, options=options)
+    assert result == {"Survey": {"Shot": ["A1"], "Note": {}}}
+
+
+def test_keyword_arguments_override_options():
+    options = openspeleo_core.ParseOptions(keep_null=False)
+    xml = "<Survey><Note/></Survey>"
+    result = openspeleo_core.xml_str_to_dict(xml, keep_null=True, options=options)
+    assert result == {"Survey": {"Note": None}}
+
+
+def test_serialize_options():
+    assert openspeleo_core.SerializeOptions().attr_quote == '"'
+    options = openspeleo_core.SerializeOptions(attr_quote="'")
+    xml = openspeleo_core.dict_to_xml_str({"@unit": "m"}, "Survey", options=options)
+    assert xml.endswith("<Survey unit='m'/>")
+
+    with pytest.raises(openspeleo_core.XmlDictError, match="attr_quote"):
+        openspeleo_core.SerializeOptions(attr_quote="`")
+
+
+def test_concurrent_parses_from_multiple_threads():
+    from concurrent.futures import ThreadPoolExecutor
+
+    documents = [
+        "<Survey>"
+        + "".join(f"<Shot id='{i}-{j}'><Length>{j}</Length></Shot>" for j in range(500))
+        + "</Survey>"
+        for i in range(16)
+    ]
+    expected = [openspeleo_core.xml_str_to_dict(xml) for xml in documents]
+    with ThreadPoolExecutor(max_workers=8) as executor:
+        results = list(executor.map(openspeleo_core.xml_str_to_dict, documents))
+    assert results == expected
+
+
+def test_concurrent_serialization_from_multiple_threads():
+    from concurrent.futures import ThreadPoolExecutor
+
+    surveys = [
+        {"Shot": [{"@id": f"{i}-{j}", "Length": str(j)} for j in range(500)]}
+        for i in range(16)
+    ]
+
+    def serialize(data):
+        return openspeleo_core.dict_to_xml_str(data, root_name="Survey")
+
+    expected = [serialize(data) for data in surveys]
+    with ThreadPoolExecutor(max_workers=8) as executor:
+        results = list(executor.map(serialize, surveys))
+    assert results == expected
+
+
+def test_set_of_strings_is_sorted():
+    data = {"Explorer": {"C", "A", "B"}, "Team": frozenset({"Y", "X"})}
+    xml = openspeleo_core.dict_to_xml_str(data, root_name="Survey")
+    assert xml.endswith(
+        "<Survey><Explorer>A</Explorer><Explorer>B</Explorer><Explorer>C</Explorer>"
+        "<Team>X</Team><Team>Y</Team></Survey>"
+    )
+
+
+def test_set_of_mixed_types():
+    data = {"Tag": {1, "a"}}
+    with pytest.raises(ValueError, match="not orderable"):
+        openspeleo_core.dict_to_xml_str(data, root_name="Survey")
+
+    options = openspeleo_core.SerializeOptions(allow_unordered_sets=True)
+    xml = openspeleo_core.dict_to_xml_str(data, root_name="Survey", options=options)
+    assert "<Tag>a</Tag>" in xml
+    assert xml.count("<Tag>") == 2
+
+
+def test_parse_events_counts_start_events():
+    events = []
+    openspeleo_core.parse_events(
+        '<Survey unit="m"><Shot id="1">A1</Shot><Shot id="2"/></Survey>', events.append
+    )
+    assert sum(1 for event in events if event[0] == "start") == 3
+    assert events == [
+        ("start", "Survey", {"unit": "m"}),
+        ("start", "Shot", {"id": "1"}),
+        ("text", "A1"),
+        ("end", "Shot"),
+        ("start", "Shot", {"id": "2"}),
+        ("end", "Shot"),
+        ("end", "Survey"),
+    ]
+
+
+def test_parse_events_propagates_callback_exceptions():
+    def callback(event):
+        if event[0] == "text":
+            raise KeyError("stop")
+
+    with pytest.raises(KeyError, match="stop"):
+        openspeleo_core.parse_events("<a><b>x</b></a>", callback)
+
+
+def test_value_with_attrs():
+    xml = '<Shot><Length unit="m">5</Length><Depth>3</Depth></Shot>'
+    options = openspeleo_core.ParseOptions(value_with_attrs=True, coerce_types=True)
+    data = openspeleo_core.xml_str_to_dict(xml, options=options)
+    length = data["Shot"]["Length"]
+    assert isinstance(length, openspeleo_core.ValueWithAttrs)
+    assert length == {"#text": 5, "@unit": "m"}
+    assert length.value == 5
+    assert length.attrs == {"unit": "m"}
+    assert data["Shot"]["Depth"] == 3
+
+    plain = openspeleo_core.xml_str_to_dict(xml)
+    assert type(plain["Shot"]["Length"]) is dict
+
+    options = openspeleo_core.ParseOptions(value_with_attrs=True)
+    data = openspeleo_core.xml_str_to_dict(xml, options=options)
+    xml_back = openspeleo_core.dict_to_xml_str(data["Shot"], root_name="Shot")
+    assert xml_back.endswith(xml)
+
+
+def test_xml_buffer_to_dict_from_memoryview():
+    xml = "<Survey><Shot>A1</Shot><Name>Grotte é</Name></Survey>".encode()
+    expected = openspeleo_core.xml_str_to_dict(xml.decode())
+    assert openspeleo_core.xml_buffer_to_dict(memoryview(xml)) == expected
+    assert openspeleo_core.xml_buffer_to_dict(bytearray(xml)) == expected
+    options = openspeleo_core.ParseOptions(force_list=["Shot"])
+    assert openspeleo_core.xml_buffer_to_dict(memoryview(xml), options=options) == {
+        "Survey": {"Shot": ["A1"], "Name": "Grotte é"}
+    }
+    with pytest.raises(BufferError, match="contiguous"):
+        openspeleo_core.xml_buffer_to_dict(memoryview(xml)[::2])
+
+
+class _RowMapping(collections.abc.Mapping):
+    def __init__(self, **fields):
+        self._fields = fields
+
+    def __getitem__(self, key):
+        return self._fields[key]
+
+    def __iter__(self):
+        return iter(self._fields)
+
+    def __len__(self):
+        return len(self._fields)
+
+
+def test_generic_mapping_values():
+    data = {"Shot": _RowMapping(**{"@id": "1", "From": "A1", "To": "A2"})}
+    xml = openspeleo_core.dict_to_xml_str(data, "Survey")
+    assert xml.endswith('<Survey><Shot id="1"><From>A1</From><To>A2</To></Shot></Survey>')
+
+
+def test_dict_to_xml_file(tmp_path):
+    data = {"Shot": [{"@id": str(i)} for i in range(100)]}
+    path = tmp_path / "survey.xml"
+    options = openspeleo_core.SerializeOptions(flush_bytes=64)
+    openspeleo_core.dict_to_xml_file(data, "Survey", path, options=options)
+    assert path.read_text() == openspeleo_core.dict_to_xml_str(data, "Survey")
+
+
+def test_records_to_xml_file(tmp_path):
+    def records():
+        for i in range(10_000):
+            yield {"@id": str(i), "From": f"A{i}", "To": f"A{i + 1}"}
+
+    path = tmp_path / "shots.xml"
+    options = openspeleo_core.SerializeOptions(flush_bytes=1024)
+    openspeleo_core.records_to_xml_file(records(), "Survey", "Shot", path, options=options)
+    parsed = openspeleo_core.xml_str_to_dict(path.read_text())
+    assert parsed["Survey"]["Shot"] == list(records())
+
+
+def test_key_transform():
+    xml = '<Survey unit="m"><Shot id="1"><From>A1</From></Shot><Shot id="2"/></Survey>'
+    calls = []
+
+    def upper(name):
+        calls.append(name)
+        return name.upper()
+
+    assert openspeleo_core.xml_str_to_dict(xml, key_transform=upper) == {
+        "SURVEY": {"@UNIT": "m", "SHOT": [{"@ID": "1", "FROM": "A1"}, {"@ID": "2"}]}
+    }
+    # Called once per distinct name
+    assert sorted(calls) == ["From", "Shot", "Survey", "id", "unit"]
+
+
+def test_key_transform_exceptions_propagate():
+    def fail(name):
+        raise KeyError(name)
+
+    with pytest.raises(KeyError, match="Survey"):
+        openspeleo_core.xml_str_to_dict("<Survey/>", key_transform=fail)
+    with pytest.raises(TypeError, match="must return a str"):
+        openspeleo_core.xml_str_to_dict("<Survey/>", key_transform=len)
+
+
+def test_xml_elements_to_dict_resumes_at_offset():
+    xml = "<Survey><Shot>A1</Shot><Shot>A2</Shot><Shot>A3</Shot></Survey>"
+    first, offset = openspeleo_core.xml_elements_to_dict(xml, len("<Survey>"), 2)
+    assert first == {"Shot": ["A1", "A2"]}
+    rest, offset = openspeleo_core.xml_elements_to_dict(xml, offset, 2)
+    assert rest == {"Shot": "A3"}
+    assert offset is None
+
+
+def test_flat_round_trip():
+    xml = '<Survey unit="m"><Shot><From>A1</From></Shot><Shot><From>A2</From></Shot></Survey>'
+    flat = openspeleo_core.xml_str_to_flat(xml)
+    assert flat == {
+        "Survey.@unit": "m",
+        "Survey.Shot.0.From": "A1",
+        "Survey.Shot.1.From": "A2",
+    }
+    assert openspeleo_core.flat_to_xml_str(flat).endswith(xml)
+
+
+class _Station:
+    def __init__(self, name):
+        self.name = name
+
+
+def test_default_converts_unsupported_types():
+    data = {"From": _Station("A1"), "To": [_Station("A2")]}
+    xml = openspeleo_core.dict_to_xml_str(data, "Shot", default=lambda obj: obj.name)
+    assert xml.endswith("<Shot><From>A1</From><To>A2</To></Shot>")
+
+    with pytest.raises(ValueError, match="Unsupported Python type"):
+        openspeleo_core.dict_to_xml_str(data, "Shot")
+    with pytest.raises(ValueError, match="_Station returned by default"):
+        openspeleo_core.dict_to_xml_str(data, "Shot", default=lambda obj: obj)
+
+
+class _Wrapper:
+    def __init__(self, inner):
+        self.inner = inner
+
+
+def test_default_applies_to_its_result():
+    def default(obj):
+        if isinstance(obj, _Wrapper):
+            return {"x": obj.inner}
+        return obj.name
+
+    xml = openspeleo_core.dict_to_xml_str(
+        {"v": _Wrapper(_Station("a"))}, "r", default=default
+    )
+    assert xml.endswith("<r><v><x>a</x></v></r>")
+
+    # Errors within the result are reported as they are
+    cycle = []
+    cycle.append(cycle)
+    with pytest.raises(openspeleo_core.XmlDictError, match="Circular reference"):
+        openspeleo_core.dict_to_xml_str({"v": _Station("a")}, "r", default=lambda o: cycle)
+
+    def fail_inner(obj):
+        if isinstance(obj, _Wrapper):
+            return [obj.inner]
+        raise TypeError("inner failed")
+
+    with pytest.raises(TypeError, match="inner failed"):
+        openspeleo_core.dict_to_xml_str(
+            {"v": _Wrapper(_Station("a"))}, "r", default=fail_inner
+        )
+
+    # A default always wrapping again ends at max_depth
+    with pytest.raises(openspeleo_core.XmlDictError, match="max_depth"):
+        openspeleo_core.dict_to_xml_str({"v": _Station("a")}, "r", default=_Wrapper)
+
+
+def test_default_exceptions_propagate():
+    def fail(obj):
+        raise TypeError(f"cannot serialize {type(obj).__name__}")
+
+    with pytest.raises(TypeError, match="cannot serialize _Station"):
+        openspeleo_core.dict_to_xml_str({"From": _Station("A1")}, "Shot", default=fail)
+
+
+def test_cyclic_list_is_rejected():
+    shots = ["A1"]
+    shots.append(shots)
+    with pytest.raises(openspeleo_core.XmlDictError, match="Circular reference"):
+        openspeleo_core.dict_to_xml_str({"Shot": shots}, "Survey")
+
+
+def test_max_depth():
+    data = {"Shot": "A1"}
+    for _ in range(10):
+        data = {"Shot": data}
+    options = openspeleo_core.SerializeOptions(max_depth=5)
+    with pytest.raises(openspeleo_core.XmlDictError, match="max_depth=5"):
+        openspeleo_core.dict_to_xml_str(data, "Survey", options=options)
+    xml = openspeleo_core.dict_to_xml_str(data, "Survey")
+    assert xml.endswith("A1" + "</Shot>" * 11 + "</Survey>")
+
+
+def test_flat_attributes():
+    options = openspeleo_core.SerializeOptions(flat_attributes=True)
+    nested = {"Length": {"#text": "5", "@unit": "m"}}
+    flat = {"Length": "5", "Length@unit": "m"}
+    xml = openspeleo_core.dict_to_xml_str(flat, "Shot", options=options)
+    assert xml == openspeleo_core.dict_to_xml_str(nested, "Shot", options=options)
+    assert xml.endswith('<Shot><Length unit="m">5</Length></Shot>')
+
+
+def _shots_xml(count):
+    shots = "".join(
+        f'<Shot id="{i}"><From>A{i}</From><Length>{i}.5</Length></Shot>'
+        for i in range(count)
+    )
+    return f"<Survey>{shots}</Survey>"
+
+
+def test_intern_names_output_is_identical():
+    xml = _shots_xml(100)
+    options = openspeleo_core.ParseOptions(intern_names=True)
+    interned = openspeleo_core.xml_str_to_dict(xml, options=options)
+    assert interned == openspeleo_core.xml_str_to_dict(xml)
+
+    first, second = interned["Survey"]["Shot"][:2]
+    for a, b in zip(first, second):
+        assert a is b
+
+
+def test_intern_names_saves_memory():
+    xml = _shots_xml(10_000)
+
+    def retained(options):
+        tracemalloc.start()
+        try:
+            result = openspeleo_core.xml_str_to_dict(xml, options=options)
+            size, _ = tracemalloc.get_traced_memory()
+        finally:
+            tracemalloc.stop()
+        del result
+        return size
+
+    plain = retained(openspeleo_core.ParseOptions())
+    interned = retained(openspeleo_core.ParseOptions(intern_names=True))
+    # Three names per shot, each a separate `str` object without interning
+    assert plain - interned > 10_000 * 3 * 40
+
+
+def test_round_trip_preset():
+    options = openspeleo_core.ParseOptions(round_trip=True, trim_whitespace=True)
+    assert options.ordered_content
+    assert options.keep_comments
+    assert options.keep_declaration
+    assert options.keep_empty_text
+    assert not options.trim_whitespace
+    assert options.keep_doctype
+    assert options.cdata_marker
+
+    xml = (
+        '<?xml version="1.0" encoding="utf-8"?>\n'
+        '<r><!-- c --><a x="1"> t <b/></a><c></c></r>'
+    )
+    document = openspeleo_core.xml_str_to_dict(xml, options=options)
+    written = openspeleo_core.document_to_xml_str(document)
+    assert written.partition("?>")[2] == xml.partition("?>\n")[2]
+
+
+def test_collect_errors():
+    xml = "<survey><shot id='1' depth/><station>A0</stn></survey>extra"
+    data, errors = openspeleo_core.xml_str_to_dict(xml, collect_errors=True)
+    assert data == {"survey": {"shot": {"@id": "1"}, "station": "A0"}}
+    assert [position for position, _ in errors] == [8, xml.index("</stn>"), 54]
+    assert "</stn>" in errors[1][1]
+
+    data, stats, errors = openspeleo_core.xml_str_to_dict(
+        "<a/>", return_stats=True, collect_errors=True
+    )
+    assert (data, stats["elements"], errors) == ({"a": None}, 1, [])
+
+
+def test_sort_keys():
+    data = {"@z": "1", "@a": "2", "to": "A2", "from": "A1"}
+    assert openspeleo_core.dict_to_xml_str(data, "leg").endswith(
+        '<leg z="1" a="2"><to>A2</to><from>A1</from></leg>'
+    )
+    options = openspeleo_core.SerializeOptions(sort_attributes=True)
+    assert openspeleo_core.dict_to_xml_str(
+        data, "leg", sort_keys=True, options=options
+    ).endswith('<leg a="2" z="1"><from>A1</from><to>A2</to></leg>')
+
+    # Parsed documents keep their order
+    xml = '<leg z="1" a="2"><to>A2</to><from>A1</from></leg>'
+    assert list(openspeleo_core.xml_str_to_dict(xml)["leg"]) == ["@z", "@a", "to", "from"]
+
+
+def test_xml_find_all():
+    xml = (
+        '<caves><cave name="North"><shot id="1" type="splay"/><shot id="2"/>'
+        '</cave><area><shot id="3" type="splay"/></area></caves>'
+    )
+    assert openspeleo_core.xml_find_all(xml, "caves/*/shot/@id") == ["1", "2", "3"]
+    assert openspeleo_core.xml_find_all(xml, "caves/*/shot[@type='splay']") == [
+        {"@id": "1", "@type": "splay"},
+        {"@id": "3", "@type": "splay"},
+    ]
+    assert openspeleo_core.xml_find_all(xml, "caves/cave[@name='South']") == []
+    with pytest.raises(openspeleo_core.XmlDictError, match="Invalid path"):
+        openspeleo_core.xml_find_all(xml, "caves/cave[0]")
+
+
+def test_nonfinite_floats():
+    data = {"depth": math.nan, "length": math.inf, "offset": -math.inf}
+    for value in data.values():
+        with pytest.raises(openspeleo_core.XmlDictError, match="nonfinite_floats"):
+            openspeleo_core.dict_to_xml_str({"shot": value}, "survey")
+
+    options = openspeleo_core.SerializeOptions(nonfinite_floats="string")
+    assert openspeleo_core.dict_to_xml_str(data, "shot", options=options).endswith(
+        "<shot><depth>NaN</depth><length>Infinity</length>"
+        "<offset>-Infinity</offset></shot>"
+    )
+
+    options.nonfinite_floats = "null"
+    assert options.nonfinite_floats == "null"
+    assert openspeleo_core.dict_to_xml_str(
+        {"@depth": math.nan, "length": math.inf}, "shot", options=options
+    ).endswith("<shot><length/></shot>")
+
+    with pytest.raises(ValueError, match="nonfinite_floats"):
+        openspeleo_core.SerializeOptions(nonfinite_floats="zero")
+
+
+def test_namespaces():
+    xml = openspeleo_core.dict_to_xml_str(
+        {"soap:Body": {"m:GetShot": {"m:Station": "A1"}}},
+        "soap:Envelope",
+        namespaces={"soap": "http://schemas.xmlsoap.org/soap/envelope/", "m": "urn:survey"},
+    )
+    assert xml.endswith(
+        '<soap:Envelope xmlns:m="urn:survey" '
+        'xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/">'
+        "<soap:Body><m:GetShot><m:Station>A1</m:Station></m:GetShot></soap:Body>"
+        "</soap:Envelope>"
+    )
+    with pytest.raises(openspeleo_core.XmlDictError, match="not declared"):
+        openspeleo_core.dict_to_xml_str(
+            {"x:Body": {}}, "soap:Envelope", namespaces={"soap": "urn:soap"}
+        )
+
+
+def test_doctype():
+    xml = openspeleo_core.dict_to_xml_str(
+        {"name": "Main"}, "survey", doctype='survey SYSTEM "survey.dtd"'
+    )
+    assert xml == (
+        '<?xml version="1.0" encoding="utf-8"?>'
+        '<!DOCTYPE survey SYSTEM "survey.dtd"><survey><name>Main</name></survey>'
+    )
+
+    document = openspeleo_core.xml_str_to_dict(
+        xml, options=openspeleo_core.ParseOptions(keep_doctype=True)
+    )
+    assert document["#doctype"] == 'survey SYSTEM "survey.dtd"'
+    assert openspeleo_core.document_to_xml_str(document) == xml
+
+    with pytest.raises(ValueError):
+        openspeleo_core.dict_to_xml_str({}, "survey", doctype="survey>")
+
+
+def test_xml_project():
+    xml = (
+        '<survey name="Main"><cave name="North"><shot id="1"><to>A1</to></shot>'
+        '<shot id="2"><to>A2</to><length>3.5</length></shot></cave></survey>'
+    )
+    schema = {
+        "survey": "survey/@name",
+        "last_to": "survey/cave/shot[2]/to",
+        "length": "survey/cave/shot[@id='2']/length",
+        "missing": "survey/area",
+    }
+    options = openspeleo_core.ParseOptions(coerce_types=True)
+    assert openspeleo_core.xml_project(xml, schema, options=options) == {
+        "survey": "Main",
+        "last_to": "A2",
+        "length": 3.5,
+        "missing": None,
+    }
+
+
+def test_exact_numbers():
+    xml = "<shot><depth>0.1000000000000000055511151231257827</depth><n>12</n></shot>"
+    options = openspeleo_core.ParseOptions(coerce_types=True, exact_numbers=True)
+    data = openspeleo_core.xml_str_to_dict(xml, options=options)
+    assert data == {
+        "shot": {"depth": decimal.Decimal("0.1000000000000000055511151231257827"), "n": 12}
+    }
+    assert type(data["shot"]["n"]) is int
+
+    written = openspeleo_core.dict_to_xml_str(data["shot"], "shot")
+    assert written.endswith(
+        "<shot><depth>0.1000000000000000055511151231257827</depth><n>12</n></shot>"
+    )
+    written = openspeleo_core.dict_to_xml_str(
+        {"big": 2**70, "flag": True, "price": decimal.Decimal("12.50")}, "item"
+    )
+    assert written.endswith(
+        "<item><big>1180591620717411303424</big><flag>true</flag>"
+        "<price>12.50</price></item>"
+    )
+
+
+def test_base64_bytes_round_trip():
+    blob = bytes(range(256)) + b"\x00cave\xff"
+    xml = openspeleo_core.dict_to_xml_str(
+        {"name": "scan", "data": blob, "@checksum": b"\x01\x02"}, "file"
+    )
+    assert 'checksum="AQI="' in xml
+    assert '<data encoding="base64">' in xml
+
+    options = openspeleo_core.ParseOptions(decode_base64=True)
+    data = openspeleo_core.xml_str_to_dict(xml, options=options)
+    assert data == {"file": {"@checksum": "AQI=", "name": "scan", "data": blob}}
+    assert type(data["file"]["data"]) is bytes
+
+    # Without the option the marked element stays as written
+    data = openspeleo_core.xml_str_to_dict(xml)
+    assert data["file"]["data"]["@encoding"] == "base64"
+
+    # Wrapped lines are accepted, invalid content is rejected
+    data = openspeleo_core.xml_str_to_dict(
+        '<data encoding="base64">\n  Y2F2\n  ZQ==\n</data>', options=options
+    )
+    assert data == {"data": b"cave"}
+    with pytest.raises(openspeleo_core.XmlDictError, match="invalid base64"):
+        openspeleo_core.xml_str_to_dict(
+            '<data encoding="base64">not base64!</data>', options=options
+        )
+
+
+def test_allowed_tags():
+    options = openspeleo_core.ParseOptions(
+        allowed_tags=["survey", "shot"], allowed_attributes={"id"}
+    )
+    assert options.allowed_tags == {"survey", "shot"}
+    data = openspeleo_core.xml_str_to_dict(
+        '<survey><shot id="1"/></survey>', options=options
+    )
+    assert data == {"survey": {"shot": {"@id": "1"}}}
+    with pytest.raises(openspeleo_core.XmlDictError, match="allowed_tags"):
+        openspeleo_core.xml_str_to_dict("<survey><note/></survey>", options=options)
+
+    options.allowed_tags = None
+    assert openspeleo_core.xml_str_to_dict("<note/>", options=options) == {"note": None}
@@ -1,5 +1,16 @@
+use openspeleo_core::{
+    as_f64_path, as_i64_path, as_u64_path, document_to_xml, extract_path, find_all, flat_to_xml,
+    flatten, get_path, is_well_formed, parse_elements, parse_subtree, parse_xml, parse_xml_bytes,
+    parse_xml_collecting_errors, parse_xml_reader, parse_xml_with_stats, unflatten,
+    validate_well_formed, value_to_xml, value_to_xml_bytes, value_to_xml_document,
+    value_to_xml_string, value_to_xml_writer, walk, xml_diff, xml_find_all, xml_project,
+    xml_to_flat, BigIntMode, ControlCharPolicy, DecodeErrors, Difference, DuplicatePolicy,
+    EmptyElementValue, NameCase, ParseOptions, ParsePath, ParseStats, RecordWriter,
+    SerializeOptions, Visitor, WhitespaceMode, XmlDictError, XmlParser,
+};
+use serde_json::json;
+use std::collections::HashMap;
 use std::fs;
-use xml_to_dict::{dict_to_xml_str, xml_str_to_dict};
 
 #[cfg(test)]
 mod tests {
@@ -8,16 +19,2922 @@ mod tests {
     #[test]
     fn test_xml_str_to_dict() {
         let xml_str = fs::read_to_string("demo.xml").expect("Unable to read file");
-        let result = xml_str_to_dict(&xml_str).expect("Conversion failed");
-        let expected_json = fs::read_to_string("demo.json").expect("Unable to read file");
-        assert_eq!(result, expected_json);
+        let result = parse_xml(&xml_str, &ParseOptions::default()).expect("Conversion failed");
+        assert_eq!(result["CaveFile"]["caveName"], json!("DEMO CAVE"));
+        assert_eq!(result["CaveFile"]["unit"], json!("m"));
     }
 
     #[test]
     fn test_dict_to_xml_str() {
-        let json_str = fs::read_to_string("demo.json").expect("Unable to read file");
-        let result = dict_to_xml_str(&json_str).expect("Conversion failed");
-        let expected_xml = fs::read_to_string("demo.xml").expect("Unable to read file");
-        assert_eq!(result, expected_xml);
+        let data = json!({"caveName": {"#text": "DEMO CAVE"}, "unit": {"#text": "m"}});
+        let result =
+            value_to_xml_document(&data, "CaveFile", "utf-8", &SerializeOptions::default())
+                .expect("Conversion failed");
+        assert_eq!(
+            result,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+             <CaveFile><caveName>DEMO CAVE</caveName><unit>m</unit></CaveFile>"
+        );
+    }
+
+    #[test]
+    fn test_demo_round_trip() {
+        let xml_str = fs::read_to_string("demo.xml").expect("Unable to read file");
+        let options = ParseOptions {
+            keep_null: false,
+            ..Default::default()
+        };
+        let data = parse_xml(&xml_str, &options).expect("Conversion failed");
+        let result = value_to_xml_document(
+            &data["CaveFile"],
+            "CaveFile",
+            "utf-8",
+            &SerializeOptions::default(),
+        )
+        .expect("Conversion failed");
+        assert_eq!(
+            parse_xml(&result, &options).expect("Conversion failed"),
+            data
+        );
+    }
+
+    #[cfg(feature = "encodings")]
+    #[test]
+    fn test_dict_to_xml_bytes_windows_1252() {
+        let data = json!({"@unit": "m", "caveName": {"#text": "Café €"}});
+        let result = value_to_xml_bytes(
+            &data,
+            "CaveFile",
+            "windows-1252",
+            &SerializeOptions::default(),
+        )
+        .expect("Encoding failed");
+        let mut expected = b"<?xml version=\"1.0\" encoding=\"windows-1252\"?>".to_vec();
+        expected.extend_from_slice(
+            b"<CaveFile unit=\"m\"><caveName>Caf\xe9 \x80</caveName></CaveFile>",
+        );
+        assert_eq!(result, expected);
+    }
+
+    #[cfg(feature = "encodings")]
+    #[test]
+    fn test_dict_to_xml_bytes_unencodable_character() {
+        let data = json!({"caveName": {"#text": "Cueva 洞窟"}});
+        let err = value_to_xml_bytes(
+            &data,
+            "CaveFile",
+            "windows-1252",
+            &SerializeOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, XmlDictError::Unencodable { ch: '洞', .. }));
+        assert!(err.to_string().contains("U+6D1E"), "{}", err);
+    }
+
+    #[cfg(not(feature = "encodings"))]
+    #[test]
+    fn test_dict_to_xml_bytes_needs_encodings_feature() {
+        let data = json!({"caveName": "Café"});
+        let options = SerializeOptions::default();
+        let err = value_to_xml_bytes(&data, "CaveFile", "windows-1252", &options).unwrap_err();
+        assert!(
+            matches!(err, XmlDictError::UnsupportedEncoding(_)),
+            "{}",
+            err
+        );
+        let xml = value_to_xml_bytes(&data, "CaveFile", " UTF8", &options).unwrap();
+        assert_eq!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?><CaveFile><caveName>Café</caveName></CaveFile>"
+                .as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_auto_list_wrapper_children() {
+        let options = ParseOptions {
+            auto_list: true,
+            ..Default::default()
+        };
+        let single = parse_xml("<books><book>A</book></books>", &options).unwrap();
+        assert_eq!(single, json!({"books": {"book": ["A"]}}));
+
+        let multiple = parse_xml("<books><book>A</book><book>B</book></books>", &options).unwrap();
+        assert_eq!(multiple, json!({"books": {"book": ["A", "B"]}}));
+    }
+
+    #[test]
+    fn test_auto_list_guarded_cases() {
+        let options = ParseOptions {
+            auto_list: true,
+            ..Default::default()
+        };
+        let with_attrs = parse_xml(r#"<books id="1"><book>A</book></books>"#, &options).unwrap();
+        assert_eq!(with_attrs, json!({"books": {"@id": "1", "book": "A"}}));
+
+        let text_only = parse_xml("<books>none</books>", &options).unwrap();
+        assert_eq!(text_only, json!({"books": "none"}));
+
+        let mixed_names = parse_xml("<books><book>A</book><dvd>B</dvd></books>", &options).unwrap();
+        assert_eq!(mixed_names, json!({"books": {"book": "A", "dvd": "B"}}));
+    }
+
+    #[test]
+    fn test_force_list() {
+        let options = ParseOptions {
+            force_list: ["book".to_string()].into(),
+            ..Default::default()
+        };
+        let result = parse_xml("<books><book>A</book><dvd>B</dvd></books>", &options).unwrap();
+        assert_eq!(result, json!({"books": {"book": ["A"], "dvd": "B"}}));
+    }
+
+    #[test]
+    fn test_key_by_unique_keys() {
+        let options = ParseOptions {
+            key_by: [("user".to_string(), "id".to_string())].into(),
+            ..Default::default()
+        };
+        let xml = r#"<users><user id="a"><name>Ann</name></user><user id="b"/><group/></users>"#;
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"users": {
+                "user": {"a": {"@id": "a", "name": "Ann"}, "b": {"@id": "b"}},
+                "group": null,
+            }})
+        );
+
+        // A single element is keyed too
+        let result = parse_xml(r#"<users><user id="a"/></users>"#, &options).unwrap();
+        assert_eq!(result, json!({"users": {"user": {"a": {"@id": "a"}}}}));
+
+        let err = parse_xml(r#"<users><user id="a"/><user/></users>"#, &options).unwrap_err();
+        assert!(err.to_string().contains("no \"id\" attribute"), "{}", err);
+    }
+
+    #[test]
+    fn test_key_by_duplicate_keys() {
+        let xml = r#"<users><user id="a" n="1"/><user id="b"/><user id="a" n="2"/></users>"#;
+        let options = ParseOptions {
+            key_by: [("user".to_string(), "id".to_string())].into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"users": {"user": {
+                "a": [{"@id": "a", "@n": "1"}, {"@id": "a", "@n": "2"}],
+                "b": {"@id": "b"},
+            }}})
+        );
+
+        let last_wins = ParseOptions {
+            duplicate_policy: DuplicatePolicy::LastWins,
+            ..options.clone()
+        };
+        assert_eq!(
+            parse_xml(xml, &last_wins).unwrap()["users"]["user"]["a"],
+            json!({"@id": "a", "@n": "2"})
+        );
+
+        let error = ParseOptions {
+            duplicate_policy: DuplicatePolicy::Error,
+            ..options
+        };
+        let err = parse_xml(xml, &error).unwrap_err();
+        assert!(err.to_string().contains("key \"a\" is repeated"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_stats() {
+        let xml =
+            r#"<survey id="1" unit="m"><shot><from>A1</from><to>A2</to></shot><note/></survey>"#;
+        let (_, stats) = parse_xml_with_stats(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            stats,
+            ParseStats {
+                elements: 5,
+                attributes: 2,
+                max_depth: 3,
+                text_bytes: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn test_single_quoted_attributes() {
+        let data = json!({"@name": "O'Brien \"Bob\"", "@unit": "m"});
+        let options = SerializeOptions {
+            attr_quote: '\'',
+            ..Default::default()
+        };
+        let result = value_to_xml_document(&data, "Explorer", "utf-8", &options).unwrap();
+        assert_eq!(
+            result,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+             <Explorer name='O&apos;Brien &quot;Bob&quot;' unit='m'/>"
+        );
+        let round_trip = parse_xml(&result, &ParseOptions::default()).unwrap();
+        assert_eq!(round_trip, json!({"Explorer": data}));
+    }
+
+    #[test]
+    fn test_invalid_attr_quote() {
+        let options = SerializeOptions {
+            attr_quote: '`',
+            ..Default::default()
+        };
+        let err = value_to_xml_document(&json!({}), "a", "utf-8", &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::InvalidOption(_)));
+    }
+
+    const SURVEY: &str = r#"<survey unit="m">
+        <name>Main line</name>
+        <shots>
+            <shot id="1"><from>A1</from><to>A2</to></shot>
+            <shot id="2"><from>A2</from><to>A3</to></shot>
+        </shots>
+    </survey>"#;
+
+    #[test]
+    fn test_extract_attribute() {
+        let options = ParseOptions::default();
+        let result = extract_path(SURVEY, "survey/@unit", &options).unwrap();
+        assert_eq!(result, Some(json!("m")));
+        let result = extract_path(SURVEY, "survey/shots/shot[2]/@id", &options).unwrap();
+        assert_eq!(result, Some(json!("2")));
+        let result = extract_path(SURVEY, "survey/@missing", &options).unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_extract_text_node() {
+        let result = extract_path(SURVEY, "survey/name", &ParseOptions::default()).unwrap();
+        assert_eq!(result, Some(json!("Main line")));
+    }
+
+    #[test]
+    fn test_extract_indexed_element() {
+        let options = ParseOptions::default();
+        let result = extract_path(SURVEY, "survey/shots/shot[2]", &options).unwrap();
+        assert_eq!(result, Some(json!({"@id": "2", "from": "A2", "to": "A3"})));
+        let result = extract_path(SURVEY, "survey/shots/shot[3]", &options).unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_extract_invalid_path() {
+        let err = extract_path(SURVEY, "survey/shot[0]", &ParseOptions::default()).unwrap_err();
+        assert!(matches!(err, XmlDictError::InvalidPath(_)));
+    }
+
+    const CAVES: &str = r#"<caves>
+        <cave name="North">
+            <shot id="1" type="splay"><to>A1</to></shot>
+            <shot id="2"><to>A2</to></shot>
+        </cave>
+        <cave name="South"><shot id="3" type="splay"/></cave>
+        <area name="East"><shot id="4"/></area>
+    </caves>"#;
+
+    #[test]
+    fn test_find_all_wildcards() {
+        let options = ParseOptions::default();
+        let ids = xml_find_all(CAVES, "caves/*/shot/@id", &options).unwrap();
+        assert_eq!(ids, [json!("1"), json!("2"), json!("3"), json!("4")]);
+        let shots = xml_find_all(CAVES, "caves/cave/shot", &options).unwrap();
+        assert_eq!(
+            shots,
+            [
+                json!({"@id": "1", "@type": "splay", "to": "A1"}),
+                json!({"@id": "2", "to": "A2"}),
+                json!({"@id": "3", "@type": "splay"}),
+            ]
+        );
+        let names = xml_find_all(CAVES, "*/*[1]/@name", &options).unwrap();
+        assert_eq!(names, [json!("North"), json!("East")]);
+        assert!(xml_find_all(CAVES, "caves/*/missing", &options)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_find_all_attribute_predicates() {
+        let options = ParseOptions::default();
+        let splays = xml_find_all(CAVES, "caves/cave/shot[@type='splay']/@id", &options).unwrap();
+        assert_eq!(splays, [json!("1"), json!("3")]);
+        let typed = xml_find_all(CAVES, "caves/*/shot[@type]/@id", &options).unwrap();
+        assert_eq!(typed, [json!("1"), json!("3")]);
+        let south = xml_find_all(CAVES, r#"caves/cave[@name="South"]/shot"#, &options).unwrap();
+        assert_eq!(south, [json!({"@id": "3", "@type": "splay"})]);
+
+        // The first match is the one `extract_path` streams to
+        let first = extract_path(CAVES, "caves/*/shot[@type='splay']", &options).unwrap();
+        assert_eq!(
+            first,
+            Some(json!({"@id": "1", "@type": "splay", "to": "A1"}))
+        );
+
+        for path in ["caves/cave[@]", "caves/cave[@name='x]", "caves/cave[@name"] {
+            let err = xml_find_all(CAVES, path, &options).unwrap_err();
+            assert!(matches!(err, XmlDictError::InvalidPath(_)), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_find_all_ordered_content() {
+        let document = parse_xml(
+            "<p>Shots <b>A1</b> and <b>A2</b><i>x</i></p>",
+            &ParseOptions {
+                ordered_content: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            find_all(&document, "p/b").unwrap(),
+            [json!("A1"), json!("A2")]
+        );
+        assert_eq!(find_all(&document, "p/b[2]").unwrap(), [json!("A2")]);
+    }
+
+    #[test]
+    fn test_xml_project() {
+        let schema: HashMap<String, String> = [
+            ("north", "caves/cave/@name"),
+            ("south_splay", "caves/cave[@name='South']/shot/@id"),
+            ("second_station", "caves/cave/shot[2]/to"),
+            ("first_shot", "caves/cave/shot"),
+            ("east", "caves/area"),
+            ("west", "caves/cave[@name='West']"),
+        ]
+        .into_iter()
+        .map(|(key, path)| (key.to_string(), path.to_string()))
+        .collect();
+        let projected = xml_project(CAVES, &schema, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            serde_json::Value::Object(projected),
+            json!({
+                "north": "North",
+                "south_splay": "3",
+                "second_station": "A2",
+                "first_shot": {"@id": "1", "@type": "splay", "to": "A1"},
+                "east": {"@name": "East", "shot": {"@id": "4"}},
+                "west": null,
+            })
+        );
+
+        // Reading stops once every path is satisfied
+        let schema: HashMap<String, String> =
+            [("name".to_string(), "survey/name".to_string())].into();
+        let xml = "<survey><name>Main</name><shot></survey>";
+        let projected = xml_project(xml, &schema, &ParseOptions::default()).unwrap();
+        assert_eq!(projected["name"], json!("Main"));
+        let schema: HashMap<String, String> =
+            [("to".to_string(), "survey/shot/to".to_string())].into();
+        assert!(xml_project(xml, &schema, &ParseOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_text_order_simple_vs_ordered_content() {
+        let xml = "<p>Station <b>A1</b> is flooded</p>";
+
+        let simple = parse_xml(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(simple, json!({"p": {"#text": "is flooded", "b": "A1"}}));
+        let result =
+            value_to_xml_document(&simple["p"], "p", "utf-8", &SerializeOptions::default())
+                .unwrap();
+        assert!(result.ends_with("<p>is flooded<b>A1</b></p>"), "{}", result);
+
+        let options = ParseOptions {
+            ordered_content: true,
+            ..Default::default()
+        };
+        let ordered = parse_xml(xml, &options).unwrap();
+        assert_eq!(
+            ordered,
+            json!({"p": {"#content": ["Station", {"b": "A1"}, "is flooded"]}})
+        );
+        let result =
+            value_to_xml_document(&ordered["p"], "p", "utf-8", &SerializeOptions::default())
+                .unwrap();
+        assert!(
+            result.ends_with("<p>Station<b>A1</b>is flooded</p>"),
+            "{}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_ordered_content_without_mixed_content() {
+        let options = ParseOptions {
+            ordered_content: true,
+            ..Default::default()
+        };
+        let result = parse_xml("<shot><from>A1</from><to>A2</to></shot>", &options).unwrap();
+        assert_eq!(result, json!({"shot": {"from": "A1", "to": "A2"}}));
+    }
+
+    #[test]
+    fn test_text_separator() {
+        let xml = "<survey><p>Station <b>A1</b> is <i>very</i> flooded</p><n>1<br/>2</n></survey>";
+        assert_eq!(
+            parse_xml(xml, &ParseOptions::default()).unwrap(),
+            json!({"survey": {"p": {"#text": "flooded", "b": "A1", "i": "very"}, "n": {"#text": "2", "br": null}}})
+        );
+
+        let options = ParseOptions {
+            text_separator: Some(" ".to_string()),
+            coerce_types: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"survey": {
+                "p": {"#text": "Station is flooded", "b": "A1", "i": "very"},
+                "n": {"#text": "1 2", "br": null},
+            }})
+        );
+
+        // Ordered content keeps the runs apart
+        let ordered = ParseOptions {
+            ordered_content: true,
+            ..options
+        };
+        let result = parse_xml(xml, &ordered).unwrap();
+        assert_eq!(result["survey"]["p"]["#content"][0], json!("Station"));
+    }
+
+    #[test]
+    fn test_borrowed_and_buffered_paths_match() {
+        let xml_str = fs::read_to_string("demo.xml").expect("Unable to read file");
+        for options in [
+            ParseOptions::default(),
+            ParseOptions {
+                keep_null: false,
+                ordered_content: true,
+                ..Default::default()
+            },
+        ] {
+            let borrowed = parse_xml(&xml_str, &options).expect("Conversion failed");
+            let buffered =
+                parse_xml_reader(xml_str.as_bytes(), &options).expect("Conversion failed");
+            assert_eq!(borrowed, buffered);
+        }
+    }
+
+    #[test]
+    fn test_parse_paths_yield_identical_output() {
+        let demo = fs::read_to_string("demo.xml").expect("Unable to read file");
+        let xml = r#"<?xml version="1.0"?><!-- c --><Survey xml:space="preserve">
+            <Shot id="1" note="a &amp; b"><![CDATA[<raw>]]> &#233; <Length>1.5</Length></Shot>
+            <Shot/></Survey>"#;
+        for base in [
+            ParseOptions::default(),
+            ParseOptions {
+                ordered_content: true,
+                keep_comments: true,
+                keep_declaration: true,
+                coerce_types: true,
+                ..Default::default()
+            },
+        ] {
+            for doc in [demo.as_str(), xml] {
+                let [auto, borrowed, buffered] =
+                    [ParsePath::Auto, ParsePath::Borrowed, ParsePath::Buffered].map(|parse_path| {
+                        let options = ParseOptions {
+                            parse_path,
+                            ..base.clone()
+                        };
+                        parse_xml_with_stats(doc, &options).expect("Conversion failed")
+                    });
+                assert_eq!(borrowed, buffered);
+                assert_eq!(auto, borrowed);
+            }
+        }
+
+        let options = ParseOptions {
+            parse_path: ParsePath::Buffered,
+            max_bytes: Some(16),
+            ..Default::default()
+        };
+        let err = parse_xml(xml, &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::LimitExceeded(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_borrowed_path_unescapes_entities() {
+        let xml = r#"<Explorer name="A &amp; B">&lt;Explorer&gt; &#233;</Explorer>"#;
+        let result = parse_xml(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            result,
+            json!({"Explorer": {"@name": "A & B", "#text": "<Explorer> é"}})
+        );
+        assert_eq!(
+            parse_xml_reader(xml.as_bytes(), &ParseOptions::default()).unwrap(),
+            result
+        );
+    }
+
+    #[test]
+    fn test_reusable_parser_matches_free_function() {
+        let options = ParseOptions {
+            keep_null: false,
+            ..Default::default()
+        };
+        let mut parser = XmlParser::new(options.clone());
+        let xml_str = fs::read_to_string("demo.xml").expect("Unable to read file");
+        for xml in [
+            xml_str.as_str(),
+            "<a><b>1</b><b>2</b></a>",
+            xml_str.as_str(),
+        ] {
+            let expected = parse_xml(xml, &options).unwrap();
+            assert_eq!(parser.parse(xml).unwrap(), expected);
+            assert_eq!(parser.parse_reader(xml.as_bytes()).unwrap(), expected);
+        }
+
+        // Strings read through the buffered path share the read buffer
+        let buffered = ParseOptions {
+            parse_path: ParsePath::Buffered,
+            ..options.clone()
+        };
+        let mut parser = XmlParser::new(buffered);
+        for xml in [xml_str.as_str(), "<a><b>1</b><b>2</b></a>"] {
+            assert_eq!(
+                parser.parse(xml).unwrap(),
+                parse_xml(xml, &options).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_reusable_parser_honors_options_across_calls() {
+        let options = ParseOptions {
+            force_list: ["Shot".to_string()].into_iter().collect(),
+            coerce_types: true,
+            keep_null: false,
+            ..Default::default()
+        };
+        let mut parser = XmlParser::new(options.clone());
+        for xml in [
+            "<Survey><Shot><Length>1.5</Length></Shot></Survey>",
+            "<Survey><Shot><Length>2</Length><Note/></Shot><Shot/></Survey>",
+            "<Survey><Shot><Length>1.5</Length></Shot></Survey>",
+        ] {
+            assert_eq!(
+                parser.parse(xml).unwrap(),
+                parse_xml(xml, &options).unwrap()
+            );
+        }
+        assert_eq!(
+            parser
+                .parse("<Survey><Shot><Length>3</Length></Shot></Survey>")
+                .unwrap(),
+            json!({"Survey": {"Shot": [{"Length": 3}]}})
+        );
+    }
+
+    #[test]
+    fn test_reusable_parser_recovers_after_error() {
+        let mut parser = XmlParser::default();
+        assert!(parser.parse("<a><b></a>").is_err());
+        assert_eq!(parser.parse("<a>1</a>").unwrap(), json!({"a": "1"}));
+    }
+
+    #[test]
+    fn test_self_closing_element_keeps_attributes() {
+        let result = parse_xml(
+            r#"<shot><ref id="5"/><note/></shot>"#,
+            &ParseOptions::default(),
+        );
+        assert_eq!(
+            result.unwrap(),
+            json!({"shot": {"ref": {"@id": "5"}, "note": null}})
+        );
+    }
+
+    #[test]
+    fn test_collapse_single_attr() {
+        let options = ParseOptions {
+            collapse_single_attr: true,
+            ..Default::default()
+        };
+        let xml = r#"<shot><ref id="5"/><to id="A2"></to><from id="A1" unit="m"/></shot>"#;
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"shot": {"ref": "5", "to": "A2", "from": {"@id": "A1", "@unit": "m"}}})
+        );
+    }
+
+    #[test]
+    fn test_skip_empty_elements() {
+        let options = ParseOptions {
+            skip_empty: true,
+            ..Default::default()
+        };
+        let xml = r#"<shot><from>A1</from><note/><comment></comment><ref id="5"/></shot>"#;
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"shot": {"from": "A1", "ref": {"@id": "5"}}})
+        );
+        assert_eq!(
+            parse_xml("<shot><note/></shot>", &options).unwrap(),
+            json!({"shot": {}})
+        );
+    }
+
+    #[test]
+    fn test_skip_empty_keeps_forced_lists() {
+        let options = ParseOptions {
+            skip_empty: true,
+            force_list: ["note".to_string()].into(),
+            ..Default::default()
+        };
+        let result = parse_xml("<shot><from>A1</from><note/><to/></shot>", &options).unwrap();
+        assert_eq!(result, json!({"shot": {"from": "A1", "note": [null]}}));
+    }
+
+    #[test]
+    fn test_custom_text_key() {
+        let options = ParseOptions {
+            text_key: "value".to_string(),
+            ..Default::default()
+        };
+        let result = parse_xml(r#"<shot><length unit="m">12.5</length></shot>"#, &options);
+        assert_eq!(
+            result.unwrap(),
+            json!({"shot": {"length": {"@unit": "m", "value": "12.5"}}})
+        );
+    }
+
+    #[test]
+    fn test_text_key_collides_with_child_name() {
+        let options = ParseOptions {
+            text_key: "text".to_string(),
+            ..Default::default()
+        };
+        for xml in [
+            "<note>Flooded<text>sump</text></note>",
+            "<note><text>sump</text>Flooded</note>",
+        ] {
+            let err = parse_xml(xml, &options).unwrap_err();
+            assert!(matches!(err, XmlDictError::Parse(_)));
+            assert!(err.to_string().contains("text_key \"text\""), "{}", err);
+        }
+        assert_eq!(
+            parse_xml("<note><text>sump</text></note>", &options).unwrap(),
+            json!({"note": {"text": "sump"}})
+        );
+    }
+
+    const INVALID_UTF8: &[u8] =
+        b"<survey><st\xffa>A1</st\xffa><note>B\xff2 &bogus;</note></survey>";
+
+    #[test]
+    fn test_decode_errors_replace_by_default() {
+        let options = ParseOptions::default();
+        assert_eq!(
+            parse_xml_reader(INVALID_UTF8, &options).unwrap(),
+            json!({"survey": {"st\u{fffd}a": "A1", "note": "B\u{fffd}2 \u{fffd}"}})
+        );
+    }
+
+    #[test]
+    fn test_decode_errors_ignore() {
+        let options = ParseOptions {
+            errors: DecodeErrors::Ignore,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml_reader(INVALID_UTF8, &options).unwrap(),
+            json!({"survey": {"sta": "A1", "note": "B2"}})
+        );
+        let result = parse_xml(r#"<note by="A &bogus;"/>"#, &options).unwrap();
+        assert_eq!(result, json!({"note": {"@by": "A "}}));
+    }
+
+    #[test]
+    fn test_decode_errors_strict() {
+        let options = ParseOptions {
+            errors: DecodeErrors::Strict,
+            ..Default::default()
+        };
+        let err = parse_xml_reader(INVALID_UTF8, &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::Utf8(_)), "{}", err);
+        let err = parse_xml_reader(&b"<note>\xff</note>"[..], &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::Parse(_)), "{}", err);
+        let err = parse_xml("<note>&bogus;</note>", &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::Parse(_)), "{}", err);
+        let err = parse_xml(r#"<note by="&bogus;"/>"#, &options).unwrap_err();
+        assert!(err.to_string().contains("attribute \"by\""), "{}", err);
+        assert!("lossy".parse::<DecodeErrors>().is_err());
+    }
+
+    #[test]
+    fn test_well_formed_documents() {
+        let xml_str = fs::read_to_string("demo.xml").expect("Unable to read file");
+        assert!(is_well_formed(&xml_str));
+        assert!(is_well_formed("<survey/>"));
+        assert!(is_well_formed(
+            "<?xml version=\"1.0\"?><!-- c --><a><b/>text</a>\n"
+        ));
+    }
+
+    #[test]
+    fn test_malformed_documents() {
+        for xml in [
+            "",
+            "<a><b></a>",
+            "<a>",
+            "<a></a><b></b>",
+            "<a/>trailing",
+            "<a x=1/>",
+            "</a>",
+        ] {
+            assert!(!is_well_formed(xml), "{:?} accepted", xml);
+        }
+        let err = validate_well_formed("<a/><b/>").unwrap_err();
+        assert!(err.to_string().contains("more than one root"), "{}", err);
+    }
+
+    #[test]
+    fn test_max_name_len() {
+        let options = ParseOptions {
+            max_name_len: Some(16),
+            ..Default::default()
+        };
+        let long_name = "a".repeat(17);
+        let xml = format!("<survey><{0}>1</{0}></survey>", long_name);
+        let err = parse_xml(&xml, &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::LimitExceeded(_)), "{}", err);
+        assert!(err.to_string().contains("max_name_len=16"), "{}", err);
+
+        let xml = format!(r#"<survey {}="1"/>"#, long_name);
+        assert!(parse_xml(&xml, &options).is_err());
+        assert!(parse_xml("<survey><shot>1</shot></survey>", &options).is_ok());
+    }
+
+    #[test]
+    fn test_max_attr_count() {
+        let options = ParseOptions {
+            max_attr_count: Some(2),
+            ..Default::default()
+        };
+        let err = parse_xml(r#"<shot a="1" b="2" c="3"/>"#, &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::LimitExceeded(_)), "{}", err);
+        assert!(err.to_string().contains("<shot>"), "{}", err);
+        assert!(parse_xml(r#"<shot a="1" b="2"/>"#, &options).is_ok());
+    }
+
+    #[test]
+    fn test_allowed_tags() {
+        let names = |names: &[&str]| Some(names.iter().map(|n| n.to_string()).collect());
+        let options = ParseOptions {
+            allowed_tags: names(&["survey", "shot", "length"]),
+            allowed_attributes: names(&["id", "unit"]),
+            strip_namespaces: true,
+            ..Default::default()
+        };
+        let xml = "<s:survey xmlns:s=\"urn:survey\"><shot id=\"1\">\
+                   <length unit=\"m\">3.5</length></shot></s:survey>";
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"survey": {"shot": {"@id": "1", "length": {"@unit": "m", "#text": "3.5"}}}})
+        );
+
+        let tag = parse_xml("<survey><shot/><script/></survey>", &options).unwrap_err();
+        assert!(matches!(&tag, XmlDictError::Parse(m) if m.contains("<script>")));
+        let attr = parse_xml("<survey><shot onload=\"x\"/></survey>", &options).unwrap_err();
+        assert!(matches!(&attr, XmlDictError::Parse(m) if m.contains("\"onload\"")));
+
+        // Namespace declarations are allowed without being listed
+        let options = ParseOptions {
+            allowed_attributes: names(&[]),
+            ..Default::default()
+        };
+        assert!(parse_xml("<a xmlns=\"urn:a\" xmlns:b=\"urn:b\"/>", &options).is_ok());
+    }
+
+    #[test]
+    fn test_max_array_len() {
+        let options = ParseOptions {
+            max_array_len: Some(3),
+            ..Default::default()
+        };
+        let shots = |count: usize| {
+            let shots: String = (0..count).map(|i| format!("<shot>{}</shot>", i)).collect();
+            format!("<survey><name>Main</name>{}</survey>", shots)
+        };
+        let err = parse_xml(&shots(4), &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::LimitExceeded(_)), "{}", err);
+        assert!(err.to_string().contains("<shot>"), "{}", err);
+        assert!(err.to_string().contains("max_array_len=3"), "{}", err);
+        assert_eq!(
+            parse_xml(&shots(3), &options).unwrap(),
+            json!({"survey": {"name": "Main", "shot": ["0", "1", "2"]}})
+        );
+
+        let keyed = ParseOptions {
+            key_by: [("shot".to_string(), "to".to_string())].into(),
+            ..options.clone()
+        };
+        let xml =
+            "<survey><shot to=\"A\"/><shot to=\"A\"/><shot to=\"A\"/><shot to=\"A\"/></survey>";
+        let err = parse_xml(xml, &keyed).unwrap_err();
+        assert!(matches!(err, XmlDictError::LimitExceeded(_)), "{}", err);
+
+        // A single repeat already makes an array of two
+        let single = ParseOptions {
+            max_array_len: Some(1),
+            ..Default::default()
+        };
+        let err = parse_xml("<r><a>1</a><a>2</a></r>", &single).unwrap_err();
+        assert!(matches!(err, XmlDictError::LimitExceeded(_)), "{}", err);
+        assert_eq!(
+            parse_xml("<r><a>1</a></r>", &single).unwrap(),
+            json!({"r": {"a": "1"}})
+        );
+        let keyed_pair = ParseOptions {
+            key_by: [("a".to_string(), "k".to_string())].into(),
+            ..single.clone()
+        };
+        let xml = "<r><a k=\"x\"/><a k=\"x\"/></r>";
+        assert!(matches!(
+            parse_xml(xml, &keyed_pair),
+            Err(XmlDictError::LimitExceeded(_))
+        ));
+
+        // `force_list` arrays count too
+        let forced = ParseOptions {
+            max_array_len: Some(0),
+            force_list: ["a".to_string()].into(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            parse_xml("<r><a>1</a></r>", &forced),
+            Err(XmlDictError::LimitExceeded(_))
+        ));
+
+        // Lists over the limit are read in batches
+        let xml = shots(10);
+        let offset = xml.find("<shot>").unwrap();
+        let (batch, next) = parse_elements(&xml, offset, 3, &options).unwrap();
+        assert_eq!(batch, json!({"shot": ["0", "1", "2"]}));
+        assert!(next.is_some());
+    }
+
+    #[test]
+    fn test_repeated_text_only_elements_collapse_element_wise() {
+        let xml = r#"<note><tag>a</tag><tag>b</tag><tag id="3">c</tag></note>"#;
+        assert_eq!(
+            parse_xml(xml, &ParseOptions::default()).unwrap(),
+            json!({"note": {"tag": ["a", "b", {"@id": "3", "#text": "c"}]}})
+        );
+    }
+
+    #[test]
+    fn test_array_item_name() {
+        let data = json!({"list": [1, 2, 3], "empty": []});
+        let options = SerializeOptions {
+            array_item_name: Some("item".to_string()),
+            ..Default::default()
+        };
+        let result = value_to_xml_document(&data, "root", "utf-8", &options).unwrap();
+        assert!(
+            result.ends_with(
+                "<root><list><item>1</item><item>2</item><item>3</item></list><empty/></root>"
+            ),
+            "{}",
+            result
+        );
+
+        let result =
+            value_to_xml_document(&data, "root", "utf-8", &SerializeOptions::default()).unwrap();
+        assert!(
+            result.ends_with("<root><list>1</list><list>2</list><list>3</list></root>"),
+            "{}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_xml_space_preserve() {
+        let xml = r#"<survey>
+            <name xml:space="preserve">  Main line  </name>
+            <notes xml:space="preserve"><note>  A1 </note><note xml:space="default"> A2 </note></notes>
+            <unit> m </unit>
+        </survey>"#;
+        let result = parse_xml(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            result,
+            json!({"survey": {
+                "name": {"@xml:space": "preserve", "#text": "  Main line  "},
+                "notes": {
+                    "@xml:space": "preserve",
+                    "note": ["  A1 ", {"@xml:space": "default", "#text": "A2"}],
+                },
+                "unit": "m",
+            }})
+        );
+    }
+
+    #[test]
+    fn test_trim_whitespace_disabled() {
+        let options = ParseOptions {
+            trim_whitespace: false,
+            ..Default::default()
+        };
+        let result = parse_xml("<shot>\n  <from> A1 </from>\n</shot>", &options).unwrap();
+        assert_eq!(result, json!({"shot": {"from": " A1 "}}));
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        let xml = "<shot note=\"a\tb\"><comment>\tWet \n\n  crawl,\r\n  low\tair </comment>\
+                   <raw xml:space=\"preserve\">a\t b</raw></shot>";
+        let options = ParseOptions {
+            normalize_whitespace: true,
+            trim_whitespace: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"shot": {
+                "@note": "a\tb",
+                "comment": "Wet crawl, low air",
+                "raw": {"@xml:space": "preserve", "#text": "a\t b"},
+            }})
+        );
+    }
+
+    #[test]
+    fn test_whitespace_mode() {
+        let xml = "<shot><comment>\n  Wet  crawl,\n  low air  \n</comment>\
+                   <raw xml:space=\"preserve\"> a  b </raw><empty>  </empty></shot>";
+        let comment = |mode: WhitespaceMode| {
+            let options = ParseOptions {
+                whitespace_mode: Some(mode),
+                ..Default::default()
+            };
+            let result = parse_xml(xml, &options).unwrap();
+            assert_eq!(result["shot"]["raw"]["#text"], json!(" a  b "));
+            assert_eq!(result["shot"]["empty"], json!({}));
+            result["shot"]["comment"].clone()
+        };
+        assert_eq!(
+            comment(WhitespaceMode::TrimEnds),
+            json!("Wet  crawl,\n  low air")
+        );
+        assert_eq!(
+            comment(WhitespaceMode::Collapse),
+            json!("Wet crawl, low air")
+        );
+        assert_eq!(
+            comment(WhitespaceMode::Preserve),
+            json!("\n  Wet  crawl,\n  low air  \n")
+        );
+
+        // The mode wins over the booleans it replaces
+        let options = ParseOptions {
+            whitespace_mode: Some(WhitespaceMode::Preserve),
+            normalize_whitespace: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml("<a> x  y </a>", &options).unwrap(),
+            json!({"a": " x  y "})
+        );
+        assert_eq!(
+            ParseOptions::default().effective_whitespace_mode(),
+            WhitespaceMode::TrimEnds
+        );
+        assert!(matches!(
+            "trim".parse::<WhitespaceMode>(),
+            Err(XmlDictError::InvalidOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_subtree() {
+        let options = ParseOptions::default();
+        let result = parse_subtree(SURVEY, "shots", &options).unwrap().unwrap();
+        assert_eq!(
+            result,
+            json!({"shots": {"shot": [
+                {"@id": "1", "from": "A1", "to": "A2"},
+                {"@id": "2", "from": "A2", "to": "A3"},
+            ]}})
+        );
+        assert!(!result.to_string().contains("Main line"));
+
+        let result = parse_subtree(SURVEY, "from", &options).unwrap();
+        assert_eq!(result, Some(json!({"from": "A1"})));
+        assert_eq!(parse_subtree(SURVEY, "missing", &options).unwrap(), None);
+    }
+
+    #[test]
+    fn test_namespace_declaration_map() {
+        let data = json!({
+            "@xmlns": {"": "urn:cave", "sv": "urn:survey"},
+            "@unit": "m",
+            "sv:shot": {"sv:from": "A1"},
+        });
+        let options = SerializeOptions::default();
+        let result = value_to_xml_document(&data, "CaveFile", "utf-8", &options).unwrap();
+        assert!(
+            result.ends_with(
+                "<CaveFile xmlns=\"urn:cave\" xmlns:sv=\"urn:survey\" unit=\"m\">\
+                 <sv:shot><sv:from>A1</sv:from></sv:shot></CaveFile>"
+            ),
+            "{}",
+            result
+        );
+
+        // Declarations parse back as plain attributes, which write the same XML
+        let parsed = parse_xml(&result, &ParseOptions::default()).unwrap();
+        assert_eq!(parsed["CaveFile"]["@xmlns:sv"], json!("urn:survey"));
+        let again =
+            value_to_xml_document(&parsed["CaveFile"], "CaveFile", "utf-8", &options).unwrap();
+        assert_eq!(parse_xml(&again, &ParseOptions::default()).unwrap(), parsed);
+    }
+
+    #[test]
+    fn test_namespace_declarations_round_trip_as_attributes() {
+        let xml = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+                   <CaveFile xmlns=\"urn:cave\" xmlns:sv=\"urn:survey\">\
+                   <sv:shot xmlns:gml=\"http://www.opengis.net/gml\" gml:id=\"s1\">\
+                   <sv:from>A1</sv:from></sv:shot></CaveFile>";
+        let parsed = parse_xml(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            parsed,
+            json!({"CaveFile": {
+                "@xmlns": "urn:cave",
+                "@xmlns:sv": "urn:survey",
+                "sv:shot": {
+                    "@xmlns:gml": "http://www.opengis.net/gml",
+                    "@gml:id": "s1",
+                    "sv:from": "A1",
+                },
+            }})
+        );
+        let written = value_to_xml_document(
+            &parsed["CaveFile"],
+            "CaveFile",
+            "utf-8",
+            &SerializeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(written, xml);
+    }
+
+    #[test]
+    fn test_coerce_types_default_booleans() {
+        let options = ParseOptions {
+            coerce_types: true,
+            ..Default::default()
+        };
+        let xml = r#"<shot id="12" surveyed="true"><length>4.5</length><station>007</station>
+            <flags>yes</flags><done>True</done><lost>false</lost></shot>"#;
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"shot": {
+                "@id": 12,
+                "@surveyed": true,
+                "length": 4.5,
+                "station": "007",
+                "flags": "yes",
+                "done": "True",
+                "lost": false,
+            }})
+        );
+    }
+
+    #[test]
+    fn test_coerce_types_custom_booleans() {
+        let options = ParseOptions {
+            coerce_types: true,
+            true_values: vec!["yes".to_string(), "1".to_string()],
+            false_values: vec!["no".to_string(), "0".to_string()],
+            bool_case_sensitive: false,
+            ..Default::default()
+        };
+        let xml = "<shot><a>Yes</a><b>no</b><c>1</c><d>0</d><e>true</e><f>2</f></shot>";
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"shot": {"a": true, "b": false, "c": true, "d": false, "e": "true", "f": 2}})
+        );
+    }
+
+    #[test]
+    fn test_coerce_types_big_int_mode() {
+        let xml = "<survey><i>1234567890123456789012345678901234567890</i>\
+                   <u>18446744073709551615</u><n>-42</n></survey>";
+        let options = ParseOptions {
+            coerce_types: true,
+            ..Default::default()
+        };
+        let result = parse_xml(xml, &options).unwrap();
+        assert_eq!(
+            result["survey"]["i"],
+            json!("1234567890123456789012345678901234567890")
+        );
+        assert_eq!(result["survey"]["u"], json!(u64::MAX));
+        assert_eq!(result["survey"]["n"], json!(-42));
+
+        let options = ParseOptions {
+            big_int_mode: BigIntMode::Float,
+            ..options
+        };
+        let result = parse_xml(xml, &options).unwrap();
+        assert_eq!(result["survey"]["i"], json!(1.2345678901234568e39));
+
+        assert!(matches!(
+            "int".parse::<BigIntMode>(),
+            Err(XmlDictError::InvalidOption(_))
+        ));
+    }
+
+    #[cfg(feature = "exact_numbers")]
+    #[test]
+    fn test_exact_numbers() {
+        let xml = "<survey><depth>0.1000000000000000055511151231257827</depth>\
+                   <length>12.50</length><i>1234567890123456789012345678901234567890</i>\
+                   <e>1E+3</e><n>-42</n><dot>1.</dot></survey>";
+        let rounded = ParseOptions {
+            coerce_types: true,
+            big_int_mode: BigIntMode::Float,
+            ..Default::default()
+        };
+        let result = parse_xml(xml, &rounded).unwrap();
+        assert_eq!(result["survey"]["depth"], json!(0.1));
+        assert_eq!(result["survey"]["length"], json!(12.5));
+
+        let options = ParseOptions {
+            exact_numbers: true,
+            ..rounded
+        };
+        let result = parse_xml(xml, &options).unwrap();
+        let text = |key: &str| result["survey"][key].to_string();
+        assert!(result["survey"]["depth"].is_number());
+        assert_eq!(text("depth"), "0.1000000000000000055511151231257827");
+        assert_eq!(text("length"), "12.50");
+        assert_eq!(text("i"), "1234567890123456789012345678901234567890");
+        assert_eq!(text("e"), "1E+3");
+        assert_eq!(result["survey"]["n"].as_i64(), Some(-42));
+        assert_eq!(result["survey"]["dot"], json!(1.0));
+
+        let written = document_to_xml(&result, &SerializeOptions::default()).unwrap();
+        assert!(
+            written.contains(
+                "<depth>0.1000000000000000055511151231257827</depth><length>12.50</length>\
+                 <i>1234567890123456789012345678901234567890</i><e>1E+3</e><n>-42</n><dot>1.0</dot>"
+            ),
+            "{}",
+            written
+        );
+    }
+
+    #[cfg(not(feature = "exact_numbers"))]
+    #[test]
+    fn test_exact_numbers_needs_feature() {
+        let options = ParseOptions {
+            coerce_types: true,
+            exact_numbers: true,
+            ..Default::default()
+        };
+        let err = parse_xml("<n>0.10</n>", &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::InvalidOption(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_element_order_template() {
+        let data = json!({
+            "shot": {"azimuth": "90", "comment": "dry", "from": "A1", "length": "4.5", "to": "A2"},
+            "name": "Main line",
+        });
+        let options = SerializeOptions {
+            element_order: [
+                (
+                    "survey".to_string(),
+                    vec!["name".to_string(), "shot".to_string()],
+                ),
+                (
+                    "shot".to_string(),
+                    vec!["from".to_string(), "to".to_string(), "length".to_string()],
+                ),
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let result = value_to_xml_document(&data, "survey", "utf-8", &options).unwrap();
+        assert!(
+            result.ends_with(
+                "<survey><name>Main line</name><shot><from>A1</from><to>A2</to>\
+                 <length>4.5</length><azimuth>90</azimuth><comment>dry</comment></shot></survey>"
+            ),
+            "{}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_documents_without_root() {
+        let documents = [
+            "",
+            "  \n ",
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            "<!-- exported by Ariane -->",
+            "<?xml version=\"1.0\"?>\n<!-- a --><!-- b -->\n",
+        ];
+        let options = ParseOptions {
+            allow_empty: true,
+            ..Default::default()
+        };
+        for xml in documents {
+            assert!(
+                parse_xml(xml, &ParseOptions::default()).is_err(),
+                "{:?}",
+                xml
+            );
+            assert_eq!(parse_xml(xml, &options).unwrap(), json!({}), "{:?}", xml);
+        }
+        assert_eq!(
+            parse_xml("<!-- a --><a>1</a>", &options).unwrap(),
+            json!({"a": "1"})
+        );
+    }
+
+    #[test]
+    fn test_content_after_root() {
+        let options = ParseOptions::default();
+        for xml in ["<a>1</a><b>2</b>", "<a>1</a>junk", "<a/><a/>"] {
+            let err = parse_xml(xml, &options).unwrap_err();
+            assert!(matches!(err, XmlDictError::Parse(_)), "{}", err);
+        }
+        let err = parse_xml("<a>1</a>junk", &options).unwrap_err();
+        assert!(err.to_string().contains("junk"), "{}", err);
+        assert_eq!(
+            parse_xml("<a>1</a>\n<!-- end -->\n", &options).unwrap(),
+            json!({"a": "1"})
+        );
+    }
+
+    #[test]
+    fn test_fragment() {
+        let options = ParseOptions {
+            fragment: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml("<a>1</a>junk<b>2</b><a>3</a>", &options).unwrap(),
+            json!({"a": ["1", "3"], "b": "2"})
+        );
+    }
+
+    #[test]
+    fn test_xml_diff_identical_documents() {
+        let options = ParseOptions::default();
+        assert_eq!(xml_diff(SURVEY, SURVEY, &options).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_xml_diff_ignores_attribute_order_and_formatting() {
+        let a = r#"<shot id="1" unit="m"><from>A1</from><to>A2</to></shot>"#;
+        let b = "<shot unit='m' id='1'>\n  <to>A2</to>\n  <from>A1</from>\n</shot>";
+        assert_eq!(xml_diff(a, b, &ParseOptions::default()).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_xml_diff_changed_values() {
+        let changed = SURVEY
+            .replace("<to>A3</to>", "<to>A4</to>")
+            .replace(r#" unit="m""#, "");
+        let result = xml_diff(SURVEY, &changed, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                Difference {
+                    path: "survey/@unit".to_string(),
+                    left: Some(json!("m")),
+                    right: None,
+                },
+                Difference {
+                    path: "survey/shots/shot[2]/to".to_string(),
+                    left: Some(json!("A3")),
+                    right: Some(json!("A4")),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_empty_elements() {
+        let options = ParseOptions {
+            expand_empty_elements: true,
+            ..Default::default()
+        };
+        let expanded = r#"<shot><note></note><ref id="5"></ref></shot>"#;
+        let self_closing = r#"<shot><note/><ref id="5"/></shot>"#;
+        let expected = json!({"shot": {"note": {}, "ref": {"@id": "5"}}});
+        assert_eq!(parse_xml(expanded, &options).unwrap(), expected);
+        assert_eq!(parse_xml(self_closing, &options).unwrap(), expected);
+        assert_eq!(
+            parse_xml_reader(self_closing.as_bytes(), &options).unwrap(),
+            expected
+        );
+    }
+
+    // Endless `<survey><shot/><shot/>...` stream counting the bytes read
+    struct EndlessSurvey {
+        read: usize,
+    }
+
+    impl std::io::Read for EndlessSurvey {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let pattern: &[u8] = if self.read == 0 {
+                b"<survey>"
+            } else {
+                b"<shot/>"
+            };
+            let n = pattern.len().min(buf.len());
+            buf[..n].copy_from_slice(&pattern[..n]);
+            self.read += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let options = ParseOptions {
+            max_bytes: Some(4096),
+            ..Default::default()
+        };
+        let mut source = EndlessSurvey { read: 0 };
+        let err = parse_xml_reader(std::io::BufReader::new(&mut source), &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::LimitExceeded(_)), "{}", err);
+        assert!(source.read < 4096 + 8192, "read {} bytes", source.read);
+
+        let xml = "<survey><shot/></survey>";
+        let err = parse_xml(
+            xml,
+            &ParseOptions {
+                max_bytes: Some(10),
+                ..options.clone()
+            },
+        );
+        assert!(matches!(err, Err(XmlDictError::LimitExceeded(_))));
+        assert!(parse_xml(xml, &options).is_ok());
+        assert!(parse_xml_reader(xml.as_bytes(), &options).is_ok());
+    }
+
+    #[test]
+    fn test_nested_arrays_are_wrapped() {
+        let value = json!({"matrix": [[1, 2], [3, 4]]});
+        let mut writer = quick_xml::Writer::new(Vec::new());
+        value_to_xml(&value, "root", &mut writer, &SerializeOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "<root><matrix><matrix>1</matrix><matrix>2</matrix></matrix>\
+             <matrix><matrix>3</matrix><matrix>4</matrix></matrix></root>"
+        );
+
+        let options = SerializeOptions {
+            array_item_name: Some("row".to_string()),
+            ..Default::default()
+        };
+        let mut writer = quick_xml::Writer::new(Vec::new());
+        value_to_xml(&value, "root", &mut writer, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "<root><matrix><row><row>1</row><row>2</row></row>\
+             <row><row>3</row><row>4</row></row></matrix></root>"
+        );
+    }
+
+    #[test]
+    fn test_keep_declaration_round_trip() {
+        let xml =
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><survey><shot/></survey>"#;
+        let options = ParseOptions {
+            keep_declaration: true,
+            ..Default::default()
+        };
+        let document = parse_xml(xml, &options).unwrap();
+        assert_eq!(
+            document,
+            json!({
+                "#xml": {"version": "1.0", "encoding": "UTF-8", "standalone": "yes"},
+                "survey": {"shot": null}
+            })
+        );
+        assert_eq!(
+            document_to_xml(&document, &SerializeOptions::default()).unwrap(),
+            xml
+        );
+
+        // Discarded by default
+        let document = parse_xml(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(document, json!({"survey": {"shot": null}}));
+        assert_eq!(
+            document_to_xml(&document, &SerializeOptions::default()).unwrap(),
+            r#"<?xml version="1.0" encoding="utf-8"?><survey><shot/></survey>"#
+        );
+    }
+
+    #[test]
+    fn test_document_to_xml_rejects_invalid_documents() {
+        let write = |document: serde_json::Value| {
+            document_to_xml(&document, &SerializeOptions::default()).unwrap_err()
+        };
+        // The output is a UTF-8 string
+        let xml = b"<?xml version=\"1.0\" encoding=\"windows-1252\"?><survey>Caf\xe9</survey>";
+        let options = ParseOptions {
+            keep_declaration: true,
+            ..Default::default()
+        };
+        let document = parse_xml_bytes(xml, &options).unwrap();
+        let err = write(document);
+        assert!(matches!(err, XmlDictError::Write(_)), "{}", err);
+        assert!(err.to_string().contains("windows-1252"), "{}", err);
+
+        let err = write(json!({"#foo": "bar", "root": {}}));
+        assert!(err.to_string().contains("\"#foo\""), "{}", err);
+        let err = write(json!({"a": "1", "b": "2"}));
+        assert!(err.to_string().contains("exactly one root"), "{}", err);
+        let err = write(json!({"#comment": "only"}));
+        assert!(err.to_string().contains("exactly one root"), "{}", err);
+
+        assert_eq!(
+            document_to_xml(
+                &json!({"#comment": "c", "root": "1"}),
+                &SerializeOptions::default()
+            )
+            .unwrap(),
+            r#"<?xml version="1.0" encoding="utf-8"?><!--c--><root>1</root>"#
+        );
+    }
+
+    #[test]
+    fn test_pretty_indentation() {
+        let value = json!({"@unit": "m", "shot": [{"length": 1}, {"length": 2}]});
+        let serialize = |indent_char, indent_size| {
+            let options = SerializeOptions {
+                pretty: true,
+                indent_char,
+                indent_size,
+                ..Default::default()
+            };
+            value_to_xml_document(&value, "survey", "utf-8", &options).unwrap()
+        };
+
+        assert_eq!(
+            serialize('\t', 1),
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<survey unit=\"m\">\n\
+             \t<shot>\n\t\t<length>1</length>\n\t</shot>\n\
+             \t<shot>\n\t\t<length>2</length>\n\t</shot>\n</survey>"
+        );
+        assert_eq!(
+            serialize(' ', 4),
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<survey unit=\"m\">\n    \
+             <shot>\n        <length>1</length>\n    </shot>\n    \
+             <shot>\n        <length>2</length>\n    </shot>\n</survey>"
+        );
+
+        let options = SerializeOptions {
+            pretty: true,
+            indent_char: '-',
+            ..Default::default()
+        };
+        let err = value_to_xml_document(&value, "survey", "utf-8", &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::InvalidOption(_)));
+    }
+
+    #[test]
+    fn test_pretty_comments() {
+        let xml = concat!(
+            "<survey unit=\"m\"><!-- Main line --><name>Main</name>",
+            "<shot><!-- resurveyed --><!-- twice --><to>A1</to></shot>",
+            "<note>Flooded <!-- since 2024 -->after <b>rain</b><!-- end --></note>",
+            "</survey>"
+        );
+        let options = ParseOptions {
+            keep_comments: true,
+            ordered_content: true,
+            trim_whitespace: false,
+            ..Default::default()
+        };
+        let document = parse_xml(xml, &options).unwrap();
+        let pretty = SerializeOptions {
+            pretty: true,
+            ..Default::default()
+        };
+        let written = document_to_xml(&document, &pretty).unwrap();
+        assert_eq!(
+            written,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+             <survey unit=\"m\">\n  \
+               <!-- Main line -->\n  \
+               <name>Main</name>\n  \
+               <shot>\n    \
+                 <!-- resurveyed -->\n    \
+                 <!-- twice -->\n    \
+                 <to>A1</to>\n  \
+               </shot>\n  \
+               <note>Flooded <!-- since 2024 -->after <b>rain</b><!-- end --></note>\n\
+             </survey>"
+        );
+        // Indentation only goes where whitespace is not content
+        assert_eq!(parse_xml(&written, &options).unwrap(), document);
+
+        // Comments of the simple representation stay inline with text too
+        let value = json!({"#text": "Main", "#comment": "renamed"});
+        assert!(value_to_xml_document(&value, "name", "utf-8", &pretty)
+            .unwrap()
+            .ends_with("\n<name>Main<!--renamed--></name>"));
+    }
+
+    #[test]
+    fn test_strict_coercion() {
+        let options = ParseOptions {
+            coerce_types: true,
+            strict_coercion: true,
+            ..Default::default()
+        };
+        let clean = r#"<survey date="2024-01-05"><shot length="12.5"><from>1</from><to>A2</to></shot></survey>"#;
+        assert_eq!(
+            parse_xml(clean, &options).unwrap(),
+            json!({"survey": {"@date": "2024-01-05", "shot": {"@length": 12.5, "from": 1, "to": "A2"}}})
+        );
+
+        let err = parse_xml(
+            "<survey><shot><length>1,5</length></shot></survey>",
+            &options,
+        )
+        .unwrap_err();
+        match err {
+            XmlDictError::AmbiguousNumber { path, text } => {
+                assert_eq!(path, "survey/shot/length");
+                assert_eq!(text, "1,5");
+            }
+            other => panic!("unexpected error {}", other),
+        }
+        let err = parse_xml(r#"<survey><shot id="007"/></survey>"#, &options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Ambiguous numeric value "007" at survey/shot/@id"#
+        );
+
+        // Lenient coercion keeps such values as strings
+        let lenient = ParseOptions {
+            strict_coercion: false,
+            ..options
+        };
+        let value = parse_xml(r#"<survey><shot id="007"/></survey>"#, &lenient).unwrap();
+        assert_eq!(value, json!({"survey": {"shot": {"@id": "007"}}}));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_attr_preserve_pattern() {
+        let xml = r#"<shot id="007" color="1E3" depth="0.50" count="12"><code>1E3</code></shot>"#;
+        let options = ParseOptions {
+            coerce_types: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"shot": {"@id": "007", "@color": 1000.0, "@depth": 0.5, "@count": 12, "code": 1000.0}})
+        );
+
+        let options = ParseOptions {
+            attr_preserve_pattern: Some(regex::Regex::new(r"^0\d|\d0$|^[0-9A-F]+E").unwrap()),
+            strict_coercion: true,
+            ..options
+        };
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"shot": {"@id": "007", "@color": "1E3", "@depth": "0.50", "@count": 12, "code": 1000.0}})
+        );
+    }
+
+    #[test]
+    fn test_parse_xml_bytes() {
+        let xml = "<survey><name>Grotte é</name></survey>";
+        assert_eq!(
+            parse_xml_bytes(xml.as_bytes(), &ParseOptions::default()).unwrap(),
+            parse_xml(xml, &ParseOptions::default()).unwrap()
+        );
+        // Invalid UTF-8 goes through the decoding fallback
+        let value = parse_xml_bytes(INVALID_UTF8, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            value,
+            parse_xml_reader(INVALID_UTF8, &ParseOptions::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_lenient_attributes() {
+        let xml = "<form><input disabled checked type=text/></form>";
+        let options = ParseOptions {
+            lenient_attributes: true,
+            errors: DecodeErrors::Strict,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"form": {"input": {"@disabled": "", "@checked": "", "@type": "text"}}})
+        );
+
+        let strict = ParseOptions {
+            lenient_attributes: false,
+            ..options
+        };
+        assert!(parse_xml(xml, &strict).is_err());
+    }
+
+    #[test]
+    fn test_lenient_html() {
+        let xml = concat!(
+            "<form action=/survey?cave=1&amp;unit=m><input type=checkbox checked disabled/>",
+            "<label title='A&nbsp;&#x42;'>Depth &gt; 10&nbsp;m & rising &copy</label></form>"
+        );
+        let options = ParseOptions {
+            lenient_html: true,
+            errors: DecodeErrors::Strict,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"form": {
+                "@action": "/survey?cave=1&unit=m",
+                "input": {"@type": "checkbox", "@checked": "", "@disabled": ""},
+                "label": {"@title": "A\u{a0}B", "#text": "Depth > 10\u{a0}m & rising &copy"},
+            }})
+        );
+
+        let strict = ParseOptions {
+            lenient_html: false,
+            ..options
+        };
+        assert!(parse_xml(xml, &strict).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_policy() {
+        let xml = "<survey><shot>A1</shot><name>Main</name><shot>A2</shot></survey>";
+        let parse = |duplicate_policy, force_list: &[&str]| {
+            let options = ParseOptions {
+                duplicate_policy,
+                force_list: force_list.iter().map(|s| s.to_string()).collect(),
+                ..Default::default()
+            };
+            parse_xml(xml, &options)
+        };
+
+        assert_eq!(
+            parse(DuplicatePolicy::Array, &[]).unwrap(),
+            json!({"survey": {"shot": ["A1", "A2"], "name": "Main"}})
+        );
+        assert_eq!(
+            parse(DuplicatePolicy::LastWins, &[]).unwrap(),
+            json!({"survey": {"shot": "A2", "name": "Main"}})
+        );
+        assert_eq!(
+            parse(DuplicatePolicy::FirstWins, &[]).unwrap(),
+            json!({"survey": {"shot": "A1", "name": "Main"}})
+        );
+        assert_eq!(
+            parse(DuplicatePolicy::LastWins, &["shot"]).unwrap(),
+            json!({"survey": {"shot": ["A2"], "name": "Main"}})
+        );
+        let err = parse(DuplicatePolicy::Error, &[]).unwrap_err();
+        assert!(err.to_string().contains("<shot> is repeated"), "{}", err);
+
+        assert_eq!(
+            "last-wins".parse::<DuplicatePolicy>().unwrap(),
+            DuplicatePolicy::LastWins
+        );
+        assert!("last".parse::<DuplicatePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_control_char_policy() {
+        let serialize = |value: &serde_json::Value, control_char_policy| {
+            let options = SerializeOptions {
+                control_char_policy,
+                ..Default::default()
+            };
+            let mut writer = quick_xml::Writer::new(Vec::new());
+            value_to_xml(value, "note", &mut writer, &options)
+                .map(|_| String::from_utf8(writer.into_inner()).unwrap())
+        };
+        let feed = json!({"@page": "1\u{c}2", "#text": "a\u{c}b<"});
+        let nul = json!({"#text": "a\u{0}b"});
+
+        assert!(serialize(&feed, ControlCharPolicy::Reject).is_err());
+        assert!(serialize(&nul, ControlCharPolicy::Reject).is_err());
+
+        assert_eq!(
+            serialize(&feed, ControlCharPolicy::Strip).unwrap(),
+            r#"<note page="12">ab&lt;</note>"#
+        );
+        assert_eq!(
+            serialize(&nul, ControlCharPolicy::Strip).unwrap(),
+            "<note>ab</note>"
+        );
+
+        assert_eq!(
+            serialize(&feed, ControlCharPolicy::NumericEscape).unwrap(),
+            r#"<note page="1&#xC;2">a&#xC;b&lt;</note>"#
+        );
+        let err = serialize(&nul, ControlCharPolicy::NumericEscape).unwrap_err();
+        assert!(err.to_string().contains("U+0000"), "{}", err);
+
+        // Tabs and line breaks are legal and kept
+        assert_eq!(
+            serialize(&json!("a\tb\n"), ControlCharPolicy::Reject).unwrap(),
+            "<note>a\tb\n</note>"
+        );
+    }
+
+    // Sink recording the size of each write it receives
+    #[derive(Default)]
+    struct RecordingSink {
+        data: Vec<u8>,
+        writes: Vec<usize>,
+    }
+
+    impl std::io::Write for RecordingSink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.data.extend_from_slice(buf);
+            self.writes.push(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_value_to_xml_writer_flushes() {
+        let shots: Vec<_> = (0..2000)
+            .map(|i| json!({"@id": i, "length": i as f64 / 4.0}))
+            .collect();
+        let value = json!({"shot": shots});
+        let options = SerializeOptions {
+            flush_bytes: 256,
+            ..Default::default()
+        };
+
+        let mut sink = RecordingSink::default();
+        value_to_xml_writer(&value, "survey", &mut sink, &options).unwrap();
+        let expected = value_to_xml_document(&value, "survey", "utf-8", &options).unwrap();
+        assert_eq!(String::from_utf8(sink.data).unwrap(), expected);
+        assert!(sink.writes.len() > expected.len() / 256);
+        assert!(sink.writes.iter().all(|&n| n <= 256), "{:?}", sink.writes);
+    }
+
+    #[test]
+    fn test_comment_position_in_ordered_content() {
+        let xml = "<survey><shot>A1</shot><!-- resurveyed --><shot>A2</shot></survey>";
+        let options = ParseOptions {
+            keep_comments: true,
+            ordered_content: true,
+            ..Default::default()
+        };
+        let value = parse_xml(xml, &options).unwrap();
+        assert_eq!(
+            value,
+            json!({"survey": {"#content": [
+                {"shot": "A1"},
+                {"#comment": " resurveyed "},
+                {"shot": "A2"}
+            ]}})
+        );
+
+        let mut writer = quick_xml::Writer::new(Vec::new());
+        value_to_xml(
+            &value["survey"],
+            "survey",
+            &mut writer,
+            &SerializeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(writer.into_inner()).unwrap(), xml);
+
+        // Without ordered_content, comments are gathered apart
+        let options = ParseOptions {
+            ordered_content: false,
+            ..options
+        };
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"survey": {"shot": ["A1", "A2"], "#comment": " resurveyed "}})
+        );
+        assert_eq!(
+            parse_xml(xml, &ParseOptions::default()).unwrap(),
+            json!({"survey": {"shot": ["A1", "A2"]}})
+        );
+    }
+
+    #[test]
+    fn test_empty_attr_as_none() {
+        let xml = r#"<station name="" depth="3"/>"#;
+        assert_eq!(
+            parse_xml(xml, &ParseOptions::default()).unwrap(),
+            json!({"station": {"@name": "", "@depth": "3"}})
+        );
+        let options = ParseOptions {
+            empty_attr_as_none: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"station": {"@name": null, "@depth": "3"}})
+        );
+    }
+
+    #[test]
+    fn test_ignore_attributes() {
+        let xml = r#"<survey unit="m"><shot id="1"><length unit="ft">5</length></shot><shot id="2"/></survey>"#;
+        let options = ParseOptions {
+            ignore_attributes: true,
+            ..Default::default()
+        };
+        let (value, stats) = parse_xml_with_stats(xml, &options).unwrap();
+        assert_eq!(value, json!({"survey": {"shot": [{"length": "5"}, null]}}));
+        assert_eq!(stats.attributes, 0);
+    }
+
+    #[test]
+    fn test_parse_elements_in_two_passes() {
+        let xml = "<survey>\n  <shot id=\"1\"/>\n  <name>Main</name>\n  <shot id=\"2\"/>\n  \
+                   <shot id=\"3\"><from>A3</from></shot>\n  <shot>é</shot>\n</survey>";
+        let options = ParseOptions::default();
+        let full = parse_xml(xml, &options).unwrap();
+
+        let start = "<survey>".len();
+        let (first, next) = parse_elements(xml, start, 2, &options).unwrap();
+        assert_eq!(first, json!({"shot": {"@id": "1"}, "name": "Main"}));
+        let (second, next) = parse_elements(xml, next.unwrap(), 10, &options).unwrap();
+        assert_eq!(next, None);
+
+        let mut shots = vec![first["shot"].clone()];
+        shots.extend(second["shot"].as_array().unwrap().iter().cloned());
+        assert_eq!(
+            json!({"survey": {"shot": shots, "name": first["name"]}}),
+            full
+        );
+
+        assert!(parse_elements(xml, start + 4, 1, &options).is_err());
+        assert!(parse_elements(xml, xml.len() + 1, 1, &options).is_err());
+    }
+
+    #[test]
+    fn test_xml_to_flat() {
+        let xml = r#"<survey unit="m"><ns.name>Main</ns.name><shots>
+            <shot id="1"><from>A1</from></shot>
+            <shot id="2">A2<note/></shot>
+            <empty></empty>
+        </shots></survey>"#;
+        let flat = xml_to_flat(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            serde_json::Value::Object(flat),
+            json!({
+                "survey.@unit": "m",
+                "survey.ns\\.name": "Main",
+                "survey.shots.shot.0.@id": "1",
+                "survey.shots.shot.0.from": "A1",
+                "survey.shots.shot.1.@id": "2",
+                "survey.shots.shot.1.#text": "A2",
+                "survey.shots.shot.1.note": null,
+                "survey.shots.empty": {},
+            })
+        );
+        assert_eq!(
+            flatten(&json!([])),
+            json!({"": []}).as_object().unwrap().clone()
+        );
+    }
+
+    #[test]
+    fn test_flat_round_trip() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?><survey unit="m"><ns.name>Main</ns.name><shots><empty/><shot id="1"><from>A1</from></shot><shot id="2">A2<note/></shot></shots></survey>"#;
+        let parsed = parse_xml(xml, &ParseOptions::default()).unwrap();
+        let flat = xml_to_flat(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(unflatten(&flat).unwrap(), parsed);
+        assert_eq!(
+            flat_to_xml(&flat, &SerializeOptions::default()).unwrap(),
+            xml
+        );
+
+        // Siblings keep their document order, not an alphabetical one
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?><root><zeta>1</zeta><alpha z="1" a="2"/><mid><b>1</b><a>2</a></mid></root>"#;
+        let flat = xml_to_flat(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            flat_to_xml(&flat, &SerializeOptions::default()).unwrap(),
+            xml
+        );
+    }
+
+    #[test]
+    fn test_unflatten_conflicts() {
+        let flat = |value: serde_json::Value| value.as_object().unwrap().clone();
+        let err = unflatten(&flat(json!({"a.b": "1", "a.b.c": "2"}))).unwrap_err();
+        assert!(matches!(err, XmlDictError::InvalidPath(_)), "{}", err);
+        let err = unflatten(&flat(json!({"a.0": "1", "a.b": "2"}))).unwrap_err();
+        assert!(matches!(err, XmlDictError::InvalidPath(_)), "{}", err);
+        let err = unflatten(&flat(json!({"a.0": "1", "a.2": "2"}))).unwrap_err();
+        assert!(err.to_string().contains("no item 1"), "{}", err);
+    }
+
+    #[test]
+    fn test_typed_path_accessors() {
+        let xml = concat!(
+            "<survey><shot id=\"1\"><length unit=\"m\">3.5</length><clino>-12</clino></shot>",
+            "<shot id=\"18446744073709551615\"><length>12</length><note>wet</note></shot>",
+            "<ns.name>7</ns.name></survey>"
+        );
+        for coerce_types in [false, true] {
+            let options = ParseOptions {
+                coerce_types,
+                ..Default::default()
+            };
+            let document = parse_xml(xml, &options).unwrap();
+            assert_eq!(as_f64_path(&document, "survey.shot.0.length"), Some(3.5));
+            assert_eq!(as_i64_path(&document, "survey.shot.0.length"), None);
+            assert_eq!(as_i64_path(&document, "survey.shot.0.clino"), Some(-12));
+            assert_eq!(as_u64_path(&document, "survey.shot.0.clino"), None);
+            assert_eq!(as_i64_path(&document, "survey.shot.0.@id"), Some(1));
+            assert_eq!(as_f64_path(&document, "survey.shot.1.length"), Some(12.0));
+            assert_eq!(as_i64_path(&document, "survey.ns\\.name"), Some(7));
+            assert_eq!(as_i64_path(&document, "survey.shot.1.note"), None);
+            assert_eq!(as_i64_path(&document, "survey.shot.2.length"), None);
+            assert_eq!(as_i64_path(&document, "survey.cave"), None);
+        }
+
+        let document = json!({"shot": {"@id": u64::MAX, "length": 12}});
+        assert_eq!(as_u64_path(&document, "shot.@id"), Some(u64::MAX));
+        assert_eq!(as_i64_path(&document, "shot.@id"), None);
+        assert_eq!(get_path(&document, "shot.length"), Some(&json!(12)));
+        assert_eq!(get_path(&document, ""), Some(&document));
+    }
+
+    #[test]
+    fn test_null_attributes() {
+        let value = json!({"@id": null, "@unit": "m", "station": {"@name": null}});
+        let omitted =
+            value_to_xml_document(&value, "shot", "utf-8", &SerializeOptions::default()).unwrap();
+        assert!(
+            omitted.ends_with("<shot unit=\"m\"><station/></shot>"),
+            "{}",
+            omitted
+        );
+
+        let options = SerializeOptions {
+            none_attr_as_empty: true,
+            ..Default::default()
+        };
+        let emitted = value_to_xml_document(&value, "shot", "utf-8", &options).unwrap();
+        assert!(
+            emitted.ends_with("<shot id=\"\" unit=\"m\"><station name=\"\"/></shot>"),
+            "{}",
+            emitted
+        );
+
+        // Round trip of empty attributes read as `null`
+        let parsed = parse_xml(
+            &emitted,
+            &ParseOptions {
+                empty_attr_as_none: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(parsed["shot"], value);
+    }
+
+    #[test]
+    fn test_index_attribute_restores_order() {
+        let value = json!({
+            "shot": ["A1", null, {"@id": "x", "to": "B2"}, "A4", "A5", "A6", "A7", "A8", "A9", "A10", "A11"],
+            "station": "A0",
+        });
+        let options = SerializeOptions {
+            index_attribute: Some("index".to_string()),
+            ..Default::default()
+        };
+        let xml = value_to_xml_document(&value, "survey", "utf-8", &options).unwrap();
+        assert!(
+            xml.contains("<shot index=\"0\">A1</shot><shot index=\"1\"/>"),
+            "{}",
+            xml
+        );
+
+        // An intermediary reversing the shots, keeping their attributes
+        let mut scrambled = parse_xml(&xml, &ParseOptions::default()).unwrap();
+        scrambled["survey"]["shot"]
+            .as_array_mut()
+            .unwrap()
+            .reverse();
+        let scrambled =
+            value_to_xml_document(&scrambled["survey"], "survey", "utf-8", &Default::default())
+                .unwrap();
+        assert!(
+            scrambled.contains("<shot index=\"10\">A11</shot>"),
+            "{}",
+            scrambled
+        );
+
+        let honor = ParseOptions {
+            honor_index: Some("index".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(parse_xml(&scrambled, &honor).unwrap()["survey"], value);
+        // Indices are compared as numbers, also once coerced
+        let coerced = ParseOptions {
+            coerce_types: true,
+            ..honor.clone()
+        };
+        assert_eq!(
+            parse_xml(&scrambled, &coerced).unwrap()["survey"]["shot"][10],
+            json!("A11")
+        );
+
+        let err = parse_xml("<r><a index=\"x\"/><a index=\"1\"/></r>", &honor).unwrap_err();
+        assert!(err.to_string().contains("@index"), "{}", err);
+    }
+
+    #[test]
+    fn test_track_positions() {
+        let xml = "<survey unit=\"m\">\n  <shot id=\"1\">A1</shot>\n  <flag/>\n</survey>";
+        let options = ParseOptions {
+            track_positions: true,
+            ..Default::default()
+        };
+        let document = parse_xml(xml, &options).unwrap();
+        let source = |value: &serde_json::Value| {
+            let pos = value["#pos"].as_array().unwrap();
+            &xml[pos[0].as_u64().unwrap() as usize..pos[1].as_u64().unwrap() as usize]
+        };
+        assert_eq!(source(&document["survey"]), xml);
+        assert_eq!(
+            source(&document["survey"]["shot"]),
+            "<shot id=\"1\">A1</shot>"
+        );
+        assert_eq!(document["survey"]["shot"]["#text"], "A1");
+        assert_eq!(source(&document["survey"]["flag"]), "<flag/>");
+        let flag = xml.find("<flag/>").unwrap();
+        assert_eq!(
+            document["survey"]["flag"],
+            json!({"#pos": [flag, flag + 7]})
+        );
+
+        // Offsets are the same through a reader, and ignored when writing
+        let buffered = ParseOptions {
+            parse_path: ParsePath::Buffered,
+            ..options.clone()
+        };
+        assert_eq!(parse_xml(xml, &buffered).unwrap(), document);
+        let written =
+            value_to_xml_document(&document["survey"], "survey", "utf-8", &Default::default())
+                .unwrap();
+        assert!(
+            written.ends_with("<survey unit=\"m\"><shot id=\"1\">A1</shot><flag/></survey>"),
+            "{}",
+            written
+        );
+
+        // `parse_elements` gives offsets in the whole document
+        let offset = xml.find("<shot").unwrap();
+        let (records, _) = parse_elements(xml, offset, 1, &options).unwrap();
+        assert_eq!(source(&records["shot"]), "<shot id=\"1\">A1</shot>");
+    }
+
+    #[test]
+    fn test_strip_root_attributes() {
+        let xml = "<survey unit=\"m\" name=\"Main\"><shot>A1</shot><station/></survey>";
+        let stripped = ParseOptions {
+            strip_root: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml(xml, &stripped).unwrap(),
+            json!({"shot": "A1", "station": null})
+        );
+
+        let relocated = ParseOptions {
+            root_attr_key: Some("survey".to_string()),
+            ..stripped.clone()
+        };
+        assert_eq!(
+            parse_xml(xml, &relocated).unwrap(),
+            json!({"survey": {"@name": "Main", "@unit": "m"}, "shot": "A1", "station": null})
+        );
+        // Nothing to relocate without attributes, and text-only roots collapse
+        assert_eq!(
+            parse_xml("<survey><shot/></survey>", &relocated).unwrap(),
+            json!({"shot": null})
+        );
+        assert_eq!(
+            parse_xml("<name unit=\"m\">Main</name>", &stripped).unwrap(),
+            json!("Main")
+        );
+
+        let clash = ParseOptions {
+            root_attr_key: Some("shot".to_string()),
+            ..stripped.clone()
+        };
+        let err = parse_xml(xml, &clash).unwrap_err();
+        assert!(err.to_string().contains("root_attr_key"), "{}", err);
+
+        // Paths still start at the root element
+        assert_eq!(
+            extract_path(xml, "survey/shot", &stripped).unwrap(),
+            Some(json!("A1"))
+        );
+        assert_eq!(
+            xml_find_all(xml, "survey/@unit", &stripped).unwrap(),
+            [json!("m")]
+        );
+    }
+
+    #[test]
+    fn test_element_and_attribute_case() {
+        let xml = concat!(
+            "<Survey Unit=\"m\" xml:space=\"preserve\" xmlns:GML=\"urn:gml\">",
+            "<Shot ID=\"1\" fromStation=\"A0\"> A1 </Shot>",
+            "</Survey>"
+        );
+        let parse = |element_case, attr_case| {
+            let options = ParseOptions {
+                element_case,
+                attr_case,
+                ..Default::default()
+            };
+            parse_xml(xml, &options).unwrap()
+        };
+
+        assert_eq!(
+            parse(NameCase::Preserve, NameCase::Lower),
+            json!({"Survey": {
+                "@unit": "m", "@xml:space": "preserve", "@xmlns:GML": "urn:gml",
+                "Shot": {"@id": "1", "@fromstation": "A0", "#text": " A1 "},
+            }})
+        );
+        assert_eq!(
+            parse(NameCase::Lower, NameCase::Preserve),
+            json!({"survey": {
+                "@Unit": "m", "@xml:space": "preserve", "@xmlns:GML": "urn:gml",
+                "shot": {"@ID": "1", "@fromStation": "A0", "#text": " A1 "},
+            }})
+        );
+        assert_eq!(
+            parse(NameCase::Upper, NameCase::Lower),
+            json!({"SURVEY": {
+                "@unit": "m", "@xml:space": "preserve", "@xmlns:GML": "urn:gml",
+                "SHOT": {"@id": "1", "@fromstation": "A0", "#text": " A1 "},
+            }})
+        );
+        assert_eq!(
+            parse(NameCase::Preserve, NameCase::Preserve),
+            parse_xml(xml, &Default::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_record_writer_streams_records() {
+        let records: Vec<serde_json::Value> = (0..10_000)
+            .map(|i| json!({"@id": i.to_string(), "to": format!("A{}", i)}))
+            .collect();
+        let options = SerializeOptions {
+            flush_bytes: 4096,
+            ..Default::default()
+        };
+        let mut writer = RecordWriter::new(Vec::new(), "survey", "shot", &options).unwrap();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        let xml = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert!(
+            xml.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?><survey><shot id=\"0\">")
+        );
+
+        let parsed = parse_xml(&xml, &ParseOptions::default()).unwrap();
+        assert_eq!(parsed["survey"]["shot"], serde_json::Value::Array(records));
+
+        // No records still make a document
+        let writer = RecordWriter::new(Vec::new(), "survey", "shot", &options).unwrap();
+        let empty = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert!(empty.ends_with("<survey></survey>"), "{}", empty);
+    }
+
+    #[test]
+    fn test_self_closing_only_for_empty_elements() {
+        let options = SerializeOptions::default();
+        for (value, expected) in [
+            (json!({"a": {}}), "<r><a/></r>"),
+            (json!({"a": null}), "<r><a/></r>"),
+            (json!({"a": {"#text": null}}), "<r><a/></r>"),
+            (json!({"a": {"#text": ""}}), "<r><a></a></r>"),
+            (json!({"a": ""}), "<r><a></a></r>"),
+            (
+                json!({"a": {"@id": "1", "#text": ""}}),
+                "<r><a id=\"1\"></a></r>",
+            ),
+        ] {
+            let mut writer = quick_xml::Writer::new(Vec::new());
+            value_to_xml(&value, "r", &mut writer, &options).unwrap();
+            assert_eq!(
+                String::from_utf8(writer.into_inner()).unwrap(),
+                expected,
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let value = json!({
+            "@z": "1", "@a": "2",
+            "to": "A2", "from": "A1", "shot": [{"id": "b"}, {"id": "a"}]
+        });
+        // Keys are written in their order by default
+        let mut writer = quick_xml::Writer::new(Vec::new());
+        value_to_xml(&value, "leg", &mut writer, &SerializeOptions::default()).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            r#"<leg z="1" a="2"><to>A2</to><from>A1</from><shot><id>b</id></shot><shot><id>a</id></shot></leg>"#
+        );
+
+        let options = SerializeOptions {
+            sort_keys: true,
+            sort_attributes: true,
+            ..Default::default()
+        };
+        let mut writer = quick_xml::Writer::new(Vec::new());
+        value_to_xml(&value, "leg", &mut writer, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            r#"<leg a="2" z="1"><from>A1</from><shot><id>b</id></shot><shot><id>a</id></shot><to>A2</to></leg>"#
+        );
+    }
+
+    #[test]
+    fn test_canonical_emission_order() {
+        let value = json!({
+            "@xmlns": {"gml": "http://www.opengis.net/gml", "": "urn:survey"},
+            "@unit": "m", "@gml:id": "s1",
+            "#content": ["Main ", {"to": "A2"}, " line", {"from": "A1"}],
+            "shot": [{"@id": "2", "to": "A3", "from": "A2"}],
+        });
+        let options = SerializeOptions {
+            canonical: true,
+            sort_keys: true,
+            element_order: [("shot".to_string(), vec!["to".to_string()])].into(),
+            ..Default::default()
+        };
+        let xml = value_to_xml_bytes(&value, "survey", "utf-8", &options).unwrap();
+        assert_eq!(
+            xml,
+            b"<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+              <survey xmlns=\"urn:survey\" xmlns:gml=\"http://www.opengis.net/gml\" \
+              gml:id=\"s1\" unit=\"m\">Main <to>A2</to> line<from>A1</from>\
+              <shot id=\"2\"><to>A3</to><from>A2</from></shot></survey>"
+        );
+    }
+
+    #[test]
+    fn test_canonical_keeps_document_order() {
+        let xml = "<survey unit=\"m\" name=\"Cave\"><station>A2</station>\
+                   <shot to=\"A1\" from=\"A0\"><length>3.5</length><azimuth>120</azimuth></shot>\
+                   <shot to=\"A2\" from=\"A1\"/><comment>wet</comment></survey>";
+        let document = parse_xml(xml, &ParseOptions::default()).unwrap();
+        let options = SerializeOptions {
+            canonical: true,
+            ..Default::default()
+        };
+        let written = value_to_xml_string(&document["survey"], "survey", &options).unwrap();
+        assert_eq!(
+            written,
+            "<survey name=\"Cave\" unit=\"m\"><station>A2</station>\
+             <shot from=\"A0\" to=\"A1\"><length>3.5</length><azimuth>120</azimuth></shot>\
+             <shot from=\"A1\" to=\"A2\"/><comment>wet</comment></survey>"
+        );
+    }
+
+    #[test]
+    fn test_flat_attributes_match_nested_notation() {
+        let nested = json!({
+            "length": {"#text": 5, "@unit": "m"},
+            "station": [{"@survey": "S"}, {"#text": "A2", "@survey": "S"}],
+            "note": {"@lang": "fr"},
+        });
+        let flat = json!({
+            "length": 5, "length@unit": "m",
+            "station": [null, "A2"], "station@survey": "S",
+            "note@lang": "fr",
+        });
+        let options = SerializeOptions {
+            flat_attributes: true,
+            ..Default::default()
+        };
+        let expected = value_to_xml_document(&nested, "shot", "utf-8", &options).unwrap();
+        assert!(
+            expected.ends_with(
+                "<shot><length unit=\"m\">5</length><station survey=\"S\"/>\
+                 <station survey=\"S\">A2</station><note lang=\"fr\"/></shot>"
+            ),
+            "{}",
+            expected
+        );
+        assert_eq!(
+            value_to_xml_document(&flat, "shot", "utf-8", &options).unwrap(),
+            expected
+        );
+        // `child@attr` keys may come ahead of `child`
+        let flat = json!({
+            "length@unit": "m", "length": 5,
+            "station@survey": "S", "station": [null, "A2"],
+            "note@lang": "fr",
+        });
+        assert_eq!(
+            value_to_xml_document(&flat, "shot", "utf-8", &options).unwrap(),
+            expected
+        );
+
+        // Without the option, `@` stays part of the element name
+        let written =
+            value_to_xml_document(&flat, "shot", "utf-8", &SerializeOptions::default()).unwrap();
+        assert!(
+            written.contains("<length@unit>m</length@unit>"),
+            "{}",
+            written
+        );
+    }
+
+    #[test]
+    fn test_presize_output_is_identical() {
+        let shots: String = (0..50)
+            .map(|i| format!(r#"<Shot id="{i}" note="a > b"><Length>{i}.5</Length></Shot>"#))
+            .collect();
+        let xml = format!(
+            "<?xml version=\"1.0\"?><!-- <Shot> --><Survey><![CDATA[<Shot/>]]>{shots}<Station/>\
+             <Deep><A><B><C>1</C></B></A></Deep></Survey>"
+        );
+        for base in [
+            ParseOptions::default(),
+            ParseOptions {
+                ordered_content: true,
+                ..Default::default()
+            },
+            ParseOptions {
+                force_list: ["Station".to_string()].into_iter().collect(),
+                ..Default::default()
+            },
+        ] {
+            let presized = ParseOptions {
+                presize: true,
+                ..base.clone()
+            };
+            assert_eq!(
+                parse_xml_with_stats(&xml, &presized).unwrap(),
+                parse_xml_with_stats(&xml, &base).unwrap()
+            );
+        }
+        let fragment = ParseOptions {
+            fragment: true,
+            presize: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml("<a><b/></a><a/>", &fragment).unwrap(),
+            json!({"a": [{"b": null}, null]})
+        );
+    }
+
+    #[test]
+    fn test_root_text_only() {
+        let options = ParseOptions::default();
+        for xml in [
+            "<a>hello</a>",
+            "<a>\n    hello\n</a>",
+            "  <a> hello </a>\n",
+            "<?xml version=\"1.0\"?>\n<a>hello</a>",
+            "<a>hel<!-- split -->lo</a>",
+            "<a>hel<?pi?>lo</a>",
+        ] {
+            assert_eq!(
+                parse_xml(xml, &options).unwrap(),
+                json!({"a": "hello"}),
+                "{}",
+                xml
+            );
+        }
+        assert_eq!(
+            parse_xml("<a id=\"1\">\n  hello\n</a>", &options).unwrap(),
+            json!({"a": {"@id": "1", "#text": "hello"}})
+        );
+        assert_eq!(
+            parse_xml("<a>\n  </a>", &options).unwrap(),
+            json!({"a": {}})
+        );
+
+        let raw = ParseOptions {
+            trim_whitespace: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml("<a> hel<!-- split -->lo </a>", &raw).unwrap(),
+            json!({"a": " hello "})
+        );
+        let coerced = ParseOptions {
+            coerce_types: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml("<a>1<!---->2</a>", &coerced).unwrap(),
+            json!({"a": 12})
+        );
+    }
+
+    #[test]
+    fn test_text_split_by_kept_comment() {
+        let options = ParseOptions {
+            keep_comments: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml("<a>hel<!--c-->lo</a>", &options).unwrap(),
+            json!({"a": {"#comment": "c", "#text": "hello"}})
+        );
+        let ordered = ParseOptions {
+            ordered_content: true,
+            ..options
+        };
+        assert_eq!(
+            parse_xml("<a>hel<!--c-->lo</a>", &ordered).unwrap(),
+            json!({"a": {"#content": ["hel", {"#comment": "c"}, "lo"]}})
+        );
+    }
+
+    #[test]
+    fn test_pretty_crlf_line_endings() {
+        let value = json!({"shot": {"note": "line 1\nline 2", "length": 1}});
+        let options = SerializeOptions {
+            pretty: true,
+            line_ending: "\r\n".to_string(),
+            ..Default::default()
+        };
+        let xml = value_to_xml_document(&value, "survey", "utf-8", &options).unwrap();
+        assert_eq!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\r\n<survey>\r\n  <shot>\r\n    \
+             <note>line 1\nline 2</note>\r\n    <length>1</length>\r\n  </shot>\r\n</survey>"
+        );
+
+        let options = SerializeOptions {
+            line_ending: "\n\r".to_string(),
+            ..options
+        };
+        let err = value_to_xml_document(&value, "survey", "utf-8", &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::InvalidOption(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_processing_instructions_after_declaration() {
+        let options = SerializeOptions {
+            processing_instructions: vec![(
+                "xml-stylesheet".to_string(),
+                r#"type="text/xsl" href="survey.xsl""#.to_string(),
+            )],
+            ..Default::default()
+        };
+        let xml =
+            value_to_xml_document(&json!({"@unit": "m"}), "survey", "utf-8", &options).unwrap();
+        assert_eq!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+             <?xml-stylesheet type=\"text/xsl\" href=\"survey.xsl\"?>\
+             <survey unit=\"m\"/>"
+        );
+
+        let options = SerializeOptions {
+            processing_instructions: vec![("xml-stylesheet".to_string(), "a?>b".to_string())],
+            ..Default::default()
+        };
+        let err = value_to_xml_document(&json!({}), "survey", "utf-8", &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::InvalidOption(_)), "{}", err);
+    }
+
+    #[test]
+    fn test_cdata_marker() {
+        let xml = "<script>if (a &lt; b) <![CDATA[{ run(\"<b>\"); }]]> end</script>";
+        let merged = parse_xml(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            merged,
+            json!({"script": "if (a < b) { run(\"<b>\"); } end"})
+        );
+
+        let options = ParseOptions {
+            cdata_marker: true,
+            ..Default::default()
+        };
+        let value = parse_xml(xml, &options).unwrap();
+        assert_eq!(
+            value,
+            json!({"script": {"#text": "if (a < b) { run(\"<b>\"); } end", "#is_cdata": true}})
+        );
+        let written =
+            value_to_xml_string(&value["script"], "script", &SerializeOptions::default()).unwrap();
+        assert_eq!(
+            written,
+            "<script><![CDATA[if (a < b) { run(\"<b>\"); } end]]></script>"
+        );
+        assert_eq!(parse_xml(&written, &options).unwrap(), value);
+
+        // `]]>` cannot appear in a section, which is split around it
+        let value = json!({"#text": "a]]>b", "#is_cdata": true});
+        let written = value_to_xml_string(&value, "t", &SerializeOptions::default()).unwrap();
+        assert_eq!(written, "<t><![CDATA[a]]]]><![CDATA[>b]]></t>");
+        assert_eq!(parse_xml(&written, &options).unwrap(), json!({"t": value}));
+
+        // Text without CDATA gets no marker
+        assert_eq!(
+            parse_xml("<t>plain</t>", &options).unwrap(),
+            json!({"t": "plain"})
+        );
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let options = SerializeOptions {
+            namespaces: HashMap::from([
+                (
+                    "soap".to_string(),
+                    "http://schemas.xmlsoap.org/soap/envelope/".to_string(),
+                ),
+                ("m".to_string(), "urn:survey".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let envelope = json!({
+            "soap:Header": {"m:Session": {"@m:id": "42"}},
+            "soap:Body": {"m:GetShot": {"m:Station": "A1", "@xml:lang": "en"}},
+        });
+        let xml = value_to_xml_string(&envelope, "soap:Envelope", &options).unwrap();
+        assert_eq!(
+            xml,
+            concat!(
+                "<soap:Envelope xmlns:m=\"urn:survey\" ",
+                "xmlns:soap=\"http://schemas.xmlsoap.org/soap/envelope/\">",
+                "<soap:Header><m:Session m:id=\"42\"/></soap:Header>",
+                "<soap:Body><m:GetShot xml:lang=\"en\"><m:Station>A1</m:Station></m:GetShot>",
+                "</soap:Body></soap:Envelope>"
+            )
+        );
+        assert_eq!(xml.matches("xmlns:soap=").count(), 1);
+
+        // Prefixes declared in the tree itself are in scope for its content
+        let data = json!({"x:a": {"@xmlns:x": "urn:x", "x:b": "1"}});
+        assert!(value_to_xml_string(&data, "m:root", &options).is_ok());
+
+        let undeclared = value_to_xml_document(
+            &json!({"soap:Body": {"@ns2:id": "1"}}),
+            "soap:Envelope",
+            "utf-8",
+            &options,
+        );
+        assert!(
+            matches!(undeclared, Err(XmlDictError::Write(message)) if message.contains("\"ns2\""))
+        );
+
+        let invalid = SerializeOptions {
+            namespaces: HashMap::from([("xmlns".to_string(), "urn:x".to_string())]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            invalid.validate(),
+            Err(XmlDictError::InvalidOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_doctype() {
+        let options = SerializeOptions {
+            doctype: Some(r#"survey SYSTEM "survey.dtd""#.to_string()),
+            processing_instructions: vec![(
+                "xml-stylesheet".to_string(),
+                "href=\"s.xsl\"".to_string(),
+            )],
+            pretty: true,
+            ..Default::default()
+        };
+        let xml =
+            value_to_xml_document(&json!({"@unit": "m"}), "survey", "utf-8", &options).unwrap();
+        let lines: Vec<&str> = xml.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+                "<!DOCTYPE survey SYSTEM \"survey.dtd\">",
+                "<?xml-stylesheet href=\"s.xsl\"?>",
+                "<survey unit=\"m\"/>",
+            ]
+        );
+
+        let xml = concat!(
+            "<?xml version=\"1.0\"?>",
+            "<!DOCTYPE survey [ <!ELEMENT survey (#PCDATA)> ]>",
+            "<survey>Main</survey>"
+        );
+        let parse_options = ParseOptions {
+            keep_declaration: true,
+            keep_doctype: true,
+            ..Default::default()
+        };
+        let document = parse_xml(xml, &parse_options).unwrap();
+        assert_eq!(
+            document["#doctype"],
+            json!("survey [ <!ELEMENT survey (#PCDATA)> ]")
+        );
+        assert_eq!(
+            document_to_xml(&document, &SerializeOptions::default()).unwrap(),
+            xml.replace("?>", " encoding=\"utf-8\"?>")
+        );
+        let explicit = SerializeOptions {
+            doctype: Some("survey PUBLIC \"-//OpenSpeleo//Survey\" \"s.dtd\"".to_string()),
+            ..Default::default()
+        };
+        assert!(document_to_xml(&document, &explicit)
+            .unwrap()
+            .contains("<!DOCTYPE survey PUBLIC \"-//OpenSpeleo//Survey\" \"s.dtd\"><survey>"));
+        assert!(!parse_xml(xml, &ParseOptions::default())
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .contains_key("#doctype"));
+
+        for doctype in ["", " survey", "survey>", "survey [ <!ELEMENT a ANY>"] {
+            let options = SerializeOptions {
+                doctype: Some(doctype.to_string()),
+                ..Default::default()
+            };
+            let err = value_to_xml_document(&json!({}), "survey", "utf-8", &options).unwrap_err();
+            assert!(matches!(err, XmlDictError::InvalidOption(_)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_strip_namespaces_keeps_xml_prefix() {
+        let xml = r#"<gml:Survey xmlns:gml="http://www.opengis.net/gml" xmlns="urn:default"
+            xml:lang="fr"><gml:name gml:id="s1" xml:space="preserve"> Grotte </gml:name>
+            </gml:Survey>"#;
+        let options = ParseOptions {
+            strip_namespaces: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"Survey": {
+                "@xml:lang": "fr",
+                "name": {"@id": "s1", "@xml:space": "preserve", "#text": " Grotte "}
+            }})
+        );
+    }
+
+    // Records the visited events, indented by nesting level
+    #[derive(Default)]
+    struct Recorder {
+        depth: usize,
+        events: Vec<String>,
+    }
+
+    impl Visitor for Recorder {
+        fn enter_element(&mut self, name: &str) {
+            self.events
+                .push(format!("{}<{}>", "  ".repeat(self.depth), name));
+            self.depth += 1;
+        }
+
+        fn attribute(&mut self, name: &str, value: &str) {
+            self.events
+                .push(format!("{}@{}={}", "  ".repeat(self.depth), name, value));
+        }
+
+        fn text(&mut self, text: &str) {
+            self.events
+                .push(format!("{}{:?}", "  ".repeat(self.depth), text));
+        }
+
+        fn comment(&mut self, comment: &str) {
+            self.events
+                .push(format!("{}<!--{}-->", "  ".repeat(self.depth), comment));
+        }
+
+        fn exit_element(&mut self, name: &str) {
+            self.depth -= 1;
+            self.events
+                .push(format!("{}</{}>", "  ".repeat(self.depth), name));
+        }
+    }
+
+    #[test]
+    fn test_walk_nested_document() {
+        let options = ParseOptions {
+            coerce_types: true,
+            keep_comments: true,
+            keep_declaration: true,
+            ..Default::default()
+        };
+        let document = parse_xml(
+            r#"<?xml version="1.0"?><survey unit="m"><!--main-->
+            <shot id="1"><from>A1</from><length>2.5</length></shot>
+            <shot id="2" note="">wet<from>A2</from></shot><station/></survey>"#,
+            &options,
+        )
+        .unwrap();
+        let mut recorder = Recorder::default();
+        walk(&document, &mut recorder);
+        assert_eq!(
+            recorder.events,
+            [
+                "<survey>",
+                "  @unit=m",
+                "  <!--main-->",
+                "  <shot>",
+                "    @id=1",
+                "    <from>",
+                "      \"A1\"",
+                "    </from>",
+                "    <length>",
+                "      \"2.5\"",
+                "    </length>",
+                "  </shot>",
+                "  <shot>",
+                "    @id=2",
+                "    @note=",
+                "    \"wet\"",
+                "    <from>",
+                "      \"A2\"",
+                "    </from>",
+                "  </shot>",
+                "  <station>",
+                "  </station>",
+                "</survey>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_walk_ordered_content() {
+        let options = ParseOptions {
+            ordered_content: true,
+            ..Default::default()
+        };
+        let document = parse_xml("<p>See <b>A1</b> then <b>A2</b>.</p>", &options).unwrap();
+        let mut recorder = Recorder::default();
+        walk(&document, &mut recorder);
+        assert_eq!(
+            recorder.events,
+            [
+                "<p>",
+                "  \"See\"",
+                "  <b>",
+                "    \"A1\"",
+                "  </b>",
+                "  \"then\"",
+                "  <b>",
+                "    \"A2\"",
+                "  </b>",
+                "  \".\"",
+                "</p>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_round_trip_preset() {
+        let xml = concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+            "<survey name=\"Cave\" unit=\"m\"><!-- surveyed 2024 -->",
+            "<shot from=\"A0\" to=\"A1\"> 3.5 <note>wet</note> passage</shot>",
+            "<shot from=\"A1\" to=\"A2\"/><station name=\"A2\"></station>",
+            "<flag/><memo></memo><memo>dry &amp; low</memo>",
+            "<code><![CDATA[if (a < b)]]></code></survey>"
+        );
+        let document = parse_xml(xml, &ParseOptions::round_trip()).unwrap();
+        assert_eq!(
+            document["survey"]["#content"][3]["station"],
+            json!({"@name": "A2", "#text": ""})
+        );
+        assert_eq!(document["survey"]["#content"][4]["flag"], json!(null));
+        assert_eq!(document["survey"]["#content"][5]["memo"], json!(""));
+
+        let written = document_to_xml(&document, &SerializeOptions::default()).unwrap();
+        let body = |xml: &str| xml[xml.find("?>").unwrap() + 2..].trim_start().to_string();
+        assert_eq!(body(&written), body(xml));
+    }
+
+    #[test]
+    fn test_empty_element_value() {
+        let xml = "<survey><memo></memo><flag/><shot id=\"1\"/><note>wet</note></survey>";
+        let parse = |empty_element_value, keep_null, keep_empty_text| {
+            let options = ParseOptions {
+                empty_element_value,
+                keep_null,
+                keep_empty_text,
+                ..Default::default()
+            };
+            parse_xml(xml, &options).unwrap()["survey"].clone()
+        };
+
+        let expected = |empty: serde_json::Value, flag: serde_json::Value| json!({"memo": empty, "flag": flag, "shot": {"@id": "1"}, "note": "wet"});
+        assert_eq!(
+            parse(EmptyElementValue::Object, true, false),
+            expected(json!({}), json!(null))
+        );
+        assert_eq!(
+            parse(EmptyElementValue::Object, false, true),
+            expected(json!(""), json!({}))
+        );
+        for (keep_null, keep_empty_text) in [(true, true), (false, false)] {
+            assert_eq!(
+                parse(EmptyElementValue::String, keep_null, keep_empty_text),
+                expected(json!(""), json!(""))
+            );
+            assert_eq!(
+                parse(EmptyElementValue::Null, keep_null, keep_empty_text),
+                expected(json!(null), json!(null))
+            );
+        }
+
+        let options = ParseOptions {
+            empty_element_value: EmptyElementValue::String,
+            skip_empty: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_xml(xml, &options).unwrap(),
+            json!({"survey": {"shot": {"@id": "1"}, "note": "wet"}})
+        );
+        assert!("dict".parse::<EmptyElementValue>().is_err());
+    }
+
+    #[test]
+    fn test_collect_errors() {
+        let xml = "<survey><shot id=\"1\" depth/><station>A0</stn><note>ok</note></survey>";
+        let (value, issues) = parse_xml_collecting_errors(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            value,
+            json!({"survey": {"shot": {"@id": "1"}, "station": "A0", "note": "ok"}})
+        );
+        assert_eq!(issues.len(), 2, "{:?}", issues);
+        assert_eq!(issues[0].position, 8);
+        assert!(
+            issues[0].message.starts_with("Invalid attribute"),
+            "{:?}",
+            issues
+        );
+        assert_eq!(issues[1].position, xml.find("</stn>").unwrap() as u64);
+        assert!(issues[1].message.contains("</stn>"), "{:?}", issues);
+
+        // Without collecting, the first error fails the parse
+        assert!(parse_xml(xml, &ParseOptions::default()).is_err());
+        // Malformed attributes are reported whatever the decoding mode
+        let strict = ParseOptions {
+            errors: DecodeErrors::Strict,
+            ..Default::default()
+        };
+        assert!(parse_xml(xml, &strict).is_err());
+        assert_eq!(parse_xml_collecting_errors(xml, &strict).unwrap().1, issues);
+    }
+
+    #[test]
+    fn test_collect_errors_outside_root() {
+        let xml = "<survey><shot>1</shot></survey>trailing<extra/><open>";
+        let (value, issues) = parse_xml_collecting_errors(xml, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            value,
+            json!({"survey": {"shot": "1"}, "extra": null, "open": {}})
+        );
+        let messages: Vec<&str> = issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Unexpected text \"trailing\" outside of the root element",
+                "Unexpected element <extra> after the root element",
+                "Unexpected element <open> after the root element",
+                "Unclosed element <open>",
+            ]
+        );
+
+        // Well-formed documents parse as usual
+        let (value, issues) =
+            parse_xml_collecting_errors("<a><b>1</b></a>", &ParseOptions::default()).unwrap();
+        assert_eq!(value, json!({"a": {"b": "1"}}));
+        assert!(issues.is_empty());
     }
 }
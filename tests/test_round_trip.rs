@@ -0,0 +1,149 @@
+//! Round-trip property tests: `value → xml → value` for randomly generated
+//! values.
+//!
+//! The values are generated with `proptest`, which shrinks a failing case to
+//! a minimal value before reporting it. Generated values are kept small
+//! (bounded depth and breadth) so that failing cases stay readable.
+//!
+//! The round trip is the identity modulo the normalization implemented by
+//! `normalize`, which documents the intentional lossiness of the mapping:
+//!
+//! * scalars come back as strings: numbers and booleans are written as text
+//!   and never coerced back;
+//! * text is trimmed, and whitespace-only text is dropped;
+//! * an element without content or attributes comes back as `null` when it
+//!   was self-closing, and as `{}` when it was written with an (empty) text
+//!   or empty children;
+//! * an object holding only `#text` collapses to that text;
+//! * arrays of a single item collapse to the item, and empty arrays vanish
+//!   with their key;
+//! * attribute values are kept verbatim, `null` attributes are omitted.
+//!
+//! Nested arrays (arrays directly inside arrays) are written as wrapper
+//! elements and read back as objects, so they are not generated.
+
+use openspeleo_core::{parse_xml, value_to_xml_document, ParseOptions, SerializeOptions};
+use serde_json::{Map, Value};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use proptest::{collection, sample};
+
+    const CASES: u32 = 500;
+    const NAMES: &[&str] = &["a", "b", "shot", "Station", "x-1", "ns.item"];
+    const CHARS: &[char] = &[
+        'a', 'Z', '0', ' ', '&', '<', '>', '"', '\'', ';', '#', 'é', '洞', '\t', '\n',
+    ];
+
+    fn name() -> impl Strategy<Value = &'static str> {
+        sample::select(NAMES)
+    }
+
+    fn string() -> impl Strategy<Value = String> {
+        collection::vec(sample::select(CHARS), 0..6).prop_map(String::from_iter)
+    }
+
+    fn scalar() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            1 => Just(Value::Null),
+            1 => any::<bool>().prop_map(Value::Bool),
+            1 => (-500i64..500).prop_map(Value::from),
+            1 => (0u32..1000).prop_map(|n| Value::from(f64::from(n) / 8.0)),
+            2 => string().prop_map(Value::String),
+        ]
+    }
+
+    // Objects of up to 3 keys, `item` giving the values of their children
+    fn object(item: impl Strategy<Value = Value> + Clone) -> impl Strategy<Value = Value> {
+        let entry = prop_oneof![
+            1 => (name(), scalar()).prop_map(|(name, value)| (format!("@{}", name), value)),
+            1 => string().prop_map(|text| ("#text".to_string(), Value::String(text))),
+            1 => (name(), collection::vec(item.clone(), 0..4))
+                .prop_map(|(name, items)| (name.to_string(), Value::Array(items))),
+            2 => (name(), item).prop_map(|(name, value)| (name.to_string(), value)),
+        ];
+        collection::vec(entry, 0..4).prop_map(|entries| Value::Object(Map::from_iter(entries)))
+    }
+
+    // Root element values nested up to 3 levels deep
+    fn root() -> impl Strategy<Value = Value> {
+        object(scalar().prop_recursive(3, 32, 4, object))
+    }
+
+    fn text_of(value: &Value) -> String {
+        match value {
+            Value::String(s) => s.clone(),
+            Value::Null => String::new(),
+            other => other.to_string(),
+        }
+    }
+
+    // What `parse_xml` is expected to return for an element written from
+    // `value`, see the module documentation
+    fn normalize(value: &Value) -> Value {
+        let obj = match value {
+            Value::Null => return Value::Null,
+            Value::Object(obj) => obj,
+            scalar => {
+                let text = text_of(scalar).trim().to_string();
+                return if text.is_empty() {
+                    Value::Object(Map::new())
+                } else {
+                    Value::String(text)
+                };
+            }
+        };
+
+        let mut result = Map::new();
+        for (key, value) in obj {
+            if key.starts_with('@') {
+                if !value.is_null() {
+                    result.insert(key.clone(), Value::String(text_of(value)));
+                }
+            } else if key == "#text" {
+                let text = text_of(value).trim().to_string();
+                if !text.is_empty() {
+                    result.insert(key.clone(), Value::String(text));
+                }
+            } else if let Value::Array(items) = value {
+                let mut items: Vec<Value> = items.iter().map(normalize).collect();
+                match items.len() {
+                    0 => {}
+                    1 => {
+                        result.insert(key.clone(), items.remove(0));
+                    }
+                    _ => {
+                        result.insert(key.clone(), Value::Array(items));
+                    }
+                }
+            } else {
+                result.insert(key.clone(), normalize(value));
+            }
+        }
+
+        let self_closing = obj.keys().all(|k| k.starts_with('@'));
+        if result.is_empty() && self_closing {
+            return Value::Null;
+        }
+        if result.len() == 1 && result.contains_key("#text") {
+            return result.remove("#text").unwrap();
+        }
+        Value::Object(result)
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+        #[test]
+        fn test_round_trip_is_identity_after_normalization(value in root()) {
+            let xml = value_to_xml_document(&value, "root", "utf-8", &SerializeOptions::default())
+                .map_err(|e| TestCaseError::fail(e.to_string()))?;
+            let parsed = parse_xml(&xml, &ParseOptions::default())
+                .map_err(|e| TestCaseError::fail(format!("{} for {}", e, xml)))?;
+
+            prop_assert_eq!(&parsed["root"], &normalize(&value), "written as {}", xml);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Tests of the pure Rust API, built without the `python` feature so that
+//! they do not link PyO3.
+
+use openspeleo_core::{
+    parse_xml, value_to_xml_document, value_to_xml_string, ParseOptions, SerializeOptions,
+    XmlDictError,
+};
+use serde_json::json;
+use std::process::Command;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_to_xml_string() {
+        let data = json!({"@id": "A1", "depth": "12.5", "note": null});
+        let xml = value_to_xml_string(&data, "shot", &SerializeOptions::default()).unwrap();
+        assert_eq!(xml, r#"<shot id="A1"><depth>12.5</depth><note/></shot>"#);
+
+        let pretty = SerializeOptions {
+            pretty: true,
+            ..SerializeOptions::default()
+        };
+        let xml = value_to_xml_string(&json!({"depth": "1"}), "shot", &pretty).unwrap();
+        assert_eq!(xml, "<shot>\n  <depth>1</depth>\n</shot>");
+    }
+
+    #[test]
+    fn test_value_to_xml_string_invalid_options() {
+        let options = SerializeOptions {
+            attr_quote: '`',
+            ..SerializeOptions::default()
+        };
+        let err = value_to_xml_string(&json!({}), "shot", &options).unwrap_err();
+        assert!(matches!(err, XmlDictError::InvalidOption(_)));
+    }
+
+    #[test]
+    fn test_rust_round_trip() {
+        let xml = r#"<survey name="Main"><shot><depth>3</depth></shot><shot><depth>4</depth></shot></survey>"#;
+        let options = ParseOptions {
+            coerce_types: true,
+            ..ParseOptions::default()
+        };
+        let value = parse_xml(xml, &options).unwrap();
+        assert_eq!(
+            value,
+            json!({"survey": {"@name": "Main", "shot": [{"depth": 3}, {"depth": 4}]}})
+        );
+
+        let written =
+            value_to_xml_string(&value["survey"], "survey", &SerializeOptions::default()).unwrap();
+        assert_eq!(written, xml);
+        let document = value_to_xml_document(
+            &value["survey"],
+            "survey",
+            "utf-8",
+            &SerializeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            parse_xml(&document, &options).unwrap()["survey"],
+            value["survey"]
+        );
+    }
+
+    #[test]
+    fn test_restricted_build() {
+        // A separate target directory, the outer `cargo test` holding the lock
+        // of the default one
+        let manifest_dir = env!("CARGO_MANIFEST_DIR");
+        let output = Command::new(env!("CARGO"))
+            .args(["check", "--lib", "--no-default-features", "--quiet"])
+            .current_dir(manifest_dir)
+            .env(
+                "CARGO_TARGET_DIR",
+                format!("{manifest_dir}/target/no-default-features"),
+            )
+            .output()
+            .expect("failed to run cargo");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        // The dependencies left out of the restricted build
+        let output = Command::new(env!("CARGO"))
+            .args(["tree", "--no-default-features", "--edges", "normal"])
+            .args(["--prefix", "none", "--format", "{p}"])
+            .current_dir(manifest_dir)
+            .output()
+            .expect("failed to run cargo");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let tree = String::from_utf8_lossy(&output.stdout);
+        for name in ["pyo3", "zip", "regex", "encoding_rs"] {
+            assert!(
+                !tree
+                    .lines()
+                    .any(|line| line.starts_with(&format!("{name} "))),
+                "{name} is built without default features"
+            );
+        }
+    }
+}